@@ -0,0 +1,177 @@
+use core::net::Ipv6Addr;
+
+// RFC 1071 internet checksum of a slice of any length
+#[inline]
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = Checksum::new();
+    sum.add(data);
+    sum.finish()
+}
+
+// ICMPv6 checksum, covers the RFC 8200 8.1 pseudo header as well
+#[inline]
+pub fn checksum_v6(source: Ipv6Addr, destination: Ipv6Addr, data: &[u8]) -> u16 {
+    let mut sum = Checksum::new();
+    sum.add_pseudo_header_v6(source, destination, data.len() as u32, 58);
+    sum.add(data);
+    sum.finish()
+}
+
+// RFC 1624 eqn. 3: HC' = ~(~HC + ~m + m'), when the 16 bit word m becomes m'
+#[inline]
+pub fn update(checksum: u16, old: u16, new: u16) -> u16 {
+    let sum = (!checksum) as u32 + (!old) as u32 + new as u32;
+    !fold(sum as u64)
+}
+
+// same as `update` for several words, `old` and `new` must start at an even offset and have the same even length
+pub fn update_slice(checksum: u16, old: &[u8], new: &[u8]) -> u16 {
+    debug_assert!(old.len() == new.len() && old.len().is_multiple_of(2));
+    old.chunks_exact(2)
+        .zip(new.chunks_exact(2))
+        .fold(checksum, |checksum, (old, new)| {
+            update(
+                checksum,
+                u16::from_be_bytes([old[0], old[1]]),
+                u16::from_be_bytes([new[0], new[1]]),
+            )
+        })
+}
+
+// one's complement sum over several slices, every slice except the last one must have an even length
+//
+// the sum doesn't depend on byte order (RFC 1071 2.B), so words are added in native order,
+// 4 bytes at a time into a 64 bit accumulator, and swapped once in finish()
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Checksum {
+    sum: u64,
+}
+
+impl Checksum {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, data: &[u8]) -> &mut Self {
+        let mut chunks = data.chunks_exact(8);
+        for chunk in &mut chunks {
+            let word = u64::from_ne_bytes(chunk.try_into().unwrap());
+            self.sum += (word >> 32) + (word & 0xFFFF_FFFF);
+        }
+        let mut words = chunks.remainder().chunks_exact(2);
+        for word in &mut words {
+            self.sum += u16::from_ne_bytes([word[0], word[1]]) as u64;
+        }
+        // 如果只剩一个字节，低8位补0
+        if let [last] = words.remainder() {
+            self.sum += u16::from_ne_bytes([*last, 0]) as u64;
+        }
+        // folding keeps the accumulator far from overflowing between calls
+        self.sum = fold(self.sum) as u64;
+        self
+    }
+
+    // upper-layer packet length and next header as in RFC 8200 8.1
+    pub fn add_pseudo_header_v6(
+        &mut self,
+        source: Ipv6Addr,
+        destination: Ipv6Addr,
+        length: u32,
+        next_header: u8,
+    ) -> &mut Self {
+        self.add(&source.octets())
+            .add(&destination.octets())
+            .add(&length.to_be_bytes())
+            .add(&[0, 0, 0, next_header])
+    }
+
+    #[inline]
+    pub fn finish(&self) -> u16 {
+        !u16::from_be_bytes(fold(self.sum).to_ne_bytes())
+    }
+}
+
+#[inline]
+fn fold(mut sum: u64) -> u16 {
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{Checksum, checksum, checksum_v6, update, update_slice};
+    use rand::{Rng, SeedableRng};
+
+    // the byte at a time loop this module replaced
+    fn reference(data: &[u8]) -> u16 {
+        let mut sum: u32 = 0;
+        let mut i = 0;
+        while i < data.len() {
+            let word = if i + 1 < data.len() {
+                ((data[i] as u16) << 8) | (data[i + 1] as u16)
+            } else {
+                (data[i] as u16) << 8
+            };
+            sum += word as u32;
+            if sum > 0xFFFF {
+                sum = (sum & 0xFFFF) + 1;
+            }
+            i += 2;
+        }
+        !(sum as u16)
+    }
+
+    #[test]
+    fn test_checksum_against_reference() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1071);
+        for len in 0..300 {
+            let mut data = vec![0_u8; len];
+            rng.fill(&mut data[..]);
+            assert_eq!(checksum(&data), reference(&data), "len {len}");
+        }
+        // carries everywhere
+        let data = vec![0xFF_u8; 65535];
+        assert_eq!(checksum(&data), reference(&data));
+        // RFC 1071 section 3 example
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&data), !0xddf2);
+    }
+
+    #[test]
+    fn test_checksum_in_parts() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut sum = Checksum::new();
+        sum.add(&data[..6]).add(&data[6..100]).add(&data[100..]);
+        assert_eq!(sum.finish(), checksum(&data));
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1624);
+        let mut data = vec![0_u8; 1000];
+        rng.fill(&mut data[..]);
+        let mut sum = checksum(&data);
+        for _ in 0..100 {
+            let offset = rng.random_range(0..500) * 2;
+            let old = [data[offset], data[offset + 1]];
+            let new: [u8; 2] = rng.random();
+            data[offset..offset + 2].copy_from_slice(&new);
+            sum = update(sum, u16::from_be_bytes(old), u16::from_be_bytes(new));
+            assert_eq!(sum, checksum(&data));
+        }
+        let old = data[10..20].to_vec();
+        data[10..20].copy_from_slice(&[0; 10]);
+        assert_eq!(update_slice(sum, &old, &data[10..20]), checksum(&data));
+    }
+
+    #[test]
+    fn test_checksum_v6() {
+        // echo request ::1 -> ::1, id 0x1234 seq 1, pseudo header sums to 0x0044
+        let message = [0x80, 0x00, 0x6d, 0x86, 0x12, 0x34, 0x00, 0x01];
+        let localhost = "::1".parse().unwrap();
+        assert_eq!(checksum_v6(localhost, localhost, &message), 0);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{Ipv4Header, Ipv6Header, checksum};
+use core::net::Ipv6Addr;
+
+// bytes 6..22 (sequence number and random part) are taken from `request_data`
+pub struct IcmpDataForPing {
+    data: [u8; IcmpDataForPing::DATA_SIZE],
+}
+
+impl IcmpDataForPing {
+    pub const DATA_SIZE: usize = 22;
+
+    #[inline]
+    pub fn new_ping_v4(request_data: u128) -> Self {
+        let mut data = [0_u8; Self::DATA_SIZE];
+        data[0] = 8;
+        data[6..].copy_from_slice(&request_data.to_be_bytes());
+
+        process_check_sum(&mut data);
+
+        IcmpDataForPing { data }
+    }
+
+    #[inline]
+    pub fn new_ping_v6(request_data: u128) -> Self {
+        let mut data = [0_u8; Self::DATA_SIZE];
+        data[0] = 128;
+        data[6..].copy_from_slice(&request_data.to_be_bytes());
+
+        process_check_sum(&mut data);
+
+        IcmpDataForPing { data }
+    }
+
+    #[inline]
+    pub fn get_type(&self) -> u8 {
+        self.data[0]
+    }
+
+    #[inline]
+    pub fn get_checksum(&self) -> u16 {
+        u16::from_be_bytes([self.data[2], self.data[3]])
+    }
+
+    #[inline]
+    pub fn get_identifier(&self) -> u16 {
+        u16::from_be_bytes([self.data[4], self.data[5]])
+    }
+
+    #[inline]
+    pub fn get_sequence(&self) -> u16 {
+        u16::from_be_bytes([self.data[6], self.data[7]])
+    }
+
+    // patches the checksum instead of summing the whole message again
+    #[inline]
+    pub fn set_sequence(&mut self, sequence: u16) {
+        let checksum = checksum::update(self.get_checksum(), self.get_sequence(), sequence);
+        self.data[2..4].copy_from_slice(&checksum.to_be_bytes());
+        self.data[6..8].copy_from_slice(&sequence.to_be_bytes());
+    }
+
+    #[inline]
+    pub fn get_inner(&self) -> &[u8; IcmpDataForPing::DATA_SIZE] {
+        &self.data
+    }
+
+    #[inline]
+    pub fn get_inner_mut(&mut self) -> &mut [u8; IcmpDataForPing::DATA_SIZE] {
+        &mut self.data
+    }
+}
+
+// fills the checksum field (bytes 2..4) of an ICMP message of any length
+fn process_check_sum(data: &mut [u8]) {
+    data[2..4].copy_from_slice(&[0, 0]);
+    let sum = checksum::checksum(data);
+    data[2..4].copy_from_slice(&sum.to_be_bytes());
+}
+
+#[derive(Debug)]
+pub struct IcmpFormat<'a> {
+    slice: &'a [u8],
+    icmp_type: u8,
+    code: u8,
+    checksum: u16,
+    other_data: &'a [u8],
+}
+
+impl<'a> IcmpFormat<'a> {
+    pub fn from_slice(slice: &[u8]) -> Option<IcmpFormat<'_>> {
+        if slice.len() < 4 {
+            None
+        } else {
+            Some(IcmpFormat {
+                slice,
+                icmp_type: slice[0],
+                code: slice[1],
+                checksum: u16::from_be_bytes(slice[2..4].try_into().unwrap()),
+                other_data: &slice[4..],
+            })
+        }
+    }
+
+    #[inline]
+    pub fn from_header_v4(header: &Ipv4Header<'a>) -> Option<IcmpFormat<'a>> {
+        IcmpFormat::from_slice(header.get_payload())
+    }
+
+    #[inline]
+    pub fn from_header_v6(header: &Ipv6Header<'a>) -> Option<IcmpFormat<'a>> {
+        IcmpFormat::from_slice(header.get_payload()?)
+    }
+
+    #[inline]
+    pub fn get_type(&self) -> u8 {
+        self.icmp_type
+    }
+
+    #[inline]
+    pub fn get_code(&self) -> u8 {
+        self.code
+    }
+
+    #[inline]
+    pub fn get_checksum(&self) -> u16 {
+        self.checksum
+    }
+
+    // a message passing verification sums up to zero, checksum field included
+    #[inline]
+    pub fn is_checksum_valid_v4(&self) -> bool {
+        checksum::checksum(self.slice) == 0
+    }
+
+    // source and destination of the IPv6 packet carrying this message
+    #[inline]
+    pub fn is_checksum_valid_v6(&self, source: Ipv6Addr, destination: Ipv6Addr) -> bool {
+        checksum::checksum_v6(source, destination, self.slice) == 0
+    }
+
+    // the 4 bytes after the checksum, their meaning depends on the type
+    #[inline]
+    pub fn get_rest_of_header(&self) -> Option<&'a [u8]> {
+        self.other_data.get(..4)
+    }
+
+    // only meaningful for echo request/reply
+    #[inline]
+    pub fn get_identifier(&self) -> Option<u16> {
+        Some(u16::from_be_bytes(
+            self.other_data.get(0..2)?.try_into().ok()?,
+        ))
+    }
+
+    // only meaningful for echo request/reply
+    #[inline]
+    pub fn get_sequence(&self) -> Option<u16> {
+        Some(u16::from_be_bytes(
+            self.other_data.get(2..4)?.try_into().ok()?,
+        ))
+    }
+
+    #[inline]
+    pub fn get_body(&self) -> &'a [u8] {
+        self.other_data.get(4..).unwrap_or_default()
+    }
+
+    // the original datagram quoted by an ICMP error (unreachable, source quench, redirect, time exceeded, parameter problem)
+    #[inline]
+    pub fn get_quoted_v4(&self) -> Option<Ipv4Header<'a>> {
+        match self.icmp_type {
+            3 | 4 | 5 | 11 | 12 => Ipv4Header::from_slice(self.get_body()),
+            _ => None,
+        }
+    }
+
+    // the original datagram quoted by an ICMPv6 error (unreachable, packet too big, time exceeded, parameter problem)
+    #[inline]
+    pub fn get_quoted_v6(&self) -> Option<Ipv6Header<'a>> {
+        match self.icmp_type {
+            1..=4 => Ipv6Header::from_slice(self.get_body()),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn check_is_correspond_v4(&self, data: &IcmpDataForPing) -> Option<()> {
+        self.check_is_answer_v4(&data.data)
+    }
+
+    #[inline]
+    pub fn check_is_correspond_v6(&self, data: &IcmpDataForPing) -> Option<()> {
+        self.check_is_answer_v6(&data.data)
+    }
+
+    // same as check_is_correspond_v4, for an echo request of any size
+    pub fn check_is_answer_v4(&self, request: &[u8]) -> Option<()> {
+        match (*request.first()?, self.icmp_type) {
+            (8, 0) => self
+                .other_data
+                .get(2..)?
+                .eq(request.get(6..)?)
+                .then_some(()),
+            (8, 11) => {
+                // Time to live exceeded
+                let sum = u16::from_be_bytes([*request.get(2)?, *request.get(3)?]);
+                self.get_quoted_v4() // 使用uncheck的原因是部分Time to live exceeded响应并未传递ICMP请求的Data部分非序列号和识别部分
+                    .and_then(|header| IcmpFormat::from_header_v4(&header))
+                    .and_then(|icmp| {
+                        // 直接比较checksum,因为有部分响应实现并未传递其余部分
+                        icmp.checksum.eq(&sum).then_some(())
+                    })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn check_is_answer_v6(&self, request: &[u8]) -> Option<()> {
+        match (*request.first()?, self.icmp_type) {
+            (128, 129) => self
+                .other_data
+                .get(2..)?
+                .eq(request.get(6..)?)
+                .then_some(()),
+            (128, 3) => self
+                .get_quoted_v6()
+                .and_then(|header| IcmpFormat::from_header_v6(&header))
+                .and_then(|format| {
+                    format
+                        .other_data
+                        .get(2..)?
+                        .eq(request.get(6..)?)
+                        .then_some(())
+                }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{IcmpDataForPing, IcmpFormat, checksum};
+
+    #[test]
+    fn test_icmp_set_sequence() {
+        let mut data = IcmpDataForPing::new_ping_v4(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10);
+        for sequence in [0, 1, 0xFFFF, 0x8000, 42] {
+            data.set_sequence(sequence);
+            assert_eq!(data.get_sequence(), sequence);
+            assert_eq!(checksum::checksum(data.get_inner()), 0);
+        }
+    }
+
+    #[test]
+    fn test_icmp_echo_reply() {
+        let slice: &[u8] = &[0, 0, 136, 240, 0, 12, 0, 42, 163, 38, 61, 106];
+        let format = IcmpFormat::from_slice(slice).unwrap();
+        assert_eq!(format.get_type(), 0);
+        assert_eq!(format.get_code(), 0);
+        assert_eq!(format.get_checksum(), 0x88f0);
+        assert_eq!(format.get_identifier(), Some(12));
+        assert_eq!(format.get_sequence(), Some(42));
+        assert_eq!(format.get_body(), &[163, 38, 61, 106]);
+        assert!(format.get_quoted_v4().is_none());
+    }
+
+    #[test]
+    fn test_icmp_time_exceeded_quote() {
+        let slice: &[u8] = &[
+            11, 0, 0, 0, 0, 0, 0, 0, // time exceeded, unused
+            0x45, 0, 0, 0x24, 0x12, 0x34, 0, 0, 1, 1, 0, 0, 192, 168, 2, 6, 1, 1, 1,
+            1, // quoted ipv4
+            8, 0, 0xab, 0xcd, 0, 7, 0, 42, // quoted echo request
+        ];
+        let format = IcmpFormat::from_slice(slice).unwrap();
+        assert_eq!(format.get_type(), 11);
+        let quoted = format.get_quoted_v4().unwrap();
+        assert_eq!(quoted.get_ttl(), 1);
+        assert_eq!(
+            quoted.get_destination_address(),
+            core::net::Ipv4Addr::new(1, 1, 1, 1)
+        );
+        let inner = IcmpFormat::from_header_v4(&quoted).unwrap();
+        assert_eq!(inner.get_type(), 8);
+        assert_eq!(inner.get_checksum(), 0xabcd);
+        assert_eq!(inner.get_identifier(), Some(7));
+        assert_eq!(inner.get_sequence(), Some(42));
+        assert_eq!(inner.get_body(), &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_icmp_answer_any_size() {
+        let request: &[u8] = &[8, 0, 0x12, 0x34, 0, 0, 0, 3, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        // DGRAM sockets rewrite the identifier
+        let reply: &[u8] = &[0, 0, 0x1a, 0x34, 0, 9, 0, 3, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let format = IcmpFormat::from_slice(reply).unwrap();
+        assert!(format.check_is_answer_v4(request).is_some());
+        assert!(format.check_is_answer_v4(&request[..12]).is_none());
+        assert!(format.check_is_answer_v6(request).is_none());
+        assert!(format.check_is_answer_v4(&[]).is_none());
+    }
+}
@@ -0,0 +1,671 @@
+use crate::checksum::checksum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    // version, header length, total length and header checksum must all be right
+    Strict,
+    // only requires the slice to hold the header, many devices send broken total lengths
+    Lenient,
+}
+
+#[derive(Debug)]
+pub struct Ipv4Header<'a> {
+    fix_slice: &'a [u8],
+    op_slice: &'a [u8], // 可选数据
+    payload_slice: &'a [u8],
+}
+
+impl<'a> Ipv4Header<'a> {
+    pub const FIXED_HEADER_SIZE: u16 = 20;
+
+    #[inline]
+    pub fn from_slice<'b: 'a>(slice: &'b [u8]) -> Option<Ipv4Header<'a>> {
+        Self::from_slice_with_mode(slice, ParseMode::Lenient)
+    }
+
+    pub fn from_slice_with_mode<'b: 'a>(
+        slice: &'b [u8],
+        mode: ParseMode,
+    ) -> Option<Ipv4Header<'a>> {
+        if slice.len() < Ipv4Header::FIXED_HEADER_SIZE as usize {
+            return None;
+        }
+        let header_length = (slice[0] << 4) >> 2;
+        if slice.len() < header_length as usize {
+            return None;
+        }
+        match mode {
+            ParseMode::Lenient => {
+                // we don't consider check the total size because many responses set this part wrongly
+                // a header length below 20 still keeps the fixed header out of the payload
+                let op_end = (header_length as usize).max(Self::FIXED_HEADER_SIZE as usize);
+                Some(Ipv4Header {
+                    fix_slice: &slice[0..Self::FIXED_HEADER_SIZE as usize],
+                    op_slice: &slice[Self::FIXED_HEADER_SIZE as usize..op_end],
+                    payload_slice: &slice[op_end..],
+                })
+            }
+            ParseMode::Strict => {
+                let total_length = u16::from_be_bytes([slice[2], slice[3]]) as usize;
+                if slice[0] >> 4 != 4
+                    || header_length < Self::FIXED_HEADER_SIZE as u8
+                    || total_length < header_length as usize
+                    || total_length > slice.len()
+                {
+                    return None;
+                }
+                let header = Ipv4Header {
+                    fix_slice: &slice[0..Self::FIXED_HEADER_SIZE as usize],
+                    op_slice: &slice[Self::FIXED_HEADER_SIZE as usize..header_length as usize],
+                    // anything behind total length is link layer padding
+                    payload_slice: &slice[header_length as usize..total_length],
+                };
+                header.is_checksum_valid().then_some(header)
+            }
+        }
+    }
+
+    #[inline]
+    pub fn get_version(&self) -> u8 {
+        self.fix_slice[0] >> 4
+    }
+
+    // in bytes, not in 32-bit words
+    #[inline]
+    pub fn get_header_length(&self) -> u8 {
+        (self.fix_slice[0] & 0x0f) << 2
+    }
+
+    // differentiated services code point, the upper 6 bits of the former TOS byte
+    #[inline]
+    pub fn get_dscp(&self) -> u8 {
+        self.fix_slice[1] >> 2
+    }
+
+    // explicit congestion notification, the lower 2 bits of the former TOS byte
+    #[inline]
+    pub fn get_ecn(&self) -> u8 {
+        self.fix_slice[1] & 0b11
+    }
+
+    // not checked against the slice, many responses set it wrongly
+    #[inline]
+    pub fn get_total_length(&self) -> u16 {
+        u16::from_be_bytes(self.fix_slice[2..4].try_into().unwrap())
+    }
+
+    #[inline]
+    pub fn get_identification(&self) -> u16 {
+        u16::from_be_bytes(self.fix_slice[4..6].try_into().unwrap())
+    }
+
+    // 3 bits: reserved, don't fragment, more fragments
+    #[inline]
+    pub fn get_flags(&self) -> u8 {
+        self.fix_slice[6] >> 5
+    }
+
+    #[inline]
+    pub fn get_dont_fragment(&self) -> bool {
+        self.get_flags() & 0b010 != 0
+    }
+
+    #[inline]
+    pub fn get_more_fragments(&self) -> bool {
+        self.get_flags() & 0b001 != 0
+    }
+
+    // in 8 bytes units
+    #[inline]
+    pub fn get_fragment_offset(&self) -> u16 {
+        u16::from_be_bytes([self.fix_slice[6] & 0x1f, self.fix_slice[7]])
+    }
+
+    #[inline]
+    pub fn get_ttl(&self) -> u8 {
+        self.fix_slice[8]
+    }
+
+    #[inline]
+    pub fn get_protocol(&self) -> u8 {
+        self.fix_slice[9]
+    }
+
+    #[inline]
+    pub fn get_header_checksum(&self) -> u16 {
+        u16::from_be_bytes(self.fix_slice[10..12].try_into().unwrap())
+    }
+
+    #[inline]
+    pub fn get_source_address(&self) -> core::net::Ipv4Addr {
+        core::net::Ipv4Addr::from(
+            <&[u8] as TryInto<[u8; 4]>>::try_into(&self.fix_slice[12..16]).unwrap(),
+        )
+    }
+
+    #[inline]
+    pub fn get_destination_address(&self) -> core::net::Ipv4Addr {
+        core::net::Ipv4Addr::from(
+            <&[u8] as TryInto<[u8; 4]>>::try_into(&self.fix_slice[16..20]).unwrap(),
+        )
+    }
+
+    #[inline]
+    pub fn get_options(&self) -> &'a [u8] {
+        self.op_slice
+    }
+
+    // checksum over the fixed header and options, with the checksum field itself taken as zero
+    pub fn compute_header_checksum(&self) -> u16 {
+        let mut header = [0_u8; 60];
+        let length = self.fix_slice.len() + self.op_slice.len();
+        header[..20].copy_from_slice(self.fix_slice);
+        header[20..length].copy_from_slice(self.op_slice);
+        header[10..12].copy_from_slice(&[0, 0]);
+        checksum(&header[..length])
+    }
+
+    #[inline]
+    pub fn is_checksum_valid(&self) -> bool {
+        self.compute_header_checksum() == self.get_header_checksum()
+    }
+
+    #[inline]
+    pub fn get_payload(&self) -> &'a [u8] {
+        self.payload_slice
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv6Header<'a> {
+    fix_slice: &'a [u8],
+    chain_slice: &'a [u8], // 扩展头 + 上层协议
+    upper: (u8, &'a [u8]), // the last entry of the chain
+}
+
+impl<'a> Ipv6Header<'a> {
+    pub const FIXED_HEADER_SIZE: u16 = 40;
+
+    pub fn from_slice(slice: &'a [u8]) -> Option<Ipv6Header<'a>> {
+        let (fix_slice, mut chain_slice) =
+            slice.split_at_checked(Self::FIXED_HEADER_SIZE as usize)?;
+        let payload_length = u16::from_be_bytes(fix_slice[4..6].try_into().unwrap()) as usize;
+        // anything behind the payload length is link layer padding,
+        // 0 means a jumbogram whose length is in the Hop-by-Hop header (RFC 2675)
+        if payload_length != 0 && chain_slice.len() > payload_length {
+            chain_slice = &chain_slice[..payload_length];
+        }
+        // the chain is walked once here so that a broken one is rejected,
+        // get_header_chain walks it again instead of keeping every entry
+        let mut chain = HeaderChain::new(fix_slice[6], chain_slice);
+        let upper = loop {
+            let (header_type, slice, last) = chain.step()?;
+            if last {
+                break (header_type, slice);
+            }
+        };
+        Some(Ipv6Header {
+            fix_slice,
+            chain_slice,
+            upper,
+        })
+    }
+
+    #[inline]
+    pub fn get_version(&self) -> u8 {
+        self.fix_slice[0] >> 4
+    }
+
+    #[inline]
+    pub fn get_traffic_class(&self) -> u8 {
+        (self.fix_slice[0] << 4) | (self.fix_slice[1] >> 4)
+    }
+
+    #[inline]
+    pub fn get_flow_label(&self) -> u32 {
+        u32::from_be_bytes([
+            0,
+            self.fix_slice[1] & 0x0f,
+            self.fix_slice[2],
+            self.fix_slice[3],
+        ])
+    }
+
+    #[inline]
+    pub fn get_payload_length(&self) -> u16 {
+        u16::from_be_bytes(self.fix_slice[4..6].try_into().unwrap())
+    }
+
+    // the Next Header field of the fixed header, see get_header_chain for the rest
+    #[inline]
+    pub fn get_next_header(&self) -> u8 {
+        self.fix_slice[6]
+    }
+
+    #[inline]
+    pub fn get_hop_limit(&self) -> u8 {
+        self.fix_slice[7]
+    }
+
+    #[inline]
+    pub fn get_source_address(&self) -> core::net::Ipv6Addr {
+        core::net::Ipv6Addr::from(
+            <&[u8] as TryInto<[u8; 16]>>::try_into(&self.fix_slice[8..24]).unwrap(),
+        )
+    }
+
+    #[inline]
+    pub fn get_destination_address(&self) -> core::net::Ipv6Addr {
+        core::net::Ipv6Addr::from(
+            <&[u8] as TryInto<[u8; 16]>>::try_into(&self.fix_slice[24..40]).unwrap(),
+        )
+    }
+
+    // every extension header followed by the upper layer, as (header type, header slice)
+    #[inline]
+    pub fn get_header_chain(&self) -> HeaderChain<'a> {
+        HeaderChain::new(self.fix_slice[6], self.chain_slice)
+    }
+
+    // the extension headers of the chain, decoded
+    #[inline]
+    pub fn get_extensions(&self) -> impl Iterator<Item = Ipv6Extension<'a>> + use<'a> {
+        self.get_header_chain()
+            .filter_map(|(header_type, slice)| Ipv6Extension::from_chain_entry(header_type, slice))
+    }
+
+    // upper layer protocol, e.g. 58 for ICMPv6
+    #[inline]
+    pub fn get_type(&self) -> Option<u8> {
+        Some(self.upper.0)
+    }
+
+    #[inline]
+    pub fn get_payload(&self) -> Option<&'a [u8]> {
+        Some(self.upper.1)
+    }
+}
+
+// walks the header chain without allocating, see Ipv6Header::get_header_chain
+#[derive(Debug, Clone)]
+pub struct HeaderChain<'a> {
+    next_header: Option<u8>,
+    rest: &'a [u8],
+}
+
+impl<'a> HeaderChain<'a> {
+    #[inline]
+    fn new(next_header: u8, rest: &'a [u8]) -> Self {
+        HeaderChain {
+            next_header: Some(next_header),
+            rest,
+        }
+    }
+
+    // the bool tells whether the entry ends the chain, None for a broken chain
+    fn step(&mut self) -> Option<(u8, &'a [u8], bool)> {
+        let header_type = self.next_header.take()?;
+        let length = match Ipv6HeaderType::new(header_type) {
+            // RFC 8200: Hdr Ext Len counts 8-octet units, not including the first 8 octets
+            Ipv6HeaderType::Options => (*self.rest.get(1)? as usize + 1) << 3,
+            Ipv6HeaderType::Fragment => 8,
+            // RFC 4302: Payload Len counts 4-octet units, minus 2
+            Ipv6HeaderType::Authentication => (*self.rest.get(1)? as usize + 2) << 2,
+            // the rest can't be walked: encrypted, empty, or the upper layer itself
+            // a shorter slice is accepted, ICMPv6 errors only quote as much of the packet as fits
+            Ipv6HeaderType::Encapsulating
+            | Ipv6HeaderType::NoNextHeader
+            | Ipv6HeaderType::Uppers => {
+                return Some((header_type, core::mem::take(&mut self.rest), true));
+            }
+            Ipv6HeaderType::Unassigned | Ipv6HeaderType::Reserved => return None,
+        };
+        let slice;
+        (slice, self.rest) = self.rest.split_at_checked(length)?;
+        self.next_header = Some(slice[0]);
+        Some((header_type, slice, false))
+    }
+}
+
+impl<'a> Iterator for HeaderChain<'a> {
+    type Item = (u8, &'a [u8]);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step()
+            .map(|(header_type, slice, _)| (header_type, slice))
+    }
+}
+
+// an extension header of the chain, decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv6Extension<'a> {
+    HopByHop {
+        options: &'a [u8],
+    },
+    Routing {
+        routing_type: u8,
+        segments_left: u8,
+        data: &'a [u8],
+    },
+    Fragment {
+        offset: u16, // in 8 bytes units
+        more_fragments: bool,
+        identification: u32,
+    },
+    DestinationOptions {
+        options: &'a [u8],
+    },
+    Authentication {
+        spi: u32,
+        sequence: u32,
+        icv: &'a [u8],
+    },
+    // only the clear part, everything behind is encrypted
+    Encapsulating {
+        spi: u32,
+        sequence: u32,
+    },
+    // Mobility, HIP, Shim6 and the experimental values
+    Other(u8, &'a [u8]),
+}
+
+impl<'a> Ipv6Extension<'a> {
+    // takes an entry of Ipv6Header::get_header_chain, None for upper layers
+    pub fn from_chain_entry(header_type: u8, slice: &'a [u8]) -> Option<Ipv6Extension<'a>> {
+        let u32_at = |at: usize| Some(u32::from_be_bytes(slice.get(at..at + 4)?.try_into().ok()?));
+        match header_type {
+            Ipv6HeaderType::HOPOPT => Some(Ipv6Extension::HopByHop {
+                options: slice.get(2..)?,
+            }),
+            Ipv6HeaderType::IPV6_ROUTE => Some(Ipv6Extension::Routing {
+                routing_type: *slice.get(2)?,
+                segments_left: *slice.get(3)?,
+                data: slice.get(4..)?,
+            }),
+            Ipv6HeaderType::IPV6_FRAG => Some(Ipv6Extension::Fragment {
+                offset: u16::from_be_bytes(slice.get(2..4)?.try_into().ok()?) >> 3,
+                more_fragments: slice.get(3)? & 1 == 1,
+                identification: u32_at(4)?,
+            }),
+            Ipv6HeaderType::IPV6_OPTS => Some(Ipv6Extension::DestinationOptions {
+                options: slice.get(2..)?,
+            }),
+            Ipv6HeaderType::AH => Some(Ipv6Extension::Authentication {
+                spi: u32_at(4)?,
+                sequence: u32_at(8)?,
+                icv: slice.get(12..)?,
+            }),
+            Ipv6HeaderType::ESP => Some(Ipv6Extension::Encapsulating {
+                spi: u32_at(0)?,
+                sequence: u32_at(4)?,
+            }),
+            header_type => match Ipv6HeaderType::new(header_type) {
+                Ipv6HeaderType::Options => Some(Ipv6Extension::Other(header_type, slice)),
+                _ => None,
+            },
+        }
+    }
+}
+
+enum Ipv6HeaderType {
+    Options,
+    Fragment,
+    Authentication,
+    Encapsulating,
+    NoNextHeader,
+    Uppers,
+    Unassigned,
+    Reserved,
+}
+
+impl Ipv6HeaderType {
+    // Options:
+    const HOPOPT: u8 = 0;
+    const IPV6_OPTS: u8 = 60;
+    const IPV6_ROUTE: u8 = 43;
+    const IPV6_FRAG: u8 = 44;
+    const AH: u8 = 51;
+    const ESP: u8 = 50;
+    const IPV6_NONXT: u8 = 59;
+    const MOBILITY: u8 = 135;
+    const HIP: u8 = 139;
+    const SHIM6: u8 = 140;
+
+    pub fn new(u: u8) -> Ipv6HeaderType {
+        match u {
+            Self::HOPOPT
+            | Self::IPV6_OPTS
+            | Self::IPV6_ROUTE
+            | Self::MOBILITY
+            | Self::HIP
+            | Self::SHIM6
+            | 253..=254 => Ipv6HeaderType::Options, // 253 and 254 are for experimentation (RFC 3692)
+            Self::IPV6_FRAG => Ipv6HeaderType::Fragment,
+            Self::AH => Ipv6HeaderType::Authentication,
+            Self::ESP => Ipv6HeaderType::Encapsulating,
+            Self::IPV6_NONXT => Ipv6HeaderType::NoNextHeader,
+            u => match u {
+                0..=145 => Ipv6HeaderType::Uppers,
+                146..=252 => Ipv6HeaderType::Unassigned,
+                253.. => Ipv6HeaderType::Reserved,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Ipv4Header, Ipv6Extension, Ipv6Header, ParseMode};
+    use core::str::FromStr;
+
+    #[test]
+    fn test_ipv4_header() {
+        let slice: &[u8] = &[
+            69, 0, 0, 42, 133, 30, 0, 0, 55, 1, 58, 5, 1, 1, 1, 1, 192, 168, 2, 6, 0, 0, 136, 240,
+            0, 0, 230, 74, 163, 38, 61, 106, 234, 34, 235, 11, 213, 222, 158, 115, 102, 178,
+        ];
+        let header = Ipv4Header::from_slice(slice).unwrap();
+        assert_eq!(header.get_version(), 4);
+        assert_eq!(header.get_header_length(), 20);
+        assert_eq!(header.get_total_length(), 42);
+        assert_eq!(header.get_dscp(), 0);
+        assert_eq!(header.get_identification(), 0x851e);
+        assert!(!header.get_dont_fragment());
+        assert_eq!(header.get_fragment_offset(), 0);
+        assert_eq!(header.get_ttl(), 55);
+        assert_eq!(header.get_header_checksum(), 0x3a05);
+        assert!(header.is_checksum_valid());
+        assert_eq!(header.get_options(), &[] as &[u8]);
+        assert_eq!(header.get_protocol(), 1);
+        assert_eq!(
+            header.get_source_address(),
+            core::net::Ipv4Addr::new(1, 1, 1, 1)
+        );
+        assert_eq!(
+            header.get_destination_address(),
+            core::net::Ipv4Addr::new(192, 168, 2, 6)
+        );
+        assert_eq!(
+            header.fix_slice,
+            [
+                69, 0, 0, 42, 133, 30, 0, 0, 55, 1, 58, 5, 1, 1, 1, 1, 192, 168, 2, 6
+            ]
+        );
+        // assert_eq!(header.op_slice, []);
+        assert_eq!(
+            header.payload_slice,
+            [
+                0, 0, 136, 240, 0, 0, 230, 74, 163, 38, 61, 106, 234, 34, 235, 11, 213, 222, 158,
+                115, 102, 178
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ipv4_header_strict() {
+        // total length 28 with 2 bytes of padding, and a 4 bytes option (NOP, NOP, NOP, EOL)
+        let mut slice = [
+            0x46, 0xb8, 0, 28, 0, 1, 0x40, 0, 64, 1, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 1, 1, 1, 0, 0,
+            0, 0, 0, 0xaa, 0xbb,
+        ];
+        let sum = Ipv4Header::from_slice(&slice)
+            .unwrap()
+            .compute_header_checksum();
+        slice[10..12].copy_from_slice(&sum.to_be_bytes());
+
+        let header = Ipv4Header::from_slice_with_mode(&slice, ParseMode::Strict).unwrap();
+        assert_eq!(header.get_header_length(), 24);
+        assert_eq!(header.get_dscp(), 46);
+        assert!(header.get_dont_fragment());
+        assert_eq!(header.get_options(), &[1, 1, 1, 0]);
+        assert_eq!(header.get_payload(), &[0, 0, 0, 0]);
+        // lenient keeps the padding
+        assert_eq!(
+            Ipv4Header::from_slice(&slice).unwrap().get_payload().len(),
+            6
+        );
+
+        let mut corrupted = slice;
+        corrupted[8] = 63;
+        assert!(Ipv4Header::from_slice_with_mode(&corrupted, ParseMode::Strict).is_none());
+        assert!(Ipv4Header::from_slice(&corrupted).is_some());
+
+        let mut too_long = slice;
+        too_long[2..4].copy_from_slice(&100_u16.to_be_bytes());
+        assert!(Ipv4Header::from_slice_with_mode(&too_long, ParseMode::Strict).is_none());
+        assert!(Ipv4Header::from_slice(&too_long).is_some());
+    }
+
+    #[test]
+    fn test_ipv4_header_short_ihl() {
+        // IHL 4 claims a 16 bytes header, shorter than the fixed one
+        let slice: &[u8] = &[
+            0x44, 0, 0, 28, 0, 1, 0, 0, 64, 1, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 8, 0, 0xf7, 0xff, 0,
+            0, 0, 0,
+        ];
+        let header = Ipv4Header::from_slice(slice).unwrap();
+        assert_eq!(header.get_header_length(), 16);
+        assert_eq!(header.get_options(), &[] as &[u8]);
+        assert_eq!(header.get_payload(), &[8, 0, 0xf7, 0xff, 0, 0, 0, 0]);
+        assert!(Ipv4Header::from_slice_with_mode(slice, ParseMode::Strict).is_none());
+    }
+
+    #[test]
+    fn test_ipv6_header() {
+        let slice = [
+            0x60, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x01, 0xfe, 0x80, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xff, 0x02, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x3a, 0x00,
+            0x01, 0x00, 0x05, 0x02, 0x00, 0x00, 0x82, 0x00, 0x80, 0x1d, 0x00, 0x0a, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let header = Ipv6Header::from_slice(&slice).unwrap();
+        assert_eq!(
+            header.fix_slice,
+            &[
+                96, 0, 0, 0, 0, 32, 0, 1, 254, 128, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 255,
+                2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1
+            ]
+        );
+        assert_eq!(header.get_version(), 6);
+        assert_eq!(header.get_payload_length(), 32);
+        assert_eq!(header.get_next_header(), 0);
+        assert_eq!(header.get_hop_limit(), 1);
+        assert_eq!(header.get_type(), Some(58));
+        let mut chain = header.get_header_chain();
+        assert_eq!(chain.next(), Some((0, &[58, 0, 1, 0, 5, 2, 0, 0][..])));
+        assert_eq!(
+            chain.next(),
+            Some((
+                58,
+                &[
+                    130, 0, 128, 29, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                ][..]
+            ))
+        );
+        assert_eq!(chain.next(), None);
+        assert_eq!(
+            header.get_source_address(),
+            core::net::Ipv6Addr::from_str("fe80::1").unwrap()
+        );
+        assert_eq!(
+            header.get_destination_address(),
+            core::net::Ipv6Addr::from_str("ff02::1").unwrap()
+        );
+    }
+
+    fn ipv6_packet(next_header: u8, payload_length: u16, rest: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&payload_length.to_be_bytes());
+        packet.extend_from_slice(&[next_header, 64]);
+        packet.extend_from_slice(&[0; 31]);
+        packet.push(1);
+        packet.extend_from_slice(rest);
+        packet
+    }
+
+    #[test]
+    fn test_ipv6_ah_esp() {
+        // AH with a 12 bytes ICV is 24 bytes, its Payload Len is 24 / 4 - 2 = 4
+        let mut rest = vec![50, 4, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 7];
+        rest.extend_from_slice(&[0xee; 12]);
+        rest.extend_from_slice(&[0, 0, 0x20, 0, 0, 0, 0, 9, 0xab, 0xcd]); // ESP
+        let packet = ipv6_packet(51, rest.len() as u16, &rest);
+        let header = Ipv6Header::from_slice(&packet).unwrap();
+        let chain: Vec<u8> = header.get_header_chain().map(|(u, _)| u).collect();
+        assert_eq!(chain, [51, 50]);
+        assert_eq!(header.get_type(), Some(50));
+        let extensions: Vec<Ipv6Extension> = header.get_extensions().collect();
+        assert_eq!(
+            extensions,
+            [
+                Ipv6Extension::Authentication {
+                    spi: 0x1000,
+                    sequence: 7,
+                    icv: &[0xee; 12]
+                },
+                Ipv6Extension::Encapsulating {
+                    spi: 0x2000,
+                    sequence: 9
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ipv6_walker_bounds() {
+        // Destination Options with Hdr Ext Len 1 is 16 bytes, not 8
+        let mut rest = vec![58, 1, 1, 12];
+        rest.extend_from_slice(&[0; 12]);
+        rest.extend_from_slice(&[129, 0, 0, 0]);
+        let packet = ipv6_packet(60, rest.len() as u16, &rest);
+        let header = Ipv6Header::from_slice(&packet).unwrap();
+        assert_eq!(header.get_header_chain().next().unwrap().1.len(), 16);
+        assert_eq!(header.get_payload(), Some(&[129, 0, 0, 0][..]));
+
+        // a quote cut inside the payload is fine, trailing padding is dropped
+        let truncated = ipv6_packet(58, u16::MAX, &[129, 0, 0, 0]);
+        assert_eq!(
+            Ipv6Header::from_slice(&truncated).unwrap().get_payload(),
+            Some(&[129, 0, 0, 0][..])
+        );
+        let padded = ipv6_packet(58, 2, &[129, 0, 0, 0]);
+        assert_eq!(
+            Ipv6Header::from_slice(&padded).unwrap().get_payload(),
+            Some(&[129, 0][..])
+        );
+
+        // a quote cut inside an extension header, or an extension header without its length byte
+        assert!(Ipv6Header::from_slice(&ipv6_packet(0, 0, &[58, 1, 0, 0])).is_none());
+        assert!(Ipv6Header::from_slice(&ipv6_packet(0, 0, &[58])).is_none());
+        assert!(Ipv6Header::from_slice(&ipv6_packet(44, 0, &[58, 0, 0])).is_none());
+        assert!(Ipv6Header::from_slice(&ipv6_packet(150, 0, &[])).is_none());
+        assert!(Ipv6Header::from_slice(&packet[..39]).is_none());
+
+        // No Next Header ends the chain
+        let none = ipv6_packet(59, 0, &[]);
+        assert_eq!(Ipv6Header::from_slice(&none).unwrap().get_type(), Some(59));
+    }
+}
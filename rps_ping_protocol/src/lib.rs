@@ -0,0 +1,15 @@
+#![cfg_attr(not(test), no_std)]
+// packet layer of rps_ping_lib, no allocation anywhere
+
+pub mod checksum;
+mod icmp;
+mod ip_header;
+
+pub use ip_header::HeaderChain;
+pub use ip_header::Ipv4Header;
+pub use ip_header::Ipv6Extension;
+pub use ip_header::Ipv6Header;
+pub use ip_header::ParseMode;
+
+pub use icmp::IcmpDataForPing;
+pub use icmp::IcmpFormat;
@@ -1,24 +1,48 @@
 mod error;
+#[cfg(all(not(target_os = "windows"), feature = "EXPORTER"))]
+pub mod exporter;
 #[cfg(not(target_os = "windows"))]
 mod linux;
 #[cfg(not(target_os = "windows"))]
 pub use linux::PingV4;
 #[cfg(not(target_os = "windows"))]
 pub use linux::PingV6;
+#[cfg(not(target_os = "windows"))]
+pub use linux::{ExtendedError, LinuxError, Payload};
 mod builder;
+#[cfg(not(target_os = "windows"))]
+pub mod capture;
+#[cfg(not(target_os = "windows"))]
+pub mod clock;
 #[cfg(target_os = "linux")]
-mod protocol;
+pub mod netns;
+pub mod protocol;
+#[cfg(not(target_os = "windows"))]
+pub mod rate;
 mod result;
+pub mod statistics;
+#[cfg(not(target_os = "windows"))]
+pub mod sweep;
+mod timeout;
+#[cfg(not(target_os = "windows"))]
+pub mod traceroute;
+#[cfg(not(target_os = "windows"))]
+pub mod transport;
+#[cfg(all(target_os = "linux", feature = "TUN"))]
+pub mod tun;
 #[cfg(target_os = "windows")]
 mod utils;
 #[cfg(target_os = "windows")]
 mod windows;
 
+pub use error::{PingError, SharedError};
 pub use result::*;
 
 #[cfg(target_os = "windows")]
 pub use windows::PingV4;
 #[cfg(target_os = "windows")]
 pub use windows::PingV6;
+#[cfg(target_os = "windows")]
+pub use windows::WindowsError;
 
 pub use builder::{PingV4Builder, PingV6Builder};
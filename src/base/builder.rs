@@ -1,9 +1,40 @@
+#[cfg(not(target_os = "windows"))]
+use crate::base::capture::Capture;
+#[cfg(not(target_os = "windows"))]
+use crate::base::clock::Clock;
+#[cfg(not(target_os = "windows"))]
+use crate::base::linux::Payload;
+#[cfg(target_os = "linux")]
+use crate::base::netns::Netns;
+#[cfg(not(target_os = "windows"))]
+use crate::base::rate::RateLimiter;
+#[cfg(not(target_os = "windows"))]
+use crate::base::transport::Transport;
 use crate::base::{PingV4, PingV6};
 
 pub struct PingV4Builder {
-    pub timeout: u32, //ms
+    pub timeout: std::time::Duration,          // per reply wait
+    pub deadline: Option<std::time::Duration>, // whole session, counted from build()
     pub ttl: Option<u8>,
     pub bind_addr: Option<std::net::Ipv4Addr>,
+    #[cfg(not(target_os = "windows"))]
+    pub transport: Option<std::sync::Arc<dyn Transport>>, // None for raw (or DGRAM_SOCKET) sockets
+    #[cfg(not(target_os = "windows"))]
+    pub clock: Option<std::sync::Arc<dyn Clock>>, // None for the system clock
+    #[cfg(not(target_os = "windows"))]
+    pub seed: Option<u64>, // probe contents, None for a random seed
+    #[cfg(not(target_os = "windows"))]
+    pub capture: Option<std::sync::Arc<Capture>>, // pcapng of every probe and reply
+    #[cfg(not(target_os = "windows"))]
+    pub payload: Option<Payload>, // echo request body, None for the 14 random bytes
+    #[cfg(not(target_os = "windows"))]
+    pub rate_limiter: Option<std::sync::Arc<RateLimiter>>, // shared pacing, e.g. of all pingers
+    #[cfg(target_os = "linux")]
+    pub netns: Option<Netns>, // where the sockets are created, ignored by other transports
+    #[cfg(target_os = "linux")]
+    pub interface: Option<String>, // SO_BINDTODEVICE, e.g. "eth0"
+    #[cfg(not(target_os = "windows"))]
+    pub broadcast: bool, // SO_BROADCAST, needed to ping a broadcast address
     #[cfg(target_os = "windows")]
     pub window_addition: Option<WindowAddition>,
 }
@@ -11,9 +42,28 @@ pub struct PingV4Builder {
 impl Default for PingV4Builder {
     fn default() -> Self {
         Self {
-            timeout: 1000,
+            timeout: std::time::Duration::from_millis(1000),
+            deadline: None,
             ttl: None,
             bind_addr: None,
+            #[cfg(not(target_os = "windows"))]
+            transport: None,
+            #[cfg(not(target_os = "windows"))]
+            clock: None,
+            #[cfg(not(target_os = "windows"))]
+            seed: None,
+            #[cfg(not(target_os = "windows"))]
+            capture: None,
+            #[cfg(not(target_os = "windows"))]
+            payload: None,
+            #[cfg(not(target_os = "windows"))]
+            rate_limiter: None,
+            #[cfg(target_os = "linux")]
+            netns: None,
+            #[cfg(target_os = "linux")]
+            interface: None,
+            #[cfg(not(target_os = "windows"))]
+            broadcast: false,
             #[cfg(target_os = "windows")]
             window_addition: None,
         }
@@ -21,11 +71,30 @@ impl Default for PingV4Builder {
 }
 
 impl PingV4Builder {
-    pub fn new(timeout: u32) -> Self {
+    pub fn new(timeout: std::time::Duration) -> Self {
         Self {
             timeout,
+            deadline: None,
             ttl: None,
             bind_addr: None,
+            #[cfg(not(target_os = "windows"))]
+            transport: None,
+            #[cfg(not(target_os = "windows"))]
+            clock: None,
+            #[cfg(not(target_os = "windows"))]
+            seed: None,
+            #[cfg(not(target_os = "windows"))]
+            capture: None,
+            #[cfg(not(target_os = "windows"))]
+            payload: None,
+            #[cfg(not(target_os = "windows"))]
+            rate_limiter: None,
+            #[cfg(target_os = "linux")]
+            netns: None,
+            #[cfg(target_os = "linux")]
+            interface: None,
+            #[cfg(not(target_os = "windows"))]
+            broadcast: false,
             #[cfg(target_os = "windows")]
             window_addition: None,
         }
@@ -38,10 +107,27 @@ impl PingV4Builder {
 }
 
 pub struct PingV6Builder {
-    pub timeout: u32, //ms
+    pub timeout: std::time::Duration,          // per reply wait
+    pub deadline: Option<std::time::Duration>, // whole session, counted from build()
     pub ttl: Option<u8>,
     pub bind_addr: Option<std::net::Ipv6Addr>,
     pub scope_id_option: Option<u32>,
+    #[cfg(not(target_os = "windows"))]
+    pub transport: Option<std::sync::Arc<dyn Transport>>, // None for raw (or DGRAM_SOCKET) sockets
+    #[cfg(not(target_os = "windows"))]
+    pub clock: Option<std::sync::Arc<dyn Clock>>, // None for the system clock
+    #[cfg(not(target_os = "windows"))]
+    pub seed: Option<u64>, // probe contents, None for a random seed
+    #[cfg(not(target_os = "windows"))]
+    pub capture: Option<std::sync::Arc<Capture>>, // pcapng of every probe and reply
+    #[cfg(not(target_os = "windows"))]
+    pub payload: Option<Payload>, // echo request body, None for the 14 random bytes
+    #[cfg(not(target_os = "windows"))]
+    pub rate_limiter: Option<std::sync::Arc<RateLimiter>>, // shared pacing, e.g. of all pingers
+    #[cfg(target_os = "linux")]
+    pub netns: Option<Netns>, // where the sockets are created, ignored by other transports
+    #[cfg(target_os = "linux")]
+    pub interface: Option<String>, // SO_BINDTODEVICE, e.g. "eth0"
     #[cfg(target_os = "windows")]
     pub window_addition: Option<WindowAddition>,
 }
@@ -49,10 +135,27 @@ pub struct PingV6Builder {
 impl Default for PingV6Builder {
     fn default() -> Self {
         Self {
-            timeout: 1000,
+            timeout: std::time::Duration::from_millis(1000),
+            deadline: None,
             ttl: None,
             bind_addr: None,
             scope_id_option: None,
+            #[cfg(not(target_os = "windows"))]
+            transport: None,
+            #[cfg(not(target_os = "windows"))]
+            clock: None,
+            #[cfg(not(target_os = "windows"))]
+            seed: None,
+            #[cfg(not(target_os = "windows"))]
+            capture: None,
+            #[cfg(not(target_os = "windows"))]
+            payload: None,
+            #[cfg(not(target_os = "windows"))]
+            rate_limiter: None,
+            #[cfg(target_os = "linux")]
+            netns: None,
+            #[cfg(target_os = "linux")]
+            interface: None,
             #[cfg(target_os = "windows")]
             window_addition: None,
         }
@@ -60,12 +163,29 @@ impl Default for PingV6Builder {
 }
 
 impl PingV6Builder {
-    pub fn new(timeout: u32) -> Self {
+    pub fn new(timeout: std::time::Duration) -> Self {
         Self {
             timeout,
+            deadline: None,
             ttl: None,
             bind_addr: None,
             scope_id_option: None,
+            #[cfg(not(target_os = "windows"))]
+            transport: None,
+            #[cfg(not(target_os = "windows"))]
+            clock: None,
+            #[cfg(not(target_os = "windows"))]
+            seed: None,
+            #[cfg(not(target_os = "windows"))]
+            capture: None,
+            #[cfg(not(target_os = "windows"))]
+            payload: None,
+            #[cfg(not(target_os = "windows"))]
+            rate_limiter: None,
+            #[cfg(target_os = "linux")]
+            netns: None,
+            #[cfg(target_os = "linux")]
+            interface: None,
             #[cfg(target_os = "windows")]
             window_addition: None,
         }
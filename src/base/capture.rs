@@ -0,0 +1,244 @@
+use crate::base::linux::ExtendedError;
+use crate::base::protocol::{IcmpMessage, checksum};
+use crate::base::transport::{Received, ReceivedKind, unspecified};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+// pcapng sink for every probe sent and every packet received, set it as `capture` of the builder,
+// each packet carries a comment saying why it was accepted or rejected
+//
+// raw IPv4 sockets give the IP header of what they receive, it is written as is,
+// DGRAM and IPv6 sockets don't, nor does the error queue, so plain ones are made up,
+// their TTL and identification mean nothing and the comment of the packet says so,
+// the timestamps are taken in userspace, right after sendto and recvmsg return
+pub struct Capture {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    writer: Box<dyn Write + Send>,
+    // to turn the Instants of the channel into wall clock time
+    base: (SystemTime, Instant),
+    // the first write error, returned by flush()
+    error: Option<io::Error>,
+}
+
+// pcapng block types and LINKTYPE_RAW, the packets start with the IP header
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const ENHANCED_PACKET: u32 = 6;
+const LINKTYPE_RAW: u16 = 101;
+
+impl Capture {
+    pub fn new<W: Write + Send + 'static>(mut writer: W) -> io::Result<Self> {
+        let mut shb = Vec::with_capacity(16);
+        shb.extend_from_slice(&0x1A2B_3C4D_u32.to_le_bytes()); // byte order magic
+        shb.extend_from_slice(&1_u16.to_le_bytes());
+        shb.extend_from_slice(&0_u16.to_le_bytes());
+        shb.extend_from_slice(&(-1_i64).to_le_bytes()); // section length unknown
+        writer.write_all(&block(SECTION_HEADER, &shb))?;
+
+        let mut idb = Vec::with_capacity(20);
+        idb.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        idb.extend_from_slice(&0_u16.to_le_bytes());
+        idb.extend_from_slice(&0_u32.to_le_bytes()); // no snap length
+        push_option(&mut idb, 9, &[9]); // if_tsresol, nanoseconds
+        idb.extend_from_slice(&[0; 4]);
+        writer.write_all(&block(INTERFACE_DESCRIPTION, &idb))?;
+
+        Ok(Capture {
+            inner: Mutex::new(Inner {
+                writer: Box::new(writer),
+                base: (SystemTime::now(), Instant::now()),
+                error: None,
+            }),
+        })
+    }
+
+    #[inline]
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(io::BufWriter::new(std::fs::File::create(path)?))
+    }
+
+    // also returns the first error met while writing packets, pings never fail because of it
+    pub fn flush(&self) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(e) = inner.error.take() {
+            return Err(e);
+        }
+        inner.writer.flush()
+    }
+
+    pub(crate) fn sent(&self, timestamp: Instant, target: IpAddr, data: &[u8]) {
+        let packet = ip_packet(unspecified(target), target, data);
+        self.write(timestamp, &packet, "probe sent, synthetic IP header");
+    }
+
+    // `header` is the IP header raw IPv4 sockets give in front of `data`, empty when there is none
+    pub(crate) fn received(
+        &self,
+        target: IpAddr,
+        received: &Received,
+        header: &[u8],
+        data: &[u8],
+        verdict: &str,
+    ) {
+        let local = received.destination.unwrap_or(unspecified(target));
+        let (packet, origin) = match &received.kind {
+            ReceivedKind::Icmp if !header.is_empty() => ([header, data].concat(), ""),
+            ReceivedKind::Icmp => {
+                let source = received.source.unwrap_or(unspecified(target));
+                (ip_packet(source, local, data), ", synthetic IP header")
+            }
+            // rebuilt from sock_extended_err, quoting the probe it was about
+            ReceivedKind::Error(extended) => {
+                let quoted = ip_packet(local, target, data);
+                let packet = match error_message(extended, target, local, &quoted) {
+                    Some((source, message)) => ip_packet(source, local, &message),
+                    None => quoted,
+                };
+                (packet, ", rebuilt from the error queue")
+            }
+        };
+        let truncated = match received.truncated {
+            true => ", truncated",
+            false => "",
+        };
+        self.write(
+            received.timestamp,
+            &packet,
+            &format!("{verdict}{origin}{truncated}"),
+        );
+    }
+
+    // what a raw IPv4 socket gave, when its IP header couldn't be read
+    pub(crate) fn unparseable(&self, timestamp: Instant, packet: &[u8]) {
+        self.write(
+            timestamp,
+            packet,
+            "rejected: no valid IP header, ResolveRecvFailed",
+        );
+    }
+
+    fn write(&self, timestamp: Instant, packet: &[u8], comment: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let (system, instant) = inner.base;
+        let time = match timestamp.checked_duration_since(instant) {
+            Some(after) => system + after,
+            None => system - instant.saturating_duration_since(timestamp),
+        };
+        let nanos = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos() as u64;
+
+        let mut epb = Vec::with_capacity(32 + packet.len() + comment.len());
+        epb.extend_from_slice(&0_u32.to_le_bytes()); // interface
+        epb.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(nanos as u32).to_le_bytes());
+        // captured and original length
+        epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        epb.extend_from_slice(packet);
+        pad(&mut epb);
+        push_option(&mut epb, 1, comment.as_bytes()); // opt_comment
+        epb.extend_from_slice(&[0; 4]);
+
+        if inner.error.is_none()
+            && let Err(e) = inner.writer.write_all(&block(ENHANCED_PACKET, &epb))
+        {
+            inner.error = Some(e);
+        }
+    }
+}
+
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let length = (12 + body.len()) as u32;
+    let mut block = Vec::with_capacity(length as usize);
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&length.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&length.to_le_bytes());
+    block
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+#[inline]
+fn pad(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+// an ICMP message in a made up IP header, the families of both addresses match `source`
+fn ip_packet(source: IpAddr, destination: IpAddr, icmp: &[u8]) -> Vec<u8> {
+    match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let mut packet = Vec::with_capacity(20 + icmp.len());
+            packet.extend_from_slice(&[0x45, 0]);
+            packet.extend_from_slice(
+                &((20 + icmp.len()).min(u16::MAX as usize) as u16).to_be_bytes(),
+            );
+            packet.extend_from_slice(&[0, 0, 0, 0, 64, 1, 0, 0]);
+            packet.extend_from_slice(&source.octets());
+            packet.extend_from_slice(&destination.octets());
+            let sum = checksum::checksum(&packet);
+            packet[10..12].copy_from_slice(&sum.to_be_bytes());
+            packet.extend_from_slice(icmp);
+            packet
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            let mut packet = Vec::with_capacity(40 + icmp.len());
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&(icmp.len().min(u16::MAX as usize) as u16).to_be_bytes());
+            packet.extend_from_slice(&[58, 64]);
+            packet.extend_from_slice(&source.octets());
+            packet.extend_from_slice(&destination.octets());
+            packet.extend_from_slice(icmp);
+            packet
+        }
+        (source, _) => ip_packet(source, unspecified(source), icmp),
+    }
+}
+
+// the ICMP error the kernel queued, None for local errors, e.g. EMSGSIZE
+fn error_message(
+    extended: &ExtendedError,
+    target: IpAddr,
+    local: IpAddr,
+    quoted: &[u8],
+) -> Option<(IpAddr, Vec<u8>)> {
+    let source = extended.offender.unwrap_or(unspecified(target));
+    match (extended.origin, source, local) {
+        (libc::SO_EE_ORIGIN_ICMP, ..) => {
+            // the next hop MTU of fragmentation needed
+            let [.., high, low] = (extended.info as u16).to_be_bytes();
+            let message = IcmpMessage::new_v4(
+                extended.icmp_type,
+                extended.icmp_code,
+                [0, 0, high, low],
+                quoted,
+            );
+            Some((source, message.get_inner().to_vec()))
+        }
+        (libc::SO_EE_ORIGIN_ICMP6, IpAddr::V6(source), IpAddr::V6(local)) => {
+            // the MTU of packet too big
+            let rest = extended.info.to_be_bytes();
+            let mut message =
+                IcmpMessage::new_v6(extended.icmp_type, extended.icmp_code, rest, quoted)
+                    .get_inner()
+                    .to_vec();
+            let sum = checksum::checksum_v6(source, local, &message);
+            message[2..4].copy_from_slice(&sum.to_be_bytes());
+            Some((source.into(), message))
+        }
+        _ => None,
+    }
+}
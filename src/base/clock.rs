@@ -0,0 +1,82 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// where timeouts, deadlines and durations are read from, set it as `clock` of the builder
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    #[inline]
+    fn sleep_until(&self, until: Instant) {
+        self.sleep(until.saturating_duration_since(self.now()));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[inline]
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+// time only moves when someone sleeps, so simulated seconds pass instantly
+#[derive(Debug)]
+pub struct VirtualClock {
+    base: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl VirtualClock {
+    #[inline]
+    pub fn new() -> Self {
+        VirtualClock {
+            base: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    // virtual time since the clock was created
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    #[inline]
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    #[inline]
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    // threads waiting at the same time share the wait, time moves to the latest end, not their sum
+    #[inline]
+    fn sleep_until(&self, until: Instant) {
+        let mut elapsed = self.elapsed.lock().unwrap();
+        *elapsed = (*elapsed).max(until.saturating_duration_since(self.base));
+    }
+}
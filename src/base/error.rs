@@ -24,6 +24,9 @@ impl std::fmt::Debug for PingError {
                 SharedError::NoElevatedPrivilege => {
                     write!(f, "SharedError::NoElevatedPrivilege")
                 }
+                SharedError::BadChecksum => {
+                    write!(f, "SharedError::BadChecksum")
+                }
             },
             #[cfg(target_os = "windows")]
             PingError::WindowsError(e) => match e {
@@ -77,6 +80,13 @@ impl std::fmt::Debug for PingError {
                         LinuxError::errno_to_str(*str)
                     )
                 }
+                LinuxError::SetNsFailed(str) => {
+                    write!(
+                        f,
+                        "PingError::LinuxError(SetNsFailed): Errno({str}) {:?}",
+                        LinuxError::errno_to_str(*str)
+                    )
+                }
                 LinuxError::SendtoFailed(str) => {
                     write!(
                         f,
@@ -113,6 +123,12 @@ impl std::fmt::Debug for PingError {
                 LinuxError::NullPtr => {
                     write!(f, "PingError::LinuxError(NullPtr)")
                 }
+                LinuxError::ReplayMismatch => {
+                    write!(f, "PingError::LinuxError(ReplayMismatch)")
+                }
+                LinuxError::IcmpError(e) => {
+                    write!(f, "PingError::LinuxError(IcmpError): {:?}", e)
+                }
             },
         }
     }
@@ -131,6 +147,9 @@ impl std::fmt::Display for PingError {
                 SharedError::NoElevatedPrivilege => {
                     write!(f, "ping no elevated privilege")
                 }
+                SharedError::BadChecksum => {
+                    write!(f, "ping reply with bad checksum")
+                }
             },
             #[cfg(target_os = "windows")]
             PingError::WindowsError(e) => match e {
@@ -181,6 +200,13 @@ impl std::fmt::Display for PingError {
                         LinuxError::errno_to_str(*str)
                     )
                 }
+                LinuxError::SetNsFailed(str) => {
+                    write!(
+                        f,
+                        "failed to enter network namespace: {:?}",
+                        LinuxError::errno_to_str(*str)
+                    )
+                }
                 LinuxError::SendFailed(str) => {
                     write!(
                         f,
@@ -219,6 +245,17 @@ impl std::fmt::Display for PingError {
                 LinuxError::NullPtr => {
                     write!(f, "query target null pointer")
                 }
+                LinuxError::ReplayMismatch => {
+                    write!(f, "sent something else than the recording")
+                }
+                LinuxError::IcmpError(e) => match e.offender {
+                    Some(offender) => write!(
+                        f,
+                        "icmp error type {} code {} from {}",
+                        e.icmp_type, e.icmp_code, offender
+                    ),
+                    None => write!(f, "icmp error type {} code {}", e.icmp_type, e.icmp_code),
+                },
             },
         }
     }
@@ -228,6 +265,8 @@ pub enum SharedError {
     Timeout,
     Unreachable,
     NoElevatedPrivilege,
+    // the reply matched the request, but its ICMP checksum is wrong
+    BadChecksum,
 }
 
 impl From<SharedError> for PingError {
@@ -0,0 +1,609 @@
+use crate::base::clock::{Clock, SystemClock};
+use crate::base::sweep::{HostState, Sweep, SweepBuilder, SweepHost};
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+// upper bounds of the RTT histogram in seconds
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+// the kinds of rps_ping_errors_total, the probes of a target that didn't answer in a round
+// count as the kind of its last one
+const KINDS: &[&str] = &[
+    "timeout",
+    "no_route",
+    "unreachable",
+    "time_exceeded",
+    "icmp_error",
+    "error",
+];
+
+// the longest request head read
+const MAX_REQUEST: usize = 8192;
+
+// for a whole request and its response, however slowly the client sends it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// connections handled at the same time, more are closed right away
+const MAX_CONNECTIONS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExporterTarget {
+    pub name: String, // the target label, e.g. the host name
+    pub addr: IpAddr,
+}
+
+impl From<IpAddr> for ExporterTarget {
+    #[inline]
+    fn from(addr: IpAddr) -> Self {
+        ExporterTarget {
+            name: addr.to_string(),
+            addr,
+        }
+    }
+}
+
+pub struct ExporterBuilder {
+    pub sweep: SweepBuilder, // how a round probes the targets
+    pub targets: Vec<ExporterTarget>,
+    pub interval: Duration, // from the start of one round to the next
+    pub buckets: Vec<f64>,  // RTT histogram bounds in seconds, ascending
+}
+
+impl Default for ExporterBuilder {
+    fn default() -> Self {
+        ExporterBuilder {
+            sweep: SweepBuilder::default(),
+            targets: Vec::new(),
+            interval: Duration::from_secs(15),
+            buckets: DEFAULT_BUCKETS.to_vec(),
+        }
+    }
+}
+
+impl ExporterBuilder {
+    #[inline]
+    pub fn new(timeout: Duration) -> Self {
+        ExporterBuilder {
+            sweep: SweepBuilder::new(timeout),
+            ..Default::default()
+        }
+    }
+
+    // rounds are scheduled on the clock of the IPv4 builder, like the sweep paces on it
+    pub fn build(self) -> Exporter {
+        let clock = self.sweep.v4.clock.clone().unwrap_or(Arc::new(SystemClock));
+        let metrics = self
+            .targets
+            .iter()
+            .map(|_| Metrics {
+                buckets: vec![0; self.buckets.len()],
+                ..Default::default()
+            })
+            .collect();
+        Exporter {
+            sweep: self.sweep.build(),
+            clock,
+            addrs: self.targets.iter().map(|target| target.addr).collect(),
+            targets: self.targets,
+            interval: self.interval,
+            buckets: self.buckets,
+            metrics: Mutex::new(metrics),
+            stopped: AtomicBool::new(false),
+        }
+    }
+}
+
+// probes its targets every interval and serves what it saw as Prometheus metrics
+pub struct Exporter {
+    sweep: Sweep,
+    clock: Arc<dyn Clock>,
+    targets: Vec<ExporterTarget>,
+    addrs: Vec<IpAddr>,
+    interval: Duration,
+    buckets: Vec<f64>,
+    metrics: Mutex<Vec<Metrics>>, // in the order of targets
+    stopped: AtomicBool,
+}
+
+// the counters of one target
+#[derive(Debug, Default)]
+struct Metrics {
+    buckets: Vec<u64>, // replies up to each bound but above the one before
+    count: u64,
+    sum: f64,
+    sent: u64,
+    lost: u64,
+    errors: [u64; KINDS.len()],
+    last_success: Option<SystemTime>,
+    up: bool,
+}
+
+impl Metrics {
+    fn add(&mut self, bounds: &[f64], host: &SweepHost) {
+        self.sent += u64::from(host.sent);
+        match &host.state {
+            HostState::Alive { duration, .. } => {
+                let seconds = duration.as_secs_f64();
+                if let Some(i) = bounds.iter().position(|bound| seconds <= *bound) {
+                    self.buckets[i] += 1;
+                }
+                self.count += 1;
+                self.sum += seconds;
+                self.lost += u64::from(host.sent) - 1;
+                self.last_success = Some(SystemTime::now());
+                self.up = true;
+            }
+            state => {
+                let kind = match state {
+                    HostState::Timeout => "timeout",
+                    HostState::Unreachable => "no_route",
+                    HostState::IcmpError(e) if e.is_unreachable() => "unreachable",
+                    HostState::IcmpError(e) if e.is_time_exceeded() => "time_exceeded",
+                    HostState::IcmpError(_) => "icmp_error",
+                    _ => "error",
+                };
+                if let Some(i) = KINDS.iter().position(|known| *known == kind) {
+                    self.errors[i] += u64::from(host.sent);
+                }
+                self.lost += u64::from(host.sent);
+                self.up = false;
+            }
+        }
+    }
+}
+
+impl Exporter {
+    // one probe of every target, the metrics change when all are done
+    pub fn round(&self) {
+        let hosts = self.sweep.run(&self.addrs, |_| {});
+        let mut metrics = self.metrics.lock().unwrap();
+        for (metrics, host) in metrics.iter_mut().zip(&hosts) {
+            metrics.add(&self.buckets, host);
+        }
+    }
+
+    // rounds every interval until stop()
+    pub fn probe(&self) {
+        let mut next = self.clock.now();
+        while !self.stopped.load(Ordering::Relaxed) {
+            self.round();
+            // a round longer than the interval delays the next one instead of piling up
+            next = (next + self.interval).max(self.clock.now());
+            // in slices, so stop() doesn't wait for the whole interval
+            while !self.stopped.load(Ordering::Relaxed) && self.clock.now() < next {
+                let slice = self.clock.now() + Duration::from_millis(100);
+                self.clock.sleep_until(next.min(slice));
+            }
+        }
+    }
+
+    // answers HTTP requests on `listener` until stop(), each connection on its own thread,
+    // so a slow client doesn't hold up the scrapes of the others
+    pub fn serve(&self, listener: &TcpListener) -> io::Result<()> {
+        listener.set_nonblocking(true)?;
+        let connections = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            while !self.stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if connections.load(Ordering::Relaxed) >= MAX_CONNECTIONS {
+                            continue;
+                        }
+                        stream.set_nonblocking(false)?;
+                        connections.fetch_add(1, Ordering::Relaxed);
+                        let connections = &connections;
+                        scope.spawn(move || {
+                            let until = Instant::now() + REQUEST_TIMEOUT;
+                            // a client going away is its problem, not the exporter's
+                            let _ = self.handle(Deadline { stream, until });
+                            connections.fetch_sub(1, Ordering::Relaxed);
+                        });
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // probe() and serve() together until stop()
+    pub fn run(&self, listener: &TcpListener) -> io::Result<()> {
+        std::thread::scope(|scope| {
+            scope.spawn(|| self.probe());
+            let result = self.serve(listener);
+            self.stop();
+            result
+        })
+    }
+
+    // probe() and serve() return soon after
+    #[inline]
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    // one HTTP/1.x request, GET /metrics is the text format, OpenMetrics when the client asks for it
+    pub fn handle<S: Read + Write>(&self, mut stream: S) -> io::Result<()> {
+        let mut request = Vec::new();
+        let mut buff = [0_u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            if request.len() > MAX_REQUEST {
+                return respond(&mut stream, "431 Request Header Fields Too Large", &[], "");
+            }
+            match stream.read(&mut buff)? {
+                0 => break,
+                len => request.extend_from_slice(&buff[..len]),
+            }
+        }
+        let request = String::from_utf8_lossy(&request);
+        let mut lines = request.lines();
+        let mut first = lines.next().unwrap_or_default().split(' ');
+        let (method, path) = (first.next().unwrap_or_default(), first.next());
+        let path = path.map(|path| path.split('?').next().unwrap_or_default());
+        let openmetrics = lines.any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.eq_ignore_ascii_case("accept")
+                    && value.contains("application/openmetrics-text")
+            })
+        });
+        match (method, path) {
+            ("GET" | "HEAD", Some("/metrics")) => {
+                let content_type = match openmetrics {
+                    true => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+                    false => "text/plain; version=0.0.4; charset=utf-8",
+                };
+                let body = self.render(openmetrics);
+                let body = if method == "HEAD" { "" } else { &body };
+                respond(
+                    &mut stream,
+                    "200 OK",
+                    &[("Content-Type", content_type)],
+                    body,
+                )
+            }
+            ("GET" | "HEAD", Some(_)) => respond(&mut stream, "404 Not Found", &[], ""),
+            (_, Some(_)) => respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                &[("Allow", "GET, HEAD")],
+                "",
+            ),
+            _ => respond(&mut stream, "400 Bad Request", &[], ""),
+        }
+    }
+
+    // the Prometheus text format, or OpenMetrics with `openmetrics`
+    pub fn render(&self, openmetrics: bool) -> String {
+        let metrics = self.metrics.lock().unwrap();
+        let labels: Vec<String> = self.targets.iter().map(labels).collect();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            openmetrics,
+            "rps_ping_rtt_seconds",
+            "histogram",
+            "Round trip time of the echo replies.",
+        );
+        for (metrics, labels) in metrics.iter().zip(&labels) {
+            let mut cumulative = 0;
+            for (bound, count) in self.buckets.iter().zip(&metrics.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "rps_ping_rtt_seconds_bucket{{{labels},le=\"{}\"}} {cumulative}",
+                    number(*bound)
+                );
+            }
+            let _ = writeln!(
+                out,
+                "rps_ping_rtt_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                metrics.count
+            );
+            let _ = writeln!(
+                out,
+                "rps_ping_rtt_seconds_sum{{{labels}}} {}",
+                number(metrics.sum)
+            );
+            let _ = writeln!(
+                out,
+                "rps_ping_rtt_seconds_count{{{labels}}} {}",
+                metrics.count
+            );
+        }
+
+        header(
+            &mut out,
+            openmetrics,
+            "rps_ping_probes_total",
+            "counter",
+            "Echo requests sent.",
+        );
+        for (metrics, labels) in metrics.iter().zip(&labels) {
+            let _ = writeln!(out, "rps_ping_probes_total{{{labels}}} {}", metrics.sent);
+        }
+
+        header(
+            &mut out,
+            openmetrics,
+            "rps_ping_lost_total",
+            "counter",
+            "Echo requests without a reply.",
+        );
+        for (metrics, labels) in metrics.iter().zip(&labels) {
+            let _ = writeln!(out, "rps_ping_lost_total{{{labels}}} {}", metrics.lost);
+        }
+
+        header(
+            &mut out,
+            openmetrics,
+            "rps_ping_errors_total",
+            "counter",
+            "Probes of targets that didn't answer in their round, by how the last one ended.",
+        );
+        for (metrics, labels) in metrics.iter().zip(&labels) {
+            for (kind, count) in KINDS.iter().zip(metrics.errors) {
+                let _ = writeln!(
+                    out,
+                    "rps_ping_errors_total{{{labels},kind=\"{kind}\"}} {count}"
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            openmetrics,
+            "rps_ping_last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last reply, 0 before the first one.",
+        );
+        for (metrics, labels) in metrics.iter().zip(&labels) {
+            let seconds = metrics
+                .last_success
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map_or(0.0, |since| since.as_secs_f64());
+            let _ = writeln!(
+                out,
+                "rps_ping_last_success_timestamp_seconds{{{labels}}} {}",
+                number(seconds)
+            );
+        }
+
+        header(
+            &mut out,
+            openmetrics,
+            "rps_ping_up",
+            "gauge",
+            "Whether the last probe got a reply.",
+        );
+        for (metrics, labels) in metrics.iter().zip(&labels) {
+            let _ = writeln!(out, "rps_ping_up{{{labels}}} {}", u8::from(metrics.up));
+        }
+
+        if openmetrics {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+}
+
+// OpenMetrics names a counter family without its _total
+fn header(out: &mut String, openmetrics: bool, name: &str, kind: &str, help: &str) {
+    let name = match openmetrics {
+        true => name.strip_suffix("_total").unwrap_or(name),
+        false => name,
+    };
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn labels(target: &ExporterTarget) -> String {
+    let family = match target.addr {
+        IpAddr::V4(_) => "ipv4",
+        IpAddr::V6(_) => "ipv6",
+    };
+    format!(
+        "target=\"{}\",address=\"{}\",family=\"{family}\"",
+        escape(&target.name),
+        target.addr
+    )
+}
+
+// label values escape backslashes, quotes and line feeds
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// a stream whose reads and writes all share one deadline, not a timeout each
+struct Deadline {
+    stream: TcpStream,
+    until: Instant,
+}
+
+impl Deadline {
+    fn left(&self) -> io::Result<Duration> {
+        match self.until.saturating_duration_since(Instant::now()) {
+            Duration::ZERO => Err(io::ErrorKind::TimedOut.into()),
+            left => Ok(left),
+        }
+    }
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buff: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.left()?))?;
+        self.stream.read(buff)
+    }
+}
+
+impl Write for Deadline {
+    fn write(&mut self, buff: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.left()?))?;
+        self.stream.write(buff)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// whole numbers keep a ".0", OpenMetrics wants le="1.0" rather than le="1"
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{value:.1}")
+    } else {
+        format!("{value}")
+    }
+}
+
+fn respond<S: Write>(
+    stream: &mut S,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\n");
+    for (name, value) in headers {
+        let _ = write!(head, "{name}: {value}\r\n");
+    }
+    let _ = write!(
+        head,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::clock::VirtualClock;
+    use crate::base::transport::{MockReply, MockTransport};
+
+    // 192.0.2.1 answers after 3ms, 192.0.2.2 gets host unreachable, the rest stay silent
+    fn exporter(attempts: u8) -> Exporter {
+        let clock = Arc::new(VirtualClock::new());
+        let transport = MockTransport::new(|sent, target| {
+            let delay = Duration::from_millis(3);
+            match target.to_string().as_str() {
+                "192.0.2.1" => vec![MockReply::echo_reply(sent, target, delay)],
+                "192.0.2.2" => vec![MockReply::unreachable(
+                    sent,
+                    "192.0.2.254".parse().unwrap(),
+                    1,
+                    delay,
+                )],
+                _ => Vec::new(),
+            }
+        })
+        .with_clock(clock.clone());
+        let mut builder = ExporterBuilder::new(Duration::from_millis(100));
+        builder.sweep.concurrency = 1;
+        builder.sweep.attempts = attempts;
+        builder.sweep.v4.transport = Some(Arc::new(transport));
+        builder.sweep.v4.clock = Some(clock);
+        builder.targets = vec![
+            ExporterTarget {
+                name: String::from("gateway \"a\""),
+                addr: "192.0.2.1".parse().unwrap(),
+            },
+            "192.0.2.2".parse::<IpAddr>().unwrap().into(),
+            "192.0.2.3".parse::<IpAddr>().unwrap().into(),
+        ];
+        builder.buckets = vec![0.001, 0.005];
+        builder.build()
+    }
+
+    #[test]
+    fn test_render() {
+        let exporter = exporter(1);
+        exporter.round();
+        exporter.round();
+        let text = exporter.render(false);
+        let first = r#"target="gateway \"a\"",address="192.0.2.1",family="ipv4""#;
+        for line in [
+            String::from("# TYPE rps_ping_rtt_seconds histogram"),
+            format!("rps_ping_rtt_seconds_bucket{{{first},le=\"0.001\"}} 0"),
+            format!("rps_ping_rtt_seconds_bucket{{{first},le=\"0.005\"}} 2"),
+            format!("rps_ping_rtt_seconds_bucket{{{first},le=\"+Inf\"}} 2"),
+            format!("rps_ping_rtt_seconds_sum{{{first}}} 0.006"),
+            format!("rps_ping_probes_total{{{first}}} 2"),
+            format!("rps_ping_lost_total{{{first}}} 0"),
+            format!("rps_ping_up{{{first}}} 1"),
+            String::from(
+                r#"rps_ping_errors_total{target="192.0.2.2",address="192.0.2.2",family="ipv4",kind="unreachable"} 2"#,
+            ),
+            String::from(
+                r#"rps_ping_errors_total{target="192.0.2.3",address="192.0.2.3",family="ipv4",kind="timeout"} 2"#,
+            ),
+            String::from(
+                r#"rps_ping_last_success_timestamp_seconds{target="192.0.2.3",address="192.0.2.3",family="ipv4"} 0.0"#,
+            ),
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line}\n{text}");
+        }
+        assert!(!text.contains("# EOF"));
+
+        let text = exporter.render(true);
+        assert!(text.contains("# TYPE rps_ping_probes counter\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_errors_count_probes() {
+        let exporter = exporter(3);
+        exporter.round();
+        let text = exporter.render(false);
+        // every probe of a target without reply counts, as in rps_ping_lost_total
+        for line in [
+            r#"rps_ping_lost_total{target="192.0.2.3",address="192.0.2.3",family="ipv4"} 3"#,
+            r#"rps_ping_errors_total{target="192.0.2.3",address="192.0.2.3",family="ipv4",kind="timeout"} 3"#,
+            r#"rps_ping_errors_total{target="192.0.2.2",address="192.0.2.2",family="ipv4",kind="unreachable"} 3"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line}\n{text}");
+        }
+    }
+
+    #[test]
+    fn test_handle() {
+        let exporter = exporter(1);
+        let get = |request: &str| {
+            let mut stream = io::Cursor::new(request.as_bytes().to_vec());
+            exporter.handle(&mut stream).unwrap();
+            let response = stream.into_inner().split_off(request.len());
+            String::from_utf8(response).unwrap()
+        };
+        let response = get("GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4"));
+        assert!(response.ends_with(
+            "rps_ping_up{target=\"192.0.2.3\",address=\"192.0.2.3\",family=\"ipv4\"} 0\n"
+        ));
+        let response = get("GET /metrics HTTP/1.1\r\nAccept: application/openmetrics-text\r\n\r\n");
+        assert!(response.contains("Content-Type: application/openmetrics-text; version=1.0.0"));
+        assert!(get("GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 "));
+        assert!(get("POST /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 "));
+    }
+
+    #[test]
+    fn test_number() {
+        assert_eq!(number(1.0), "1.0");
+        assert_eq!(number(0.0025), "0.0025");
+        assert_eq!(number(0.0), "0.0");
+    }
+}
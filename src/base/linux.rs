@@ -1,13 +1,34 @@
 use crate::base::builder::{PingV4Builder, PingV6Builder};
+use crate::base::capture::Capture;
+use crate::base::clock::{Clock, SystemClock};
 use crate::base::error::{PingError, SharedError};
-use crate::base::protocol::{IcmpDataForPing, IcmpFormat, Ipv4Header};
-use crate::{PingV4Result, PingV6Result};
+use crate::base::protocol::{IcmpDataForPing, IcmpFormat, IcmpMessage};
+use crate::base::timeout;
+use crate::base::transport::{
+    Channel, ChannelConfig, Family, Received, ReceivedKind, SocketTransport, Transport,
+    split_ipv4_header,
+};
+use crate::{
+    EchoV4Replies, EchoV4Reply, EchoV6Replies, EchoV6Reply, IcmpV4Reply, IcmpV6Reply, PingV4Result,
+    PingV6Result,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
 pub struct PingV4 {
     builder: PingV4Builder,
+    session_end: Option<std::time::Instant>,
+    pub(crate) clock: Arc<dyn Clock>,
+    rng: Mutex<ChaCha8Rng>,
 }
 
 pub struct PingV6 {
     builder: PingV6Builder,
+    session_end: Option<std::time::Instant>,
+    pub(crate) clock: Arc<dyn Clock>,
+    rng: Mutex<ChaCha8Rng>,
 }
 
 pub enum LinuxError {
@@ -16,6 +37,7 @@ pub enum LinuxError {
 
     BindFailed(libc::c_int),
     ConnectFailed(libc::c_int),
+    SetNsFailed(libc::c_int),
     SendFailed(libc::c_int),
     SendtoFailed(libc::c_int),
     SendMessageFailed(libc::c_int),
@@ -24,11 +46,72 @@ pub enum LinuxError {
     ResolveRecvFailed,
     MissRespondAddr,
     NullPtr,
+    ReplayMismatch,
+    IcmpError(ExtendedError),
+}
+
+// ICMP error read from the socket error queue (IP_RECVERR / IPV6_RECVERR)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedError {
+    pub errno: u32,
+    pub origin: u8,
+    pub icmp_type: u8,
+    pub icmp_code: u8,
+    pub info: u32,
+    pub offender: Option<std::net::IpAddr>,
+}
+
+// the echo request body of ping(8) -s and -p: `size` bytes, the first 8 of them random
+// to tell our replies apart, the rest filled with `pattern` (0, 1, 2, ... when it is empty)
+#[derive(Debug, Clone, Default)]
+pub struct Payload {
+    pub size: usize,
+    pub pattern: Vec<u8>,
+}
+
+impl Payload {
+    #[inline]
+    pub fn new(size: usize) -> Self {
+        Payload {
+            size,
+            pattern: Vec::new(),
+        }
+    }
+}
+
+impl ExtendedError {
+    #[inline]
+    pub fn is_time_exceeded(&self) -> bool {
+        match self.origin {
+            libc::SO_EE_ORIGIN_ICMP => self.icmp_type == 11,
+            libc::SO_EE_ORIGIN_ICMP6 => self.icmp_type == 3,
+            _ => false,
+        }
+    }
+
+    #[inline]
+    pub fn is_unreachable(&self) -> bool {
+        match self.origin {
+            libc::SO_EE_ORIGIN_ICMP => self.icmp_type == 3,
+            libc::SO_EE_ORIGIN_ICMP6 => self.icmp_type == 1,
+            _ => false,
+        }
+    }
+
+    // time exceeded is treated as an answer from a hop, like the raw socket path does
+    #[inline]
+    fn into_offender(self) -> Result<std::net::IpAddr, PingError> {
+        if self.is_time_exceeded() {
+            self.offender.ok_or(LinuxError::MissRespondAddr.into())
+        } else {
+            Err(LinuxError::IcmpError(self).into())
+        }
+    }
 }
 
 impl LinuxError {
     #[inline]
-    fn convert_recv_failed(input: libc::c_int) -> PingError {
+    pub(crate) fn convert_recv_failed(input: libc::c_int) -> PingError {
         match input {
             101 => PingError::SharedError(SharedError::Unreachable),
             11 => PingError::SharedError(SharedError::Timeout),
@@ -37,7 +120,7 @@ impl LinuxError {
     }
 
     #[inline]
-    fn convert_setup_failed(input: libc::c_int) -> PingError {
+    pub(crate) fn convert_setup_failed(input: libc::c_int) -> PingError {
         match input {
             1 => PingError::SharedError(SharedError::NoElevatedPrivilege),
             _ => PingError::LinuxError(LinuxError::BindFailed(input)),
@@ -68,456 +151,718 @@ impl LinuxError {
     }
 }
 
-mod common {
-    use super::*;
-
+impl PingV4 {
     #[inline]
-    pub(super) fn set_timeout(sock: libc::c_int, millis: u32) -> Result<(), PingError> {
-        let timeval = libc::timeval {
-            tv_sec: (millis / 1000) as libc::time_t,
-            tv_usec: ((millis % 1000) * 1000) as libc::suseconds_t,
-        };
-        let err = unsafe {
-            libc::setsockopt(
-                sock,
-                libc::SOL_SOCKET,
-                libc::SO_RCVTIMEO_NEW,
-                &timeval as *const _ as *const libc::c_void,
-                size_of::<libc::timeval>() as libc::socklen_t,
-            )
-        };
-        if err == -1 {
-            return Err(LinuxError::SetSockOptError(LinuxError::get_errno()).into());
+    pub fn new(builder: PingV4Builder) -> Self {
+        let clock = builder.clock.clone().unwrap_or(Arc::new(SystemClock));
+        let session_end = timeout::session_end(builder.deadline, clock.now());
+        Self {
+            rng: Mutex::new(new_rng(builder.seed)),
+            builder,
+            session_end,
+            clock,
         }
-        Ok(())
     }
 
-    #[inline]
-    pub(super) fn send(sock: libc::c_int, sent: &IcmpDataForPing) -> Result<(), PingError> {
-        let err = unsafe {
-            libc::send(
-                sock,
-                sent.get_inner().as_ptr() as *const _,
-                IcmpDataForPing::DATA_SIZE,
-                0,
-            )
+    // `ttl` overrides the one of the builder
+    fn open(&self, ttl: Option<u8>) -> Result<Box<dyn Channel>, PingError> {
+        let config = ChannelConfig {
+            family: Family::V4,
+            ttl: ttl.or(self.builder.ttl),
+            bind_addr: self.builder.bind_addr.map(IpAddr::V4),
+            scope_id: 0,
+            broadcast: self.builder.broadcast,
+            #[cfg(target_os = "linux")]
+            netns: self.builder.netns.clone(),
+            #[cfg(target_os = "linux")]
+            interface: self.builder.interface.clone(),
         };
-        if err == -1 {
-            return Err(LinuxError::SendFailed(LinuxError::get_errno()).into());
+        match &self.builder.transport {
+            Some(transport) => transport.open(&config),
+            None => SocketTransport.open(&config),
         }
-        Ok(())
     }
 
+    // waits for the send slot of the shared rate limiter, if any
     #[inline]
-    pub(super) fn get_addr_v6(
-        target: std::net::Ipv6Addr,
-        sin6_scope_id: u32,
-    ) -> libc::sockaddr_in6 {
-        libc::sockaddr_in6 {
-            sin6_family: libc::AF_INET6 as u16,
-            sin6_port: 0,
-            sin6_flowinfo: 0,
-            sin6_addr: unsafe { std::mem::transmute(target) },
-            sin6_scope_id,
+    fn pace(&self, target: IpAddr) {
+        if let Some(limiter) = &self.builder.rate_limiter {
+            limiter.acquire(target);
         }
     }
-}
 
-impl PingV4 {
     #[inline]
-    pub fn new(builder: PingV4Builder) -> Self {
-        Self { builder }
+    pub fn ping(&self, target: std::net::Ipv4Addr) -> Result<std::time::Duration, PingError> {
+        self.ping_in_detail(target).map(|result| result.duration)
     }
 
-    fn precondition(&self) -> Result<libc::c_int, PingError> {
-        #[cfg(not(feature = "DGRAM_SOCKET"))]
-        let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP) };
-        #[cfg(feature = "DGRAM_SOCKET")]
-        let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, libc::IPPROTO_ICMP) };
-        if sock == -1 {
-            return Err(LinuxError::convert_setup_failed(LinuxError::get_errno()).into());
+    #[inline]
+    pub fn ping_in_detail(&self, target: std::net::Ipv4Addr) -> Result<PingV4Result, PingError> {
+        match self.send_echo(target, None, None)?.into_result()? {
+            (IpAddr::V4(ip), duration) => Ok(PingV4Result { ip, duration }),
+            (IpAddr::V6(_), _) => Err(LinuxError::MissRespondAddr.into()),
         }
+    }
 
-        common::set_timeout(sock, self.builder.timeout)?;
-
-        match self.builder.bind_addr {
-            None => {}
-            Some(addr) => {
-                let sock_addr = libc::sockaddr_in {
-                    sin_family: libc::AF_INET as u16,
-                    sin_port: 0,
-                    sin_addr: libc::in_addr {
-                        s_addr: addr.to_bits(),
-                    },
-                    sin_zero: Default::default(),
-                };
-
-                let err = unsafe {
-                    libc::bind(
-                        sock,
-                        &sock_addr as *const _ as *const libc::sockaddr,
-                        size_of::<libc::sockaddr_in>() as libc::socklen_t,
-                    )
-                };
-                if err == -1 {
-                    return Err(LinuxError::BindFailed(LinuxError::get_errno()).into());
-                }
-            }
+    // one echo request with `sequence` and the payload of the builder, as ping(8) sends them,
+    // ICMP errors quoting it (time exceeded too) are LinuxError::IcmpError
+    pub fn ping_seq(
+        &self,
+        target: std::net::Ipv4Addr,
+        sequence: u16,
+    ) -> Result<EchoV4Reply, PingError> {
+        match self.send_echo(target, Some(sequence), None)? {
+            Answer::Reply {
+                ip: IpAddr::V4(ip),
+                duration,
+                ttl,
+                size,
+            } => Ok(EchoV4Reply {
+                ip,
+                duration,
+                sequence,
+                ttl,
+                size,
+            }),
+            Answer::Reply { .. } => Err(LinuxError::MissRespondAddr.into()),
+            Answer::Error(e, _) => Err(LinuxError::IcmpError(e).into()),
         }
+    }
 
-        match self.builder.ttl {
-            None => Ok(sock),
-            Some(ttl) => {
-                let err = unsafe {
-                    libc::setsockopt(
-                        sock,
-                        libc::SOL_IP,
-                        libc::IP_TTL,
-                        &ttl as *const _ as *const libc::c_void,
-                        size_of::<u8>() as libc::socklen_t,
-                    )
-                };
-                if err == -1 {
-                    Err(LinuxError::SetSockOptError(LinuxError::get_errno()).into())
-                } else {
-                    Ok(sock)
-                }
-            }
+    // one echo request to a broadcast address (needs `broadcast` of the builder) or a multicast
+    // group such as 224.0.0.1, like ping -b, the interface of the builder picks the link,
+    // returns the first reply of every responder until the timeout, in the order they came
+    pub fn ping_all(
+        &self,
+        target: std::net::Ipv4Addr,
+        sequence: u16,
+    ) -> Result<EchoV4Replies, PingError> {
+        self.pace(IpAddr::V4(target));
+        let until = timeout::reply_until(self.builder.timeout, self.session_end, self.clock.now())?;
+        let mut channel = self.open(None)?;
+        let sent = echo_request(
+            &self.rng,
+            Family::V4,
+            self.builder.payload.as_ref(),
+            Some(sequence),
+        );
+        let (answers, corrupted) = collect(
+            channel.as_mut(),
+            self.clock.as_ref(),
+            self.builder.capture.as_deref(),
+            IpAddr::V4(target),
+            &sent,
+            until,
+        )?;
+        let replies = answers
+            .into_iter()
+            .filter_map(|answer| match answer {
+                Answer::Reply {
+                    ip: IpAddr::V4(ip),
+                    duration,
+                    ttl,
+                    size,
+                } => Some(EchoV4Reply {
+                    ip,
+                    duration,
+                    sequence,
+                    ttl,
+                    size,
+                }),
+                _ => None,
+            })
+            .collect();
+        let corrupted = corrupted
+            .into_iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V4(ip) => Some(ip),
+                _ => None,
+            })
+            .collect();
+        Ok(EchoV4Replies { replies, corrupted })
+    }
+
+    // an echo request with the payload of the builder, the sequence is random when None
+    pub(crate) fn send_echo(
+        &self,
+        target: std::net::Ipv4Addr,
+        sequence: Option<u16>,
+        ttl: Option<u8>,
+    ) -> Result<Answer, PingError> {
+        self.pace(IpAddr::V4(target));
+        let until = timeout::reply_until(self.builder.timeout, self.session_end, self.clock.now())?;
+        let mut channel = self.open(ttl)?;
+        let sent = echo_request(
+            &self.rng,
+            Family::V4,
+            self.builder.payload.as_ref(),
+            sequence,
+        );
+        echo(
+            channel.as_mut(),
+            self.clock.as_ref(),
+            self.builder.capture.as_deref(),
+            IpAddr::V4(target),
+            &sent,
+            until,
+        )
+    }
+
+    // sends any ICMP message and waits for the first packet accepted by `predicate`,
+    // needs a raw socket, the DGRAM_SOCKET feature only allows echo requests
+    pub fn send_icmp<F: FnMut(&IcmpFormat) -> bool>(
+        &self,
+        target: std::net::Ipv4Addr,
+        message: &IcmpMessage,
+        predicate: F,
+    ) -> Result<IcmpV4Reply, PingError> {
+        self.pace(IpAddr::V4(target));
+        let until = timeout::reply_until(self.builder.timeout, self.session_end, self.clock.now())?;
+        let mut channel = self.open(None)?;
+        match exchange(
+            channel.as_mut(),
+            self.clock.as_ref(),
+            self.builder.capture.as_deref(),
+            IpAddr::V4(target),
+            message,
+            until,
+            predicate,
+        )? {
+            (IpAddr::V4(ip), duration, data) => Ok(IcmpV4Reply { ip, duration, data }),
+            (IpAddr::V6(_), ..) => Err(LinuxError::MissRespondAddr.into()),
         }
     }
+}
 
+impl PingV6 {
     #[inline]
-    pub fn ping(&self, target: std::net::Ipv4Addr) -> Result<std::time::Duration, PingError> {
-        let sock = self.precondition()?;
-        {
-            let addr = libc::sockaddr_in {
-                sin_family: libc::AF_INET as u16,
-                sin_port: 0,
-                sin_addr: unsafe { std::mem::transmute(target) },
-                sin_zero: Default::default(),
-            };
-            let err = unsafe {
-                libc::connect(
-                    sock,
-                    &addr as *const _ as *const libc::sockaddr,
-                    size_of::<libc::sockaddr_in>() as libc::socklen_t,
-                )
-            };
-            if err == -1 {
-                return Err(LinuxError::ConnectFailed(LinuxError::get_errno()).into());
-            }
+    pub fn new(builder: PingV6Builder) -> Self {
+        let clock = builder.clock.clone().unwrap_or(Arc::new(SystemClock));
+        let session_end = timeout::session_end(builder.deadline, clock.now());
+        Self {
+            rng: Mutex::new(new_rng(builder.seed)),
+            builder,
+            session_end,
+            clock,
         }
-        let sent = IcmpDataForPing::new_ping_v4();
-        common::send(sock, &sent)?;
-        let start_time = std::time::Instant::now();
+    }
 
-        let mut buff = std::mem::MaybeUninit::<[u8; IcmpDataForPing::DATA_SIZE]>::uninit();
-        {
-            let len = unsafe {
-                libc::recv(
-                    sock,
-                    buff.as_mut_ptr() as *mut _,
-                    IcmpDataForPing::DATA_SIZE,
-                    0,
-                )
-            };
-            let duration = std::time::Instant::now().duration_since(start_time);
-            if len == -1 {
-                return Err(LinuxError::convert_recv_failed(LinuxError::get_errno()));
-            }
-            Ipv4Header::from_slice(&unsafe { buff.assume_init_ref() }[..len as usize])
-                .and_then(|header| {
-                    let format = IcmpFormat::from_header_v4(&header)?;
-                    format.check_is_correspond_v4(&sent)?;
-                    Some(duration)
-                })
-                .ok_or(LinuxError::ResolveRecvFailed.into())
+    // `ttl` overrides the one of the builder
+    fn open(&self, ttl: Option<u8>) -> Result<Box<dyn Channel>, PingError> {
+        let config = ChannelConfig {
+            family: Family::V6,
+            ttl: ttl.or(self.builder.ttl),
+            bind_addr: self.builder.bind_addr.map(IpAddr::V6),
+            scope_id: self.builder.scope_id_option.unwrap_or(0),
+            broadcast: false,
+            #[cfg(target_os = "linux")]
+            netns: self.builder.netns.clone(),
+            #[cfg(target_os = "linux")]
+            interface: self.builder.interface.clone(),
+        };
+        match &self.builder.transport {
+            Some(transport) => transport.open(&config),
+            None => SocketTransport.open(&config),
         }
     }
 
+    // waits for the send slot of the shared rate limiter, if any
     #[inline]
-    pub fn ping_in_detail(&self, target: std::net::Ipv4Addr) -> Result<PingV4Result, PingError> {
-        let sock = self.precondition()?;
-        let sent = IcmpDataForPing::new_ping_v4();
-        {
-            let addr = libc::sockaddr_in {
-                sin_family: libc::AF_INET as u16,
-                sin_port: 0,
-                sin_addr: unsafe { std::mem::transmute(target) },
-                sin_zero: Default::default(),
-            };
-            let err = unsafe {
-                libc::sendto(
-                    sock,
-                    sent.get_inner().as_ptr() as *const _,
-                    IcmpDataForPing::DATA_SIZE,
-                    0,
-                    &addr as *const _ as *const libc::sockaddr,
-                    size_of::<libc::sockaddr_in>() as libc::socklen_t,
-                )
-            };
-            if err == -1 {
-                return Err(LinuxError::SendtoFailed(LinuxError::get_errno()).into());
-            }
+    fn pace(&self, target: IpAddr) {
+        if let Some(limiter) = &self.builder.rate_limiter {
+            limiter.acquire(target);
         }
-        let start_time = std::time::Instant::now();
+    }
 
-        const SIZE_OF_BUFF: usize = IcmpDataForPing::DATA_SIZE + 38;
-        let mut buff = std::mem::MaybeUninit::<[u8; SIZE_OF_BUFF]>::uninit();
-        // this buff size should depend on recv ttl exceeded message size, or you want to use libc::recvmsg instead
-        // let us calculate the buff size: 4 as ICMP header fix size + 4 as unused size in ICMP data + 20 as Ipv4Header fix size + IcmpDataForPing::DATA_SIZE + 10 as Safety
-        //          = IcmpDataForPing::DATA_SIZE + 38
-        {
-            let len = unsafe { libc::recv(sock, buff.as_mut_ptr() as *mut _, SIZE_OF_BUFF, 0) };
-            let duration = std::time::Instant::now().duration_since(start_time);
-            if len == -1 {
-                return Err(LinuxError::convert_recv_failed(LinuxError::get_errno()));
-            }
-            Ipv4Header::from_slice(&unsafe { buff.assume_init_ref() }[..len as usize])
-                .and_then(|header| {
-                    let format = IcmpFormat::from_header_v4(&header)?;
-                    format.check_is_correspond_v4(&sent)?;
-                    Some(PingV4Result {
-                        ip: header.get_source_address(),
-                        duration,
-                    })
-                })
-                .ok_or(LinuxError::ResolveRecvFailed.into())
-        }
+    #[inline]
+    pub fn ping(&self, target: std::net::Ipv6Addr) -> Result<std::time::Duration, PingError> {
+        self.ping_in_detail(target).map(|result| result.duration)
     }
-}
 
-impl PingV6 {
     #[inline]
-    pub fn new(builder: PingV6Builder) -> Self {
-        Self { builder }
+    pub fn ping_in_detail(&self, target: std::net::Ipv6Addr) -> Result<PingV6Result, PingError> {
+        match self.send_echo(target, None, None)?.into_result()? {
+            (IpAddr::V6(ip), duration) => Ok(PingV6Result { ip, duration }),
+            (IpAddr::V4(_), _) => Err(LinuxError::MissRespondAddr.into()),
+        }
+    }
+
+    // one echo request with `sequence` and the payload of the builder, as ping(8) sends them,
+    // ICMP errors quoting it (time exceeded too) are LinuxError::IcmpError
+    pub fn ping_seq(
+        &self,
+        target: std::net::Ipv6Addr,
+        sequence: u16,
+    ) -> Result<EchoV6Reply, PingError> {
+        match self.send_echo(target, Some(sequence), None)? {
+            Answer::Reply {
+                ip: IpAddr::V6(ip),
+                duration,
+                ttl,
+                size,
+            } => Ok(EchoV6Reply {
+                ip,
+                duration,
+                sequence,
+                ttl,
+                size,
+            }),
+            Answer::Reply { .. } => Err(LinuxError::MissRespondAddr.into()),
+            Answer::Error(e, _) => Err(LinuxError::IcmpError(e).into()),
+        }
     }
 
-    // APIs are so different between Ipv4 socket and Ipv6 socket, so many codes are different
+    // one echo request to a multicast group such as ff02::1, the interface is the scope_id_option
+    // (or the interface) of the builder, its ttl the hop limit, 1 by default,
+    // returns the first reply of every responder until the timeout, in the order they came
+    pub fn ping_all(
+        &self,
+        target: std::net::Ipv6Addr,
+        sequence: u16,
+    ) -> Result<EchoV6Replies, PingError> {
+        self.pace(IpAddr::V6(target));
+        let until = timeout::reply_until(self.builder.timeout, self.session_end, self.clock.now())?;
+        let mut channel = self.open(None)?;
+        let sent = echo_request(
+            &self.rng,
+            Family::V6,
+            self.builder.payload.as_ref(),
+            Some(sequence),
+        );
+        let (answers, corrupted) = collect(
+            channel.as_mut(),
+            self.clock.as_ref(),
+            self.builder.capture.as_deref(),
+            IpAddr::V6(target),
+            &sent,
+            until,
+        )?;
+        let replies = answers
+            .into_iter()
+            .filter_map(|answer| match answer {
+                Answer::Reply {
+                    ip: IpAddr::V6(ip),
+                    duration,
+                    ttl,
+                    size,
+                } => Some(EchoV6Reply {
+                    ip,
+                    duration,
+                    sequence,
+                    ttl,
+                    size,
+                }),
+                _ => None,
+            })
+            .collect();
+        let corrupted = corrupted
+            .into_iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V6(ip) => Some(ip),
+                _ => None,
+            })
+            .collect();
+        Ok(EchoV6Replies { replies, corrupted })
+    }
 
-    fn precondition(&self) -> Result<libc::c_int, PingError> {
-        #[cfg(not(feature = "DGRAM_SOCKET"))]
-        let sock = unsafe { libc::socket(libc::AF_INET6, libc::SOCK_RAW, libc::IPPROTO_ICMPV6) };
-        #[cfg(feature = "DGRAM_SOCKET")]
-        let sock = unsafe { libc::socket(libc::AF_INET6, libc::SOCK_DGRAM, libc::IPPROTO_ICMPV6) };
+    // an echo request with the payload of the builder, the sequence is random when None
+    pub(crate) fn send_echo(
+        &self,
+        target: std::net::Ipv6Addr,
+        sequence: Option<u16>,
+        ttl: Option<u8>,
+    ) -> Result<Answer, PingError> {
+        self.pace(IpAddr::V6(target));
+        let until = timeout::reply_until(self.builder.timeout, self.session_end, self.clock.now())?;
+        let mut channel = self.open(ttl)?;
+        let sent = echo_request(
+            &self.rng,
+            Family::V6,
+            self.builder.payload.as_ref(),
+            sequence,
+        );
+        echo(
+            channel.as_mut(),
+            self.clock.as_ref(),
+            self.builder.capture.as_deref(),
+            IpAddr::V6(target),
+            &sent,
+            until,
+        )
+    }
 
-        if sock == -1 {
-            return Err(LinuxError::convert_setup_failed(LinuxError::get_errno()).into());
+    // sends any ICMPv6 message and waits for the first packet accepted by `predicate`,
+    // needs a raw socket, the DGRAM_SOCKET feature only allows echo requests
+    pub fn send_icmp<F: FnMut(&IcmpFormat) -> bool>(
+        &self,
+        target: std::net::Ipv6Addr,
+        message: &IcmpMessage,
+        predicate: F,
+    ) -> Result<IcmpV6Reply, PingError> {
+        self.pace(IpAddr::V6(target));
+        let until = timeout::reply_until(self.builder.timeout, self.session_end, self.clock.now())?;
+        let mut channel = self.open(None)?;
+        match exchange(
+            channel.as_mut(),
+            self.clock.as_ref(),
+            self.builder.capture.as_deref(),
+            IpAddr::V6(target),
+            message,
+            until,
+            predicate,
+        )? {
+            (IpAddr::V6(ip), duration, data) => Ok(IcmpV6Reply { ip, duration, data }),
+            (IpAddr::V4(_), ..) => Err(LinuxError::MissRespondAddr.into()),
         }
+    }
+}
 
-        common::set_timeout(sock, self.builder.timeout)?;
-
-        {
-            let sock_addr = libc::sockaddr_in6 {
-                sin6_family: libc::AF_INET6 as u16,
-                sin6_port: 0,
-                sin6_flowinfo: 0,
-                sin6_addr: libc::in6_addr {
-                    s6_addr: Default::default(),
-                },
-                sin6_scope_id: self.builder.scope_id_option.unwrap_or(0),
-            };
+// a fixed seed makes the probe contents reproducible, e.g. for the simulator
+#[inline]
+fn new_rng(seed: Option<u64>) -> ChaCha8Rng {
+    match seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => ChaCha8Rng::from_rng(&mut rand::rng()),
+    }
+}
 
-            let err = unsafe {
-                libc::bind(
-                    sock,
-                    &sock_addr as *const _ as *const libc::sockaddr,
-                    size_of::<libc::sockaddr_in6>() as libc::socklen_t,
-                )
-            };
-            if err == -1 {
-                Err(LinuxError::BindFailed(LinuxError::get_errno()).into())
-            } else {
-                Ok(sock)
-            }
+// the echo request of a ping, with a random sequence when it is None,
+// without a payload it is the IcmpDataForPing of the seed
+fn echo_request(
+    rng: &Mutex<ChaCha8Rng>,
+    family: Family,
+    payload: Option<&Payload>,
+    sequence: Option<u16>,
+) -> Vec<u8> {
+    let mut rng = rng.lock().unwrap();
+    let Some(payload) = payload else {
+        let mut data = match family {
+            Family::V4 => IcmpDataForPing::new_ping_v4(rng.random()),
+            Family::V6 => IcmpDataForPing::new_ping_v6(rng.random()),
+        };
+        if let Some(sequence) = sequence {
+            data.set_sequence(sequence);
         }
+        return data.get_inner().to_vec();
+    };
+    let [high, low] = sequence.unwrap_or_else(|| rng.random()).to_be_bytes();
+    let tag: [u8; 8] = rng.random();
+    let mut body = Vec::with_capacity(payload.size);
+    body.extend_from_slice(&tag[..payload.size.min(tag.len())]);
+    for i in body.len()..payload.size {
+        body.push(match payload.pattern.len() {
+            0 => i as u8,
+            len => payload.pattern[(i - tag.len()) % len],
+        });
     }
+    let message = match family {
+        Family::V4 => IcmpMessage::new_v4(8, 0, [0, 0, high, low], &body),
+        Family::V6 => IcmpMessage::new_v6(128, 0, [0, 0, high, low], &body),
+    };
+    message.get_inner().to_vec()
+}
 
-    #[inline]
-    pub fn ping(&self, target: std::net::Ipv6Addr) -> Result<std::time::Duration, PingError> {
-        let sock = self.precondition()?;
+// ICMP errors are limited to 576 bytes (RFC 1812) and 1280 bytes (RFC 4443),
+// so every reply we care about fits and its checksum can be verified
+const RECV_BUFF_SIZE: usize = 1500;
+
+// what echo got for the probe
+pub(crate) enum Answer {
+    // `size` is the length of the ICMP message
+    Reply {
+        ip: IpAddr,
+        duration: std::time::Duration,
+        ttl: Option<u8>,
+        size: usize,
+    },
+    // an ICMP error quoting the probe, from the error queue or a raw socket
+    Error(ExtendedError, std::time::Duration),
+}
 
-        {
-            let addr = common::get_addr_v6(target, self.builder.scope_id_option.unwrap_or(0));
-            let err = unsafe {
-                libc::connect(
-                    sock,
-                    &addr as *const _ as *const libc::sockaddr,
-                    size_of::<libc::sockaddr_in6>() as libc::socklen_t,
-                )
-            };
-            if err == -1 {
-                return Err(LinuxError::ConnectFailed(LinuxError::get_errno()).into());
-            }
+impl Answer {
+    // time exceeded is treated as an answer from a hop
+    #[inline]
+    fn into_result(self) -> Result<(IpAddr, std::time::Duration), PingError> {
+        match self {
+            Answer::Reply { ip, duration, .. } => Ok((ip, duration)),
+            Answer::Error(e, duration) => e.into_offender().map(|ip| (ip, duration)),
         }
+    }
+}
 
-        let sent = IcmpDataForPing::new_ping_v6();
-        common::send(sock, &sent)?;
-        let start_time = std::time::Instant::now();
+// sends `sent` and waits for its echo reply, or for an ICMP error quoting it
+fn echo(
+    channel: &mut dyn Channel,
+    clock: &dyn Clock,
+    capture: Option<&Capture>,
+    target: IpAddr,
+    sent: &[u8],
+    until: std::time::Instant,
+) -> Result<Answer, PingError> {
+    let sent_at = send(channel, capture, target, sent)?;
+    let mut buff = recv_buff(sent);
+    loop {
+        let timeout = timeout::remaining(until, clock.now())?;
+        let (received, packet) = recv(channel, capture, &mut buff, timeout)?;
+        if let Some(answer) = answer(capture, target, sent, sent_at, &received, packet, &[])? {
+            return Ok(answer);
+        }
+    }
+}
 
-        let mut buff = std::mem::MaybeUninit::<[u8; IcmpDataForPing::DATA_SIZE]>::uninit();
+// sends `sent` and collects the echo reply of every responder until `until`, for multicast and broadcast,
+// with the responders of replies with a wrong checksum,
+// ICMP errors, repeated replies and other replies that can't be verified are skipped
+fn collect(
+    channel: &mut dyn Channel,
+    clock: &dyn Clock,
+    capture: Option<&Capture>,
+    target: IpAddr,
+    sent: &[u8],
+    until: std::time::Instant,
+) -> Result<(Vec<Answer>, Vec<IpAddr>), PingError> {
+    let sent_at = send(channel, capture, target, sent)?;
+    let mut buff = recv_buff(sent);
+    let mut responders = Vec::new();
+    let mut answers = Vec::new();
+    let mut corrupted = Vec::new();
+    loop {
+        let (received, packet) = match timeout::remaining(until, clock.now())
+            .and_then(|timeout| recv(channel, capture, &mut buff, timeout))
         {
-            let len = unsafe {
-                libc::recv(
-                    sock,
-                    buff.as_mut_ptr() as *mut _,
-                    IcmpDataForPing::DATA_SIZE,
-                    0,
-                )
-            };
-            let duration = std::time::Instant::now().duration_since(start_time);
-            if len == -1 {
-                return Err(LinuxError::convert_recv_failed(LinuxError::get_errno()));
+            Ok(next) => next,
+            Err(PingError::SharedError(SharedError::Timeout)) => return Ok((answers, corrupted)),
+            Err(e) => return Err(e),
+        };
+        match answer(
+            capture,
+            target,
+            sent,
+            sent_at,
+            &received,
+            packet,
+            &responders,
+        ) {
+            Ok(Some(answer @ Answer::Reply { ip, .. })) => {
+                responders.push(ip);
+                answers.push(answer);
+            }
+            Err(PingError::SharedError(SharedError::BadChecksum)) => {
+                if let Some(ip) = received.source
+                    && !corrupted.contains(&ip)
+                {
+                    corrupted.push(ip);
+                }
             }
-            IcmpFormat::from_slice(unsafe { buff.assume_init_ref() })
-                .and_then(|format| format.check_is_correspond_v6(&sent))
-                .map(|_| duration)
-                .ok_or(LinuxError::ResolveRecvFailed.into())
+            _ => {}
         }
     }
+}
 
-    #[inline]
-    pub fn ping_in_detail(&self, target: std::net::Ipv6Addr) -> Result<PingV6Result, PingError> {
-        let sock = self.precondition()?;
+#[inline]
+fn send(
+    channel: &mut dyn Channel,
+    capture: Option<&Capture>,
+    target: IpAddr,
+    sent: &[u8],
+) -> Result<std::time::Instant, PingError> {
+    let sent_at = channel.send_to(sent, target)?;
+    if let Some(capture) = capture {
+        capture.sent(sent_at, target, sent);
+    }
+    Ok(sent_at)
+}
 
-        let mut sent = IcmpDataForPing::new_ping_v6();
-        {
-            let mut addr_v6 =
-                common::get_addr_v6(target, self.builder.scope_id_option.unwrap_or(0));
-
-            match self.builder.ttl {
-                // 没错, ipv6设置ttl(HopLimit)就是这么繁琐
-                None => {
-                    let err = unsafe {
-                        libc::sendto(
-                            sock,
-                            sent.get_inner_mut().as_mut_ptr() as *mut _,
-                            IcmpDataForPing::DATA_SIZE,
-                            0,
-                            &mut addr_v6 as *mut _ as *mut _,
-                            size_of::<libc::sockaddr_in6>() as libc::socklen_t,
-                        )
-                    };
-                    if err == -1 {
-                        return Err(LinuxError::SendtoFailed(LinuxError::get_errno()).into());
-                    }
-                }
-                Some(ttl) => {
-                    let ttl = ttl as TTL; // use u32, instead you will have to deal with problem in CMSG_LEN API
-                    type TTL = u32;
-
-                    let mut iovec = [libc::iovec {
-                        iov_base: sent.get_inner_mut().as_mut_ptr() as *mut _,
-                        iov_len: IcmpDataForPing::DATA_SIZE,
-                    }];
-
-                    const CONTROL_BUFF_LEN: usize =
-                        unsafe { libc::CMSG_SPACE(size_of::<TTL>() as _) as usize };
-                    let mut control_buff = [0_u8; CONTROL_BUFF_LEN];
-                    let msghdr = libc::msghdr {
-                        msg_name: &mut addr_v6 as *mut _ as *mut _,
-                        msg_namelen: size_of::<libc::sockaddr_in6>() as libc::socklen_t,
-                        msg_iov: &mut iovec as *mut _ as *mut _,
-                        msg_iovlen: 1,
-                        msg_control: &mut control_buff as *mut _ as *mut _,
-                        msg_controllen: CONTROL_BUFF_LEN,
-                        msg_flags: 0,
-                    };
-                    let ttl_cmsghdr: volatile::VolatilePtr<libc::cmsghdr> = unsafe {
-                        volatile::VolatilePtr::new(
-                            std::ptr::NonNull::new(libc::CMSG_FIRSTHDR(&msghdr))
-                                .ok_or(LinuxError::NullPtr)?,
-                        ) // use VolatilePtr to avoid being optimized
-                    };
-
-                    ttl_cmsghdr.update(|mut cmsg| {
-                        cmsg.cmsg_level = libc::SOL_IPV6;
-                        cmsg.cmsg_type = libc::IPV6_HOPLIMIT;
-                        cmsg.cmsg_len =
-                            unsafe { libc::CMSG_LEN(size_of::<TTL>() as _) } as libc::size_t;
-                        cmsg
-                    });
-                    let _ = unsafe {
-                        volatile::VolatilePtr::new(
-                            std::ptr::NonNull::new(libc::CMSG_DATA(
-                                ttl_cmsghdr.as_raw_ptr().as_ptr(),
-                            ))
-                            .ok_or(LinuxError::NullPtr)?,
-                        )
-                        .map(|data_ptr| {
-                            data_ptr.as_ptr().copy_from_nonoverlapping(
-                                &ttl as *const _ as *const _,
-                                size_of::<TTL>(),
-                            );
-                            data_ptr
-                        })
-                    };
-                    let err = unsafe { libc::sendmsg(sock, &msghdr as *const _ as *const _, 0) };
-                    if err == -1 {
-                        return Err(LinuxError::SendMessageFailed(LinuxError::get_errno()).into());
-                    }
+// the ICMP message and, from raw IPv4 sockets, the IP header in front of it, captured as received
+#[derive(Clone, Copy)]
+struct Packet<'a> {
+    header: &'a [u8],
+    data: &'a [u8],
+}
+
+// the next packet, one whose IP header can't be read is still captured before failing
+fn recv<'a>(
+    channel: &mut dyn Channel,
+    capture: Option<&Capture>,
+    buff: &'a mut [u8],
+    timeout: std::time::Duration,
+) -> Result<(Received, Packet<'a>), PingError> {
+    let (mut received, ip_header) = channel.recv_raw(buff, timeout)?;
+    let buff = &*buff;
+    let header_length = match ip_header {
+        true => match split_ipv4_header(buff, &mut received) {
+            Ok(header_length) => header_length,
+            Err(e) => {
+                if let Some(capture) = capture {
+                    capture.unparseable(received.timestamp, &buff[..received.len]);
                 }
-            };
-        }
-        let start_time = std::time::Instant::now();
+                return Err(e);
+            }
+        },
+        false => 0,
+    };
+    let (header, rest) = buff.split_at(header_length);
+    let data = &rest[..received.len];
+    Ok((received, Packet { header, data }))
+}
 
-        // let us calculate the buff size: 4 as ICMPv6 header fix size + 4 as unused size in ICMPv6 data + 40 as Ipv6Header fix size + IcmpDataForPing::DATA_SIZE + 10 as Safety
-        //          = IcmpDataForPing::DATA_SIZE + 58
-        const SIZE_OF_BUFF: usize = IcmpDataForPing::DATA_SIZE + 58;
-        let mut buff: std::mem::MaybeUninit<[u8; SIZE_OF_BUFF]> = std::mem::MaybeUninit::uninit();
-        {
-            let mut addr_v6 = std::mem::MaybeUninit::<libc::sockaddr_in6>::uninit();
-            let mut iovec = [libc::iovec {
-                iov_base: buff.as_mut_ptr() as *mut _,
-                iov_len: SIZE_OF_BUFF,
-            }];
-            let mut msg = libc::msghdr {
-                msg_name: addr_v6.as_mut_ptr() as *mut _,
-                msg_namelen: size_of::<libc::sockaddr_in6>() as libc::socklen_t,
-                msg_iov: &mut iovec as *mut _ as *mut _,
-                msg_iovlen: 1,
-                msg_control: std::ptr::null_mut(),
-                msg_controllen: 0,
-                msg_flags: 0,
-            };
+// a big payload comes back as big, 60 is the longest IPv4 header
+#[inline]
+fn recv_buff(sent: &[u8]) -> Vec<u8> {
+    vec![0_u8; RECV_BUFF_SIZE.max(sent.len() + 60)]
+}
 
-            // if you don't use recvmsg, you can't get source socketaddr
-            let len = unsafe { libc::recvmsg(sock, &mut msg as *mut _ as *mut _, 0) };
-            let duration = std::time::Instant::now().duration_since(start_time);
-            if len == -1 {
-                return Err(LinuxError::convert_recv_failed(LinuxError::get_errno()));
+// what `received` tells about the probe `sent`, None when it isn't about it,
+// or it is another reply from one of `responders`
+fn answer(
+    capture: Option<&Capture>,
+    target: IpAddr,
+    sent: &[u8],
+    sent_at: std::time::Instant,
+    received: &Received,
+    packet: Packet,
+    responders: &[IpAddr],
+) -> Result<Option<Answer>, PingError> {
+    let duration = received.timestamp.saturating_duration_since(sent_at);
+    let record = |verdict: &str| {
+        if let Some(capture) = capture {
+            capture.received(target, received, packet.header, packet.data, verdict);
+        }
+    };
+    let data = packet.data;
+    match &received.kind {
+        ReceivedKind::Error(extended) => {
+            // the queued payload is the ICMP message we sent, maybe truncated by the router
+            let len = data.len().min(sent.len());
+            if len >= 8 && data[6..len].eq(&sent[6..len]) {
+                record("accepted: ICMP error quoting the probe");
+                return Ok(Some(Answer::Error(extended.clone(), duration)));
             }
-            if msg.msg_namelen == 0 {
+            record("rejected: ICMP error quoting another probe");
+            Ok(None)
+        }
+        ReceivedKind::Icmp => {
+            let Some(format) = IcmpFormat::from_slice(data) else {
+                // on a raw socket, maybe the traffic of another process, keep waiting
+                record("rejected: not an ICMP message");
+                return Ok(None);
+            };
+            let corresponds = match target {
+                IpAddr::V4(_) => format.check_is_answer_v4(sent),
+                IpAddr::V6(_) => format.check_is_answer_v6(sent),
+            };
+            if corresponds.is_none() {
+                // e.g. our own request on loopback, keep waiting
+                record("rejected: not an answer to the probe");
+                return Ok(None);
+            }
+            if let Err(e) = verify_checksum(&format, received, target) {
+                record("rejected: bad checksum, BadChecksum");
+                return Err(e);
+            }
+            let Some(ip) = received.source else {
+                record("rejected: no source address, MissRespondAddr");
                 return Err(LinuxError::MissRespondAddr.into());
+            };
+            if responders.contains(&ip) {
+                record("rejected: another reply from the same responder");
+                return Ok(None);
             }
-            let len = if len > SIZE_OF_BUFF as isize {
-                SIZE_OF_BUFF
-            } else {
-                len as usize
+            record("accepted: answer to the probe");
+            // time exceeded read by a raw socket, told as the error queue does
+            let (origin, is_error) = match target {
+                IpAddr::V4(_) => (libc::SO_EE_ORIGIN_ICMP, format.get_type() != 0),
+                IpAddr::V6(_) => (libc::SO_EE_ORIGIN_ICMP6, format.get_type() != 129),
             };
-            IcmpFormat::from_slice(&unsafe { buff.assume_init_ref() }[..len])
-                .and_then(|format| format.check_is_correspond_v6(&sent))
-                .map(|_| PingV6Result {
-                    ip: std::net::Ipv6Addr::from(
-                        unsafe { addr_v6.assume_init() }.sin6_addr.s6_addr,
-                    ),
-                    duration,
-                })
-                .ok_or(LinuxError::ResolveRecvFailed.into())
+            if is_error {
+                let extended = ExtendedError {
+                    errno: libc::EHOSTUNREACH as u32,
+                    origin,
+                    icmp_type: format.get_type(),
+                    icmp_code: format.get_code(),
+                    info: 0,
+                    offender: Some(ip),
+                };
+                return Ok(Some(Answer::Error(extended, duration)));
+            }
+            Ok(Some(Answer::Reply {
+                ip,
+                duration,
+                ttl: received.ttl,
+                size: data.len(),
+            }))
+        }
+    }
+}
+
+// sends `message` and waits for the first ICMP message accepted by `predicate`,
+// errors the message may trigger are skipped, so only a timeout ends the wait
+fn exchange<F: FnMut(&IcmpFormat) -> bool>(
+    channel: &mut dyn Channel,
+    clock: &dyn Clock,
+    capture: Option<&Capture>,
+    target: IpAddr,
+    message: &IcmpMessage,
+    until: std::time::Instant,
+    mut predicate: F,
+) -> Result<(IpAddr, std::time::Duration, Vec<u8>), PingError> {
+    let sent_at = send(channel, capture, target, message.get_inner())?;
+    let mut buff = vec![0_u8; u16::MAX as usize];
+    loop {
+        let timeout = timeout::remaining(until, clock.now())?;
+        let (received, Packet { header, data }) = recv(channel, capture, &mut buff, timeout)?;
+        let verdict = match received.kind {
+            ReceivedKind::Error(_) => "rejected: ICMP error",
+            ReceivedKind::Icmp => match IcmpFormat::from_slice(data) {
+                None => "rejected: not an ICMP message",
+                Some(format) if !predicate(&format) => "rejected by the predicate",
+                Some(_) => "accepted by the predicate",
+            },
+        };
+        if let Some(capture) = capture {
+            capture.received(target, &received, header, data, verdict);
+        }
+        if verdict.starts_with("accepted") {
+            return Ok((
+                received.source.ok_or(LinuxError::MissRespondAddr)?,
+                received.timestamp.saturating_duration_since(sent_at),
+                data.to_vec(),
+            ));
+        }
+    }
+}
+
+// a reply cut short, or an ICMPv6 one without its pseudo header addresses, can't be checked
+#[inline]
+fn verify_checksum(
+    format: &IcmpFormat,
+    received: &Received,
+    target: IpAddr,
+) -> Result<(), PingError> {
+    let valid = match (target, received.source, received.destination) {
+        _ if received.truncated => true,
+        (IpAddr::V4(_), ..) => format.is_checksum_valid_v4(),
+        (IpAddr::V6(_), Some(IpAddr::V6(source)), Some(IpAddr::V6(destination))) => {
+            format.is_checksum_valid_v6(source, destination)
         }
+        (IpAddr::V6(_), ..) => true,
+    };
+    if !valid {
+        return Err(SharedError::BadChecksum.into());
     }
+    Ok(())
 }
 
-impl Into<PingV4> for PingV4Builder {
+impl From<PingV4Builder> for PingV4 {
     #[inline]
-    fn into(self) -> PingV4 {
-        PingV4 { builder: self }
+    fn from(builder: PingV4Builder) -> Self {
+        PingV4::new(builder)
     }
 }
 
-impl Into<PingV6> for PingV6Builder {
+impl From<PingV6Builder> for PingV6 {
     #[inline]
-    fn into(self) -> PingV6 {
-        PingV6 { builder: self }
+    fn from(builder: PingV6Builder) -> Self {
+        PingV6::new(builder)
     }
 }
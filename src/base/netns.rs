@@ -0,0 +1,47 @@
+use crate::base::error::PingError;
+use crate::base::linux::LinuxError;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+// a network namespace to create the sockets in, set it as `netns` of the builder,
+// only the socket is created there, the calling thread stays where it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Netns {
+    // /var/run/netns/<name>, as made by `ip netns add`
+    Named(String),
+    // e.g. an open /proc/<pid>/ns/net, kept open by the caller
+    Fd(RawFd),
+}
+
+impl Netns {
+    // runs `f` on a helper thread moved into the namespace
+    pub(crate) fn run<T: Send>(&self, f: impl FnOnce() -> T + Send) -> Result<T, PingError> {
+        let file;
+        let fd = match self {
+            Netns::Named(name) => {
+                if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                    return Err(LinuxError::SetNsFailed(libc::EINVAL).into());
+                }
+                let path = std::ffi::CString::new(format!("/var/run/netns/{name}"))
+                    .map_err(|_| LinuxError::SetNsFailed(libc::EINVAL))?;
+                let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+                if fd == -1 {
+                    return Err(LinuxError::SetNsFailed(LinuxError::get_errno()).into());
+                }
+                file = unsafe { OwnedFd::from_raw_fd(fd) };
+                file.as_raw_fd()
+            }
+            Netns::Fd(fd) => *fd,
+        };
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    if unsafe { libc::setns(fd, libc::CLONE_NEWNET) } == -1 {
+                        return Err(LinuxError::SetNsFailed(LinuxError::get_errno()).into());
+                    }
+                    Ok(f())
+                })
+                .join()
+                .unwrap()
+        })
+    }
+}
@@ -1,8 +1,14 @@
+// the parsers live in the no_std rps_ping_protocol crate, only what allocates is kept here
 mod icmp;
-mod ip_header;
 
-pub use ip_header::Ipv4Header;
-pub use ip_header::Ipv6Header;
+pub use rps_ping_protocol::checksum;
 
-pub use icmp::IcmpDataForPing;
-pub use icmp::IcmpFormat;
+pub use rps_ping_protocol::HeaderChain;
+pub use rps_ping_protocol::Ipv4Header;
+pub use rps_ping_protocol::Ipv6Extension;
+pub use rps_ping_protocol::Ipv6Header;
+pub use rps_ping_protocol::ParseMode;
+
+pub use icmp::IcmpMessage;
+pub use rps_ping_protocol::IcmpDataForPing;
+pub use rps_ping_protocol::IcmpFormat;
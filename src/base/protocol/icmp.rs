@@ -1,136 +1,91 @@
-use crate::base::protocol::{Ipv4Header, Ipv6Header};
-use rand::Rng;
+use crate::base::protocol::{IcmpFormat, checksum};
 
-pub struct IcmpDataForPing {
-    data: [u8; IcmpDataForPing::DATA_SIZE],
+// any ICMP/ICMPv6 message, for crafting requests other than echo
+#[derive(Debug, Clone)]
+pub struct IcmpMessage {
+    data: Vec<u8>,
 }
 
-impl IcmpDataForPing {
-    pub const DATA_SIZE: usize = 22;
+impl IcmpMessage {
+    pub const HEADER_SIZE: usize = 8;
 
-    #[inline]
-    pub fn new_ping_v4() -> Self {
-        let request_data: u128 = rand::rng().random();
-
-        let mut data = [0_u8; Self::DATA_SIZE];
-        data[0] = 8;
-        data[6..].copy_from_slice(&request_data.to_be_bytes());
-
-        Self::process_check_sum(&mut data);
-
-        IcmpDataForPing { data }
+    // e.g. timestamp request: new_v4(13, 0, [id, id, seq, seq], &[0; 12])
+    pub fn new_v4(icmp_type: u8, code: u8, rest_of_header: [u8; 4], body: &[u8]) -> Self {
+        let mut message = Self::new_v6(icmp_type, code, rest_of_header, body);
+        let sum = checksum::checksum(&message.data);
+        message.data[2..4].copy_from_slice(&sum.to_be_bytes());
+        message
     }
 
-    #[inline]
-    pub fn new_ping_v6() -> Self {
-        let request_data: u128 = rand::rng().random();
-
-        let mut data = [0_u8; Self::DATA_SIZE];
-        data[0] = 128;
-        data[6..].copy_from_slice(&request_data.to_be_bytes());
-
-        Self::process_check_sum(&mut data);
-
-        IcmpDataForPing { data }
+    // the kernel always fills the ICMPv6 checksum, because it needs the pseudo header
+    pub fn new_v6(icmp_type: u8, code: u8, rest_of_header: [u8; 4], body: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(Self::HEADER_SIZE + body.len());
+        data.extend_from_slice(&[icmp_type, code, 0, 0]);
+        data.extend_from_slice(&rest_of_header);
+        data.extend_from_slice(body);
+        IcmpMessage { data }
     }
 
-    fn process_check_sum(data: &mut [u8; Self::DATA_SIZE]) {
-        let mut sum: u32 = 0;
-        let mut i = 0;
-        while i < IcmpDataForPing::DATA_SIZE {
-            // 取出每两个字节，拼接成16位
-            let word = if i + 1 < IcmpDataForPing::DATA_SIZE {
-                // 如果有两个字节，拼接成一个16位字
-                ((data[i] as u16) << 8) | (data[i + 1] as u16)
-            } else {
-                // 如果只剩一个字节，拼接成一个16位字，低8位为0
-                (data[i] as u16) << 8
-            };
-
-            // 累加到sum中
-            sum += word as u32;
-
-            // 如果有溢出，进位加回
-            if sum > 0xFFFF {
-                sum = (sum & 0xFFFF) + 1;
-            }
-
-            i += 2;
-        }
-        data[2..4].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+    // takes the message as is, the checksum is not touched
+    #[inline]
+    pub fn from_vec(data: Vec<u8>) -> Option<Self> {
+        (data.len() >= 4).then_some(IcmpMessage { data })
     }
 
-    fn icmp_type(&self) -> u8 {
+    #[inline]
+    pub fn get_type(&self) -> u8 {
         self.data[0]
     }
 
     #[inline]
-    pub fn get_inner(&self) -> &[u8; IcmpDataForPing::DATA_SIZE] {
-        &self.data
+    pub fn get_code(&self) -> u8 {
+        self.data[1]
     }
 
     #[inline]
-    pub fn get_inner_mut(&mut self) -> &mut [u8; IcmpDataForPing::DATA_SIZE] {
-        &mut self.data
+    pub fn get_checksum(&self) -> u16 {
+        u16::from_be_bytes([self.data[2], self.data[3]])
     }
-}
 
-pub struct IcmpFormat<'a> {
-    icmp_type: u8,
-    // code: u8,
-    checksum: u16,
-    other_data: &'a [u8],
-}
-
-impl<'a> IcmpFormat<'a> {
-    pub fn from_slice(slice: &[u8]) -> Option<IcmpFormat<'_>> {
-        if slice.len() < 4 {
-            None
-        } else {
-            Some(IcmpFormat {
-                icmp_type: slice[0],
-                // code: slice[1],
-                checksum: u16::from_be_bytes(slice[2..4].try_into().unwrap()),
-                other_data: &slice[4..],
-            })
-        }
+    #[inline]
+    pub fn as_format(&self) -> IcmpFormat<'_> {
+        IcmpFormat::from_slice(&self.data).unwrap()
     }
 
     #[inline]
-    pub fn from_header_v4(header: &Ipv4Header<'a>) -> Option<IcmpFormat<'a>> {
-        IcmpFormat::from_slice(header.get_payload())
+    pub fn get_inner(&self) -> &[u8] {
+        &self.data
     }
+}
 
-    // #[inline]
-    // pub fn icmp_type(&self) -> u8 {
-    //     self.icmp_type
-    // }
+#[cfg(test)]
+mod tests {
+    use crate::base::protocol::{IcmpFormat, IcmpMessage};
 
-    pub fn check_is_correspond_v4(&self, data: &IcmpDataForPing) -> Option<()> {
-        match (data.icmp_type(), self.icmp_type) {
-            (8, 0) => self.other_data[2..].eq(&data.data[6..]).then_some(()),
-            (8, 11) => {
-                // Time to live exceeded
-                Ipv4Header::from_slice(&self.other_data[4..]) // 使用uncheck的原因是部分Time to live exceeded响应并未传递ICMP请求的Data部分非序列号和识别部分
-                    .and_then(|header| IcmpFormat::from_header_v4(&header))
-                    .and_then(|icmp| {
-                        // 直接比较checksum,因为有部分响应实现并未传递其余部分
-                        icmp.checksum
-                            .eq(&u16::from_be_bytes([data.data[2], data.data[3]]))
-                            .then_some(())
-                    })
-            }
-            _ => None,
+    #[test]
+    fn test_icmp_message_checksum() {
+        let message = IcmpMessage::new_v4(13, 0, [0, 1, 0, 2], &[0; 12]);
+        assert_eq!(message.get_inner().len(), 20);
+        // summing a message together with its checksum gives 0xffff
+        let mut sum: u32 = 0;
+        for word in message.get_inner().chunks(2) {
+            sum += u16::from_be_bytes([word[0], word[1]]) as u32;
         }
-    }
-
-    pub fn check_is_correspond_v6(&self, data: &IcmpDataForPing) -> Option<()> {
-        match (data.icmp_type(), self.icmp_type) {
-            (128, 129) => self.other_data[2..].eq(&data.data[6..]).then_some(()),
-            (128, 3) => Ipv6Header::from_slice(&self.other_data[4..])
-                .and_then(|header| IcmpFormat::from_slice(header.get_payload()?))
-                .and_then(|format| format.other_data[2..].eq(&data.data[6..]).then_some(())),
-            _ => None,
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
         }
+        assert_eq!(sum, 0xffff);
+        assert_eq!(message.as_format().get_sequence(), Some(2));
+        assert!(message.as_format().is_checksum_valid_v4());
+        let mut corrupted = message.get_inner().to_vec();
+        corrupted[10] ^= 0x40;
+        assert!(
+            !IcmpFormat::from_slice(&corrupted)
+                .unwrap()
+                .is_checksum_valid_v4()
+        );
+
+        let odd = IcmpMessage::new_v4(8, 0, [0, 0, 0, 1], &[1, 2, 3]);
+        assert_eq!(odd.get_checksum(), !(0x0800u16 + 0x0001 + 0x0102 + 0x0300));
     }
 }
@@ -0,0 +1,229 @@
+use crate::base::clock::{Clock, SystemClock};
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// destinations kept before the idle ones are forgotten
+const MAX_IDLE_TARGETS: usize = 4096;
+
+pub struct RateLimiterBuilder {
+    pub rate: Option<f64>, // packets per second of all probes, None for no limit
+    pub per_target: Option<f64>, // packets per second to one destination
+    pub burst: u32,        // probes sent back to back, 1 spaces every probe evenly
+    pub clock: Option<Arc<dyn Clock>>, // None for the system clock
+}
+
+impl Default for RateLimiterBuilder {
+    fn default() -> Self {
+        RateLimiterBuilder {
+            rate: None,
+            per_target: None,
+            burst: 1,
+            clock: None,
+        }
+    }
+}
+
+impl RateLimiterBuilder {
+    #[inline]
+    pub fn new(rate: f64) -> Self {
+        RateLimiterBuilder {
+            rate: Some(rate),
+            ..Default::default()
+        }
+    }
+
+    // shared by Arc, e.g. as `rate_limiter` of every builder in the process
+    pub fn build(self) -> Arc<RateLimiter> {
+        let burst = self.burst.max(1);
+        Arc::new(RateLimiter {
+            clock: self.clock.unwrap_or(Arc::new(SystemClock)),
+            global: self.rate.and_then(|rate| Bucket::new(rate, burst)),
+            per_target: self.per_target.and_then(|rate| Bucket::new(rate, burst)),
+            state: Mutex::new(State::default()),
+        })
+    }
+}
+
+// token buckets of all probes and of each destination, kept as the time the bucket is
+// full again (GCRA), so a probe knows its send slot as soon as it asks for one,
+// a probe held back by the limit of its destination books its later slot aside instead of
+// taking the global bucket up to it, so the probes to other destinations go first
+pub struct RateLimiter {
+    clock: Arc<dyn Clock>,
+    global: Option<Bucket>,
+    per_target: Option<Bucket>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    interval: Duration,  // one token
+    tolerance: Duration, // the tokens of a burst but one
+}
+
+#[derive(Debug, Default)]
+struct State {
+    global: Option<Instant>,
+    // slots of probes held back by their destination, kept free of the others
+    booked: BTreeSet<Instant>,
+    targets: HashMap<IpAddr, Instant>,
+}
+
+impl Bucket {
+    // None without a limit
+    fn new(rate: f64, burst: u32) -> Option<Self> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return None;
+        }
+        let interval = Duration::from_secs_f64(1.0 / rate);
+        Some(Bucket {
+            interval,
+            tolerance: interval * (burst - 1),
+        })
+    }
+
+    // the first slot the bucket allows
+    #[inline]
+    fn earliest(&self, full: Option<Instant>, now: Instant) -> Instant {
+        match full {
+            Some(full) if full > now + self.tolerance => full - self.tolerance,
+            _ => now,
+        }
+    }
+
+    // takes a token at `slot`, returns when the bucket is full again
+    #[inline]
+    fn take(&self, full: Option<Instant>, slot: Instant) -> Instant {
+        full.map_or(slot, |full| full.max(slot)) + self.interval
+    }
+}
+
+impl RateLimiter {
+    // waits for the send slot of a probe to `target`, in the order asked unless its destination
+    // holds it back
+    pub fn acquire(&self, target: IpAddr) {
+        let slot = self.reserve(target);
+        self.clock.sleep_until(slot);
+    }
+
+    // the send slot of a probe to `target`, counted as sent then
+    pub fn reserve(&self, target: IpAddr) -> Instant {
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.now();
+        let full = state.targets.get(&target).copied();
+        let mut slot = match &self.per_target {
+            Some(per_target) => per_target.earliest(full, now),
+            None => now,
+        };
+        if let Some(global) = &self.global {
+            let earliest = global.earliest(state.global, now);
+            state
+                .booked
+                .retain(|booked| *booked + global.interval > now);
+            let held_back = slot > earliest;
+            slot = slot.max(earliest);
+            // at least one interval away from every booked slot
+            while let Some(booked) = state
+                .booked
+                .range(..slot + global.interval)
+                .next_back()
+                .filter(|booked| **booked + global.interval > slot)
+            {
+                slot = *booked + global.interval;
+            }
+            if held_back {
+                state.booked.insert(slot);
+            } else {
+                state.global = Some(global.take(state.global, slot));
+            }
+        }
+        if let Some(per_target) = &self.per_target {
+            if state.targets.len() >= MAX_IDLE_TARGETS {
+                state.targets.retain(|_, full| *full > now);
+            }
+            state.targets.insert(target, per_target.take(full, slot));
+        }
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::clock::VirtualClock;
+
+    #[test]
+    fn test_rate_limiter() {
+        let clock = Arc::new(VirtualClock::new());
+        let limiter = RateLimiterBuilder {
+            rate: Some(100.0),
+            per_target: Some(10.0),
+            clock: Some(clock.clone()),
+            ..Default::default()
+        }
+        .build();
+        let (a, b): (IpAddr, IpAddr) = ("192.0.2.1".parse().unwrap(), "::1".parse().unwrap());
+        let start = clock.now();
+        let slots: Vec<Duration> = [a, b, a, b, b]
+            .into_iter()
+            .map(|target| limiter.reserve(target) - start)
+            .collect();
+        // 10ms apart overall, 100ms apart to one destination
+        assert_eq!(slots, [0, 10, 100, 110, 210].map(Duration::from_millis));
+        // right when `a` allows it again, 10ms before the last one to `b`
+        limiter.acquire(a);
+        assert_eq!(clock.elapsed(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_held_back() {
+        let clock = Arc::new(VirtualClock::new());
+        let limiter = RateLimiterBuilder {
+            per_target: Some(10.0),
+            clock: Some(clock.clone()),
+            ..RateLimiterBuilder::new(100.0)
+        }
+        .build();
+        let (a, b, c): (IpAddr, IpAddr, IpAddr) = (
+            "192.0.2.1".parse().unwrap(),
+            "192.0.2.2".parse().unwrap(),
+            "192.0.2.3".parse().unwrap(),
+        );
+        let start = clock.now();
+        let mut slots = Vec::new();
+        for target in [a, a, b] {
+            slots.push(limiter.reserve(target) - start);
+        }
+        // the second probe to `a` waits for its destination alone
+        assert_eq!(slots, [0, 100, 10].map(Duration::from_millis));
+        // the others fill the time before it, but keep clear of its slot
+        clock.advance(Duration::from_millis(95));
+        let slots: Vec<Duration> = [c, b].iter().map(|t| limiter.reserve(*t) - start).collect();
+        assert_eq!(slots, [110, 120].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn test_burst() {
+        let clock = Arc::new(VirtualClock::new());
+        let limiter = RateLimiterBuilder {
+            burst: 3,
+            clock: Some(clock.clone()),
+            ..RateLimiterBuilder::new(10.0)
+        }
+        .build();
+        let target: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = clock.now();
+        let slots: Vec<Duration> = (0..5).map(|_| limiter.reserve(target) - start).collect();
+        assert_eq!(slots, [0, 0, 0, 100, 200].map(Duration::from_millis));
+        // idle long enough for a whole burst again
+        clock.advance(Duration::from_secs(1));
+        let slots: Vec<Duration> = (0..4).map(|_| limiter.reserve(target) - start).collect();
+        assert_eq!(slots, [1000, 1000, 1000, 1100].map(Duration::from_millis));
+        // no limits at all
+        let free = RateLimiterBuilder::default().build();
+        let now = Instant::now();
+        assert!(free.reserve(target) - now < Duration::from_millis(100));
+    }
+}
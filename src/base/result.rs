@@ -9,3 +9,66 @@ pub struct PingV6Result {
     pub ip: std::net::Ipv6Addr,
     pub duration: std::time::Duration,
 }
+
+// echo reply of ping_seq, size is the ICMP message length as ping(8) prints it,
+// ttl is None when the socket didn't tell
+#[derive(Debug, Clone, PartialEq)]
+pub struct EchoV4Reply {
+    pub ip: std::net::Ipv4Addr,
+    pub duration: std::time::Duration,
+    pub sequence: u16,
+    pub ttl: Option<u8>,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EchoV6Reply {
+    pub ip: std::net::Ipv6Addr,
+    pub duration: std::time::Duration,
+    pub sequence: u16,
+    pub ttl: Option<u8>,
+    pub size: usize,
+}
+
+// what ping_all got, the first reply of every responder in the order they came and the
+// responders whose reply had a wrong checksum
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EchoV4Replies {
+    pub replies: Vec<EchoV4Reply>,
+    pub corrupted: Vec<std::net::Ipv4Addr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EchoV6Replies {
+    pub replies: Vec<EchoV6Reply>,
+    pub corrupted: Vec<std::net::Ipv6Addr>,
+}
+
+// reply accepted by the predicate of send_icmp, data is the ICMP message without IP header
+#[derive(Debug)]
+pub struct IcmpV4Reply {
+    pub ip: std::net::Ipv4Addr,
+    pub duration: std::time::Duration,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct IcmpV6Reply {
+    pub ip: std::net::Ipv6Addr,
+    pub duration: std::time::Duration,
+    pub data: Vec<u8>,
+}
+
+impl IcmpV4Reply {
+    #[inline]
+    pub fn get_format(&self) -> crate::protocol::IcmpFormat<'_> {
+        crate::protocol::IcmpFormat::from_slice(&self.data).unwrap()
+    }
+}
+
+impl IcmpV6Reply {
+    #[inline]
+    pub fn get_format(&self) -> crate::protocol::IcmpFormat<'_> {
+        crate::protocol::IcmpFormat::from_slice(&self.data).unwrap()
+    }
+}
@@ -0,0 +1,534 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// z of a two sided 95% interval, for Loss::wilson
+pub const Z_95: f64 = 1.959_963_984_540_054;
+
+// RTTs in nanoseconds, HdrHistogram style: exact below 2^bits, above that every power of two
+// is split into 2^bits buckets, so a value is off by less than 1 / 2^bits of itself.
+// Counters are only allocated up to the largest value recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bits: u32,
+    counts: Vec<u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+    sum_squares: f64, // in ms², like ping(8) does it
+}
+
+impl Default for Histogram {
+    #[inline]
+    fn default() -> Self {
+        Histogram::new(2)
+    }
+}
+
+impl Histogram {
+    // `digits` significant decimal digits are kept, 1 to 5
+    pub fn new(digits: u8) -> Self {
+        let digits = digits.clamp(1, 5);
+        Histogram {
+            bits: 10_f64.powi(digits.into()).log2().ceil() as u32,
+            counts: Vec::new(),
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+            sum_squares: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, rtt: Duration) {
+        self.record_n(rtt, 1);
+    }
+
+    // `n` replies of `rtt`
+    pub fn record_n(&mut self, rtt: Duration, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let value = u64::try_from(rtt.as_nanos()).unwrap_or(u64::MAX);
+        let index = self.index(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += n;
+        self.count += n;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += u128::from(value) * u128::from(n);
+        let ms = value as f64 / 1e6;
+        self.sum_squares += ms * ms * n as f64;
+    }
+
+    // the replies of `other` too, e.g. of another thread or session,
+    // with a different precision they are recorded again at this one
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        if other.bits == self.bits {
+            if other.counts.len() > self.counts.len() {
+                self.counts.resize(other.counts.len(), 0);
+            }
+            for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+                *count += other;
+            }
+        } else {
+            for (index, count) in other.counts.iter().enumerate() {
+                if *count > 0 {
+                    let value = other.lowest(index).clamp(other.min, other.max);
+                    let index = self.index(value);
+                    if index >= self.counts.len() {
+                        self.counts.resize(index + 1, 0);
+                    }
+                    self.counts[index] += count;
+                }
+            }
+        }
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+    }
+
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn min(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_nanos(self.min))
+    }
+
+    #[inline]
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_nanos(self.max))
+    }
+
+    #[inline]
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_nanos((self.sum / u128::from(self.count)) as u64))
+    }
+
+    // the sample standard deviation, None below two replies
+    pub fn stddev(&self) -> Option<Duration> {
+        if self.count < 2 {
+            return None;
+        }
+        let n = self.count as f64;
+        let mean = self.sum as f64 / 1e6 / n;
+        let variance = (self.sum_squares - n * mean * mean) / (n - 1.0);
+        Some(Duration::from_secs_f64(variance.max(0.0).sqrt() / 1000.0))
+    }
+
+    // what ping(8) prints as mdev, sqrt(mean of squares - square of mean)
+    pub fn mdev(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as f64;
+        let mean = self.sum as f64 / 1e6 / n;
+        let variance = self.sum_squares / n - mean * mean;
+        Some(Duration::from_secs_f64(variance.max(0.0).sqrt() / 1000.0))
+    }
+
+    // the RTT `percentile` (0 to 100) of the replies are at or below,
+    // the largest value of its bucket but never above the largest recorded, 0 is the smallest
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 || percentile <= 0.0 {
+            return self.min();
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as u64)
+            .clamp(1, self.count);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let value = self.highest(index).clamp(self.min, self.max);
+                return Some(Duration::from_nanos(value));
+            }
+        }
+        self.max()
+    }
+
+    #[inline]
+    pub fn percentiles(&self) -> Option<Percentiles> {
+        Some(Percentiles {
+            p50: self.percentile(50.0)?,
+            p90: self.percentile(90.0)?,
+            p99: self.percentile(99.0)?,
+            p99_9: self.percentile(99.9)?,
+        })
+    }
+
+    // RFC 5481 packet delay variation, the `percentile` RTT above the smallest one, e.g. 99.9
+    #[inline]
+    pub fn pdv(&self, percentile: f64) -> Option<Duration> {
+        Some(self.percentile(percentile)? - self.min()?)
+    }
+
+    fn index(&self, value: u64) -> usize {
+        let buckets = 1_u64 << self.bits;
+        if value < buckets {
+            return value as usize;
+        }
+        let shift = 63 - value.leading_zeros() - self.bits;
+        let mantissa = (value >> shift) - buckets;
+        ((u64::from(shift) + 1) * buckets + mantissa) as usize
+    }
+
+    // the smallest value of the bucket
+    fn lowest(&self, index: usize) -> u64 {
+        let buckets = 1_usize << self.bits;
+        if index < buckets {
+            return index as u64;
+        }
+        let shift = index / buckets - 1;
+        ((buckets + index % buckets) as u64) << shift
+    }
+
+    fn highest(&self, index: usize) -> u64 {
+        let buckets = 1_usize << self.bits;
+        let width = match index < buckets {
+            true => 1,
+            false => 1_u64 << (index / buckets - 1),
+        };
+        self.lowest(index).saturating_add(width - 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p99_9: Duration,
+}
+
+// RFC 3550 interarrival jitter and RFC 5481 IPDV of a session, probe after probe.
+// The transit time of a probe is received - sent, its RTT, the unknown clock offset
+// of a one way delay doesn't matter to the differences.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DelayVariation {
+    replies: u64,
+    jitter: f64,           // seconds
+    last: f64,             // transit of the last reply
+    consecutive: bool,     // the last probe got it
+    ipdv: (u64, f64, f64), // pairs of consecutive replies, sum and largest of |IPDV|
+}
+
+impl DelayVariation {
+    // the next probe in sending order, None when it got no reply
+    pub fn record(&mut self, rtt: Option<Duration>) {
+        let Some(rtt) = rtt else {
+            self.consecutive = false;
+            return;
+        };
+        let transit = rtt.as_secs_f64();
+        if self.replies > 0 {
+            // J(i) = J(i-1) + (|D(i-1,i)| - J(i-1)) / 16
+            let difference = (transit - self.last).abs();
+            self.jitter += (difference - self.jitter) / 16.0;
+            // IPDV is only defined between probes that follow each other
+            if self.consecutive {
+                let (count, sum, max) = &mut self.ipdv;
+                *count += 1;
+                *sum += difference;
+                *max = max.max(difference);
+            }
+        }
+        self.replies += 1;
+        self.last = transit;
+        self.consecutive = true;
+    }
+
+    // the RFC 3550 estimate, None before two replies
+    #[inline]
+    pub fn jitter(&self) -> Option<Duration> {
+        (self.replies > 1).then(|| Duration::from_secs_f64(self.jitter))
+    }
+
+    // mean and largest |IPDV| of consecutive replies, None without such a pair
+    #[inline]
+    pub fn ipdv(&self) -> Option<(Duration, Duration)> {
+        let (count, sum, max) = self.ipdv;
+        (count > 0).then(|| {
+            (
+                Duration::from_secs_f64(sum / count as f64),
+                Duration::from_secs_f64(max),
+            )
+        })
+    }
+}
+
+// echo requests and the ones answered, duplicates aside
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Loss {
+    pub sent: u64,
+    pub received: u64,
+}
+
+impl Loss {
+    #[inline]
+    pub fn percent(&self) -> Option<f64> {
+        (self.sent > 0).then(|| (self.sent - self.received) as f64 * 100.0 / self.sent as f64)
+    }
+
+    // the Wilson score interval of the loss in percent, e.g. with Z_95,
+    // unlike the normal approximation it stays within 0..100 and works for 0 and 100% loss
+    pub fn wilson(&self, z: f64) -> Option<(f64, f64)> {
+        if self.sent == 0 {
+            return None;
+        }
+        let n = self.sent as f64;
+        let p = (self.sent - self.received) as f64 / n;
+        let z2 = z * z;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        Some((
+            ((center - margin) * 100.0).max(0.0),
+            ((center + margin) * 100.0).min(100.0),
+        ))
+    }
+
+    #[inline]
+    pub fn merge(&mut self, other: &Loss) {
+        self.sent += other.sent;
+        self.received += other.received;
+    }
+}
+
+// what is known about one target
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetStatistics {
+    pub histogram: Histogram,
+    pub loss: Loss,
+}
+
+impl TargetStatistics {
+    // one probe, None when it got no reply
+    pub fn record(&mut self, rtt: Option<Duration>) {
+        self.loss.sent += 1;
+        if let Some(rtt) = rtt {
+            self.loss.received += 1;
+            self.histogram.record(rtt);
+        }
+    }
+
+    #[inline]
+    pub fn merge(&mut self, other: &TargetStatistics) {
+        self.histogram.merge(&other.histogram);
+        self.loss.merge(&other.loss);
+    }
+}
+
+// the statistics of many targets, shared by the threads probing them
+#[derive(Debug)]
+pub struct Statistics {
+    digits: u8,
+    targets: Mutex<BTreeMap<IpAddr, TargetStatistics>>,
+}
+
+impl Default for Statistics {
+    #[inline]
+    fn default() -> Self {
+        Statistics::new(2)
+    }
+}
+
+impl Statistics {
+    // `digits` of the histograms, see Histogram::new
+    #[inline]
+    pub fn new(digits: u8) -> Self {
+        Statistics {
+            digits,
+            targets: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // one probe of `target`, None when it got no reply
+    pub fn record(&self, target: IpAddr, rtt: Option<Duration>) {
+        self.targets
+            .lock()
+            .unwrap()
+            .entry(target)
+            .or_insert_with(|| self.empty())
+            .record(rtt);
+    }
+
+    // adds the statistics of `other`, e.g. of an earlier session
+    pub fn merge(&self, other: &Statistics) {
+        let other = other.snapshot();
+        let mut targets = self.targets.lock().unwrap();
+        for (target, statistics) in &other {
+            targets
+                .entry(*target)
+                .or_insert_with(|| self.empty())
+                .merge(statistics);
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, target: IpAddr) -> Option<TargetStatistics> {
+        self.targets.lock().unwrap().get(&target).cloned()
+    }
+
+    // a copy of every target so far, in address order
+    #[inline]
+    pub fn snapshot(&self) -> BTreeMap<IpAddr, TargetStatistics> {
+        self.targets.lock().unwrap().clone()
+    }
+
+    #[inline]
+    fn empty(&self) -> TargetStatistics {
+        TargetStatistics {
+            histogram: Histogram::new(self.digits),
+            loss: Loss::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        for ms in 1..=1000 {
+            histogram.record(Duration::from_micros(ms * 100));
+        }
+        assert_eq!(histogram.count(), 1000);
+        assert_eq!(histogram.min(), Some(Duration::from_micros(100)));
+        assert_eq!(histogram.max(), Some(Duration::from_millis(100)));
+        assert_eq!(histogram.mean(), Some(Duration::from_micros(50_050)));
+        let percentiles = histogram.percentiles().unwrap();
+        for (value, exact) in [
+            (percentiles.p50, 50_000.0),
+            (percentiles.p90, 90_000.0),
+            (percentiles.p99, 99_000.0),
+            (percentiles.p99_9, 99_900.0),
+        ] {
+            let error = (value.as_secs_f64() * 1e6 - exact).abs() / exact;
+            assert!(error < 0.01, "{value:?} {exact}");
+        }
+        assert_eq!(histogram.percentile(100.0), histogram.max());
+        assert_eq!(histogram.percentile(0.0), histogram.min());
+        // 100µs steps from 100µs to 100ms
+        let stddev = histogram.stddev().unwrap().as_secs_f64() * 1e6;
+        assert!((stddev - 28_881.9).abs() < 1.0, "{stddev}");
+        let mdev = histogram.mdev().unwrap().as_secs_f64() * 1e6;
+        assert!((mdev - 28_867.5).abs() < 1.0, "{mdev}");
+        assert_eq!(Histogram::default().percentiles(), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        // a has another precision than b
+        let (mut a, mut b, mut all) = (Histogram::default(), Histogram::new(3), Histogram::new(3));
+        for us in [120, 340, 5600] {
+            a.record(Duration::from_micros(us));
+            all.record(Duration::from_micros(us));
+        }
+        for us in [80, 90_000] {
+            b.record(Duration::from_micros(us));
+            all.record(Duration::from_micros(us));
+        }
+        let mut merged = b.clone();
+        merged.merge(&a);
+        assert_eq!(merged.count(), 5);
+        assert_eq!(merged.mean(), all.mean());
+        assert_eq!(merged.min(), Some(Duration::from_micros(80)));
+        assert_eq!(merged.max(), Some(Duration::from_micros(90_000)));
+        let (p50, exact) = (merged.percentile(50.0).unwrap(), Duration::from_micros(340));
+        assert!(p50.abs_diff(exact) < Duration::from_micros(4), "{p50:?}");
+    }
+
+    #[test]
+    fn test_delay_variation() {
+        let mut variation = DelayVariation::default();
+        variation.record(Some(Duration::from_millis(10)));
+        assert_eq!((variation.jitter(), variation.ipdv()), (None, None));
+        variation.record(None);
+        variation.record(Some(Duration::from_millis(26)));
+        // 16ms apart, but not consecutive
+        assert_eq!(variation.jitter(), Some(Duration::from_millis(1)));
+        assert_eq!(variation.ipdv(), None);
+        variation.record(Some(Duration::from_millis(10)));
+        variation.record(Some(Duration::from_millis(14)));
+        let jitter = variation.jitter().unwrap().as_secs_f64() * 1000.0;
+        // 1 + 15/16, then + (4 - 1.9375) / 16
+        assert!((jitter - 2.066_406_25).abs() < 1e-6, "{jitter}");
+        let (mean, max) = variation.ipdv().unwrap();
+        assert_eq!(mean, Duration::from_millis(10));
+        assert_eq!(max, Duration::from_millis(16));
+
+        let mut histogram = Histogram::default();
+        for ms in [10, 12, 30] {
+            histogram.record(Duration::from_millis(ms));
+        }
+        let pdv = histogram.pdv(99.9).unwrap();
+        assert!(
+            pdv.abs_diff(Duration::from_millis(20)) < Duration::from_micros(200),
+            "{pdv:?}"
+        );
+    }
+
+    #[test]
+    fn test_wilson() {
+        let loss = Loss {
+            sent: 100,
+            received: 90,
+        };
+        assert_eq!(loss.percent(), Some(10.0));
+        let (low, high) = loss.wilson(Z_95).unwrap();
+        assert!(
+            (low - 5.52).abs() < 0.01 && (high - 17.44).abs() < 0.01,
+            "{low} {high}"
+        );
+        let (low, high) = Loss {
+            sent: 10,
+            received: 10,
+        }
+        .wilson(Z_95)
+        .unwrap();
+        assert_eq!(low, 0.0);
+        assert!((high - 27.75).abs() < 0.01, "{high}");
+        assert_eq!(Loss::default().wilson(Z_95), None);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let target: IpAddr = "192.0.2.1".parse().unwrap();
+        let statistics = Statistics::new(2);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for i in 0..10 {
+                        statistics.record(target, (i % 5 != 0).then_some(Duration::from_millis(i)));
+                    }
+                });
+            }
+        });
+        let earlier = Statistics::new(2);
+        earlier.record(target, None);
+        statistics.merge(&earlier);
+        let target = statistics.get(target).unwrap();
+        assert_eq!(
+            target.loss,
+            Loss {
+                sent: 41,
+                received: 32
+            }
+        );
+        assert_eq!(target.histogram.count(), 32);
+    }
+}
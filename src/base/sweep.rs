@@ -0,0 +1,325 @@
+use crate::base::error::{PingError, SharedError};
+use crate::base::linux::{Answer, ExtendedError, LinuxError};
+use crate::base::rate::{RateLimiter, RateLimiterBuilder};
+use crate::base::{PingV4, PingV4Builder, PingV6, PingV6Builder};
+use std::io::{self, BufRead};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::Duration;
+
+// the most addresses a single CIDR block or range expands to
+pub const MAX_EXPANSION: u128 = 1 << 16;
+
+// one target spec: an address, a CIDR block ("10.0.0.0/24", "2001:db8::/120")
+// or a range ("10.0.0.1-50", "2001:db8::1-ff", "10.0.0.1-10.0.0.9"),
+// IPv4 blocks up to /30 skip their network and broadcast address
+pub fn expand(spec: &str) -> io::Result<Vec<IpAddr>> {
+    let invalid =
+        |reason: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{spec}: {reason}"));
+    let spec = spec.trim();
+    let (first, last) = if let Some((addr, prefix)) = spec.split_once('/') {
+        let addr: IpAddr = addr.parse().map_err(|_| invalid("invalid address"))?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix: u32 = match prefix.parse() {
+            Ok(prefix) if prefix <= bits => prefix,
+            _ => return Err(invalid("invalid prefix length")),
+        };
+        let host_bits = bits - prefix;
+        let mask = u128::MAX.checked_shl(host_bits).unwrap_or(0);
+        let first = to_u128(addr) & mask;
+        let last = first | !mask & (u128::MAX >> (128 - bits));
+        let (first, last) = match addr {
+            IpAddr::V4(_) if host_bits >= 2 => (first + 1, last - 1),
+            _ => (first, last),
+        };
+        (from_u128(addr, first), from_u128(addr, last))
+    } else if let Some((addr, end)) = spec.split_once('-') {
+        let first: IpAddr = addr.parse().map_err(|_| invalid("invalid address"))?;
+        let last = match (first, end.parse::<IpAddr>()) {
+            (_, Ok(last)) if last.is_ipv4() == first.is_ipv4() => last,
+            (_, Ok(_)) => return Err(invalid("mixed address families")),
+            // only the last octet or group
+            (IpAddr::V4(first), Err(_)) => {
+                let octet: u8 = end.parse().map_err(|_| invalid("invalid range end"))?;
+                let mut octets = first.octets();
+                octets[3] = octet;
+                IpAddr::V4(octets.into())
+            }
+            (IpAddr::V6(first), Err(_)) => {
+                let group =
+                    u16::from_str_radix(end, 16).map_err(|_| invalid("invalid range end"))?;
+                let mut segments = first.segments();
+                segments[7] = group;
+                IpAddr::V6(segments.into())
+            }
+        };
+        (first, last)
+    } else {
+        let addr: IpAddr = spec.parse().map_err(|_| invalid("invalid address"))?;
+        (addr, addr)
+    };
+    let (start, end) = (to_u128(first), to_u128(last));
+    if start > end {
+        return Err(invalid("empty range"));
+    }
+    if end - start >= MAX_EXPANSION {
+        return Err(invalid("too many addresses"));
+    }
+    Ok((start..=end).map(|addr| from_u128(first, addr)).collect())
+}
+
+// one spec per line, empty lines and "#" comments are skipped, e.g. a file or stdin
+pub fn read_targets<R: BufRead>(reader: R) -> io::Result<Vec<IpAddr>> {
+    let mut targets = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let spec = line.split('#').next().unwrap_or_default().trim();
+        if !spec.is_empty() {
+            targets.extend(expand(spec)?);
+        }
+    }
+    Ok(targets)
+}
+
+#[inline]
+fn to_u128(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(addr).into(),
+        IpAddr::V6(addr) => addr.into(),
+    }
+}
+
+// in the family of `like`
+#[inline]
+fn from_u128(like: IpAddr, value: u128) -> IpAddr {
+    match like {
+        IpAddr::V4(_) => Ipv4Addr::from(value as u32).into(),
+        IpAddr::V6(_) => Ipv6Addr::from(value).into(),
+    }
+}
+
+pub struct SweepBuilder {
+    pub v4: PingV4Builder, // timeout, payload, interface... of the IPv4 targets
+    pub v6: PingV6Builder,
+    pub concurrency: usize, // targets probed at the same time
+    pub rate: Option<u32>,  // packets per second over all targets, None for no limit
+    pub attempts: u8,       // probes of a target until it answers
+}
+
+impl Default for SweepBuilder {
+    fn default() -> Self {
+        SweepBuilder {
+            v4: PingV4Builder::default(),
+            v6: PingV6Builder::default(),
+            concurrency: 64,
+            rate: None,
+            attempts: 1,
+        }
+    }
+}
+
+impl SweepBuilder {
+    #[inline]
+    pub fn new(timeout: Duration) -> Self {
+        SweepBuilder {
+            v4: PingV4Builder::new(timeout),
+            v6: PingV6Builder::new(timeout),
+            ..Default::default()
+        }
+    }
+
+    // probes are paced on the clock of the IPv4 builder, on top of the `rate_limiter` of
+    // the builders when several sweeps share one
+    pub fn build(self) -> Sweep {
+        let v4 = self.v4.build();
+        let pacer = self.rate.filter(|rate| *rate > 0).map(|rate| {
+            RateLimiterBuilder {
+                clock: Some(v4.clock.clone()),
+                ..RateLimiterBuilder::new(rate as f64)
+            }
+            .build()
+        });
+        Sweep {
+            v4,
+            v6: self.v6.build(),
+            concurrency: self.concurrency.max(1),
+            attempts: self.attempts.max(1),
+            pacer,
+            sequence: AtomicU16::new(1),
+            stopped: AtomicBool::new(false),
+        }
+    }
+}
+
+// probes many targets, fping style
+pub struct Sweep {
+    v4: PingV4,
+    v6: PingV6,
+    concurrency: usize,
+    attempts: u8,
+    pacer: Option<Arc<RateLimiter>>, // evenly spaced send slots shared by all workers
+    sequence: AtomicU16,
+    stopped: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct SweepHost {
+    pub target: IpAddr,
+    pub sent: u8,
+    pub state: HostState,
+}
+
+#[derive(Debug)]
+pub enum HostState {
+    Alive { ip: IpAddr, duration: Duration },
+    // the last probe got an ICMP error, e.g. host unreachable from the gateway
+    IcmpError(ExtendedError),
+    Timeout,
+    // no route to it
+    Unreachable,
+    // the probe couldn't be sent, e.g. no privilege or no route
+    Failed(PingError),
+}
+
+impl SweepHost {
+    #[inline]
+    pub fn is_alive(&self) -> bool {
+        matches!(self.state, HostState::Alive { .. })
+    }
+}
+
+impl Sweep {
+    // `report` is called on this thread as soon as a target is done,
+    // the returned hosts are in the order of `targets`, without the ones skipped after stop()
+    pub fn run<F: FnMut(&SweepHost)>(&self, targets: &[IpAddr], mut report: F) -> Vec<SweepHost> {
+        let mut hosts: Vec<Option<SweepHost>> = Vec::new();
+        hosts.resize_with(targets.len(), || None);
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(targets.len()) {
+                let sender = sender.clone();
+                let next = &next;
+                scope.spawn(move || {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(target) = targets.get(index) else {
+                            break;
+                        };
+                        if self.stopped.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if sender.send((index, self.probe(*target))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            for (index, host) in receiver {
+                report(&host);
+                hosts[index] = Some(host);
+            }
+        });
+        hosts.into_iter().flatten().collect()
+    }
+
+    // the running sweep finishes the targets in flight and starts no more, e.g. from `report`
+    #[inline]
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    // up to `attempts` probes, only timeouts and ICMP errors are tried again
+    pub fn probe(&self, target: IpAddr) -> SweepHost {
+        let mut host = SweepHost {
+            target,
+            sent: 0,
+            state: HostState::Timeout,
+        };
+        while host.sent < self.attempts {
+            if let Some(pacer) = &self.pacer {
+                pacer.acquire(target);
+            }
+            let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+            let answer = match target {
+                IpAddr::V4(target) => self.v4.send_echo(target, Some(sequence), None),
+                IpAddr::V6(target) => self.v6.send_echo(target, Some(sequence), None),
+            };
+            host.sent += 1;
+            host.state = match answer {
+                Ok(Answer::Reply { ip, duration, .. }) => HostState::Alive { ip, duration },
+                Ok(Answer::Error(e, _)) => HostState::IcmpError(e),
+                Err(PingError::SharedError(SharedError::Timeout)) => HostState::Timeout,
+                Err(PingError::SharedError(SharedError::Unreachable)) => HostState::Unreachable,
+                Err(PingError::LinuxError(LinuxError::SendtoFailed(
+                    libc::ENETUNREACH | libc::EHOSTUNREACH,
+                ))) => HostState::Unreachable,
+                Err(e) => HostState::Failed(e),
+            };
+            if !matches!(host.state, HostState::Timeout | HostState::IcmpError(_)) {
+                break;
+            }
+        }
+        host
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(list: &[&str]) -> Vec<IpAddr> {
+        list.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand("10.0.0.0/30").unwrap(),
+            addrs(&["10.0.0.1", "10.0.0.2"])
+        );
+        assert_eq!(
+            expand("10.0.0.8/31").unwrap(),
+            addrs(&["10.0.0.8", "10.0.0.9"])
+        );
+        assert_eq!(expand("10.0.0.5/24").unwrap().len(), 254);
+        assert_eq!(expand(" 192.0.2.1 ").unwrap(), addrs(&["192.0.2.1"]));
+        assert_eq!(
+            expand("2001:db8::/126").unwrap(),
+            addrs(&["2001:db8::", "2001:db8::1", "2001:db8::2", "2001:db8::3"])
+        );
+        assert_eq!(expand("2001:db8::/120").unwrap().len(), 256);
+        assert_eq!(
+            expand("10.0.0.254-10.0.1.1").unwrap(),
+            addrs(&["10.0.0.254", "10.0.0.255", "10.0.1.0", "10.0.1.1"])
+        );
+        assert_eq!(expand("10.0.0.1-50").unwrap().len(), 50);
+        assert_eq!(
+            expand("2001:db8::e-10").unwrap(),
+            addrs(&["2001:db8::e", "2001:db8::f", "2001:db8::10"])
+        );
+        for spec in [
+            "10.0.0.0/33",
+            "10.0.0.0/8",
+            "2001:db8::/64",
+            "10.0.0.9-1",
+            "10.0.0.1-256",
+            "10.0.0.1-::1",
+            "example.com",
+        ] {
+            assert!(expand(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn test_read_targets() {
+        let text = "# inventory\n192.0.2.1\n\n10.0.0.0/30 # lab\n";
+        assert_eq!(
+            read_targets(text.as_bytes()).unwrap(),
+            addrs(&["192.0.2.1", "10.0.0.1", "10.0.0.2"])
+        );
+        assert!(read_targets("10.0.0.1\nnope\n".as_bytes()).is_err());
+    }
+}
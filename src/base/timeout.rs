@@ -0,0 +1,35 @@
+use crate::base::error::{PingError, SharedError};
+use std::time::{Duration, Instant};
+
+// `now` comes from the clock of the pinger, Instant::now() on Windows
+
+// the session deadline (-w) starts counting when the pinger is built
+#[inline]
+pub(crate) fn session_end(deadline: Option<Duration>, now: Instant) -> Option<Instant> {
+    deadline.map(|deadline| now + deadline)
+}
+
+// the per-reply wait (-W), cut short by the session deadline
+#[inline]
+pub(crate) fn reply_until(
+    timeout: Duration,
+    session_end: Option<Instant>,
+    now: Instant,
+) -> Result<Instant, PingError> {
+    let until = now + timeout;
+    match session_end {
+        None => Ok(until),
+        Some(end) if end <= now => Err(SharedError::Timeout.into()),
+        Some(end) => Ok(until.min(end)),
+    }
+}
+
+// time left before `until`, used when a receive has to be retried
+#[inline]
+pub(crate) fn remaining(until: Instant, now: Instant) -> Result<Duration, PingError> {
+    let remaining = until.saturating_duration_since(now);
+    if remaining.is_zero() {
+        return Err(SharedError::Timeout.into());
+    }
+    Ok(remaining)
+}
@@ -0,0 +1,159 @@
+use crate::base::error::{PingError, SharedError};
+use crate::base::linux::{Answer, ExtendedError, LinuxError};
+use crate::base::{PingV4, PingV4Builder, PingV6, PingV6Builder};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+// echo requests with growing TTLs towards one target, the options come from the builder,
+// except its ttl, timeout is the wait of each probe
+pub struct Traceroute {
+    ping: Ping,
+    target: IpAddr,
+    max_hops: u8,
+    sequence: AtomicU16,
+}
+
+enum Ping {
+    V4(PingV4),
+    V6(PingV6),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopProbe {
+    pub ttl: u8,
+    pub sequence: u16,
+    pub kind: HopKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HopKind {
+    // the echo reply of the target, the route ends here
+    Reached { ip: IpAddr, duration: Duration },
+    TimeExceeded { ip: IpAddr, duration: Duration },
+    // any other ICMP error quoting the probe, e.g. unreachable, the route ends here too
+    Error(ExtendedError, Duration),
+    // no route for the probe and nobody to blame, e.g. ENETUNREACH, the route ends here too
+    Unreachable,
+    // an answer with a wrong checksum, lost like a timeout
+    BadChecksum,
+    Timeout,
+}
+
+impl HopProbe {
+    // who answered, None for a timeout or a local error
+    #[inline]
+    pub fn ip(&self) -> Option<IpAddr> {
+        match &self.kind {
+            HopKind::Reached { ip, .. } | HopKind::TimeExceeded { ip, .. } => Some(*ip),
+            HopKind::Error(e, _) => e.offender,
+            HopKind::Unreachable | HopKind::BadChecksum | HopKind::Timeout => None,
+        }
+    }
+
+    #[inline]
+    pub fn duration(&self) -> Option<Duration> {
+        match &self.kind {
+            HopKind::Reached { duration, .. }
+            | HopKind::TimeExceeded { duration, .. }
+            | HopKind::Error(_, duration) => Some(*duration),
+            HopKind::Unreachable | HopKind::BadChecksum | HopKind::Timeout => None,
+        }
+    }
+
+    // no hop behind this one will answer
+    #[inline]
+    pub fn is_last(&self) -> bool {
+        matches!(
+            self.kind,
+            HopKind::Reached { .. } | HopKind::Error(..) | HopKind::Unreachable
+        )
+    }
+}
+
+impl Traceroute {
+    #[inline]
+    pub fn v4(builder: PingV4Builder, target: Ipv4Addr) -> Self {
+        Self::new(Ping::V4(builder.build()), target.into())
+    }
+
+    #[inline]
+    pub fn v6(builder: PingV6Builder, target: Ipv6Addr) -> Self {
+        Self::new(Ping::V6(builder.build()), target.into())
+    }
+
+    fn new(ping: Ping, target: IpAddr) -> Self {
+        Traceroute {
+            ping,
+            target,
+            max_hops: 30,
+            sequence: AtomicU16::new(1),
+        }
+    }
+
+    // 30 by default
+    #[inline]
+    pub fn with_max_hops(mut self, max_hops: u8) -> Self {
+        self.max_hops = max_hops.max(1);
+        self
+    }
+
+    #[inline]
+    pub fn target(&self) -> IpAddr {
+        self.target
+    }
+
+    #[inline]
+    pub fn max_hops(&self) -> u8 {
+        self.max_hops
+    }
+
+    // one echo request with `ttl`, a hop that doesn't answer is HopKind::Timeout,
+    // setup errors (no privilege, bind failed, ...) are returned
+    pub fn probe(&self, ttl: u8) -> Result<HopProbe, PingError> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let answer = match (&self.ping, self.target) {
+            (Ping::V4(ping), IpAddr::V4(target)) => {
+                ping.send_echo(target, Some(sequence), Some(ttl))
+            }
+            (Ping::V6(ping), IpAddr::V6(target)) => {
+                ping.send_echo(target, Some(sequence), Some(ttl))
+            }
+            _ => unreachable!(),
+        };
+        let kind = match answer {
+            Ok(Answer::Reply { ip, duration, .. }) => HopKind::Reached { ip, duration },
+            Ok(Answer::Error(e, duration)) if e.is_time_exceeded() => match e.offender {
+                Some(ip) => HopKind::TimeExceeded { ip, duration },
+                None => HopKind::Error(e, duration),
+            },
+            Ok(Answer::Error(e, duration)) => HopKind::Error(e, duration),
+            Err(PingError::SharedError(SharedError::Timeout)) => HopKind::Timeout,
+            Err(PingError::SharedError(SharedError::BadChecksum)) => HopKind::BadChecksum,
+            Err(PingError::SharedError(SharedError::Unreachable))
+            | Err(PingError::LinuxError(LinuxError::SendtoFailed(
+                libc::ENETUNREACH | libc::EHOSTUNREACH,
+            ))) => HopKind::Unreachable,
+            Err(e) => return Err(e),
+        };
+        Ok(HopProbe {
+            ttl,
+            sequence,
+            kind,
+        })
+    }
+
+    // probes TTL 1, 2, ... one at a time, until the target or an error answers or max_hops
+    pub fn round(&self) -> Result<Vec<HopProbe>, PingError> {
+        let mut probes = Vec::new();
+        for ttl in 1..=self.max_hops {
+            let probe = self.probe(ttl)?;
+            let last = probe.is_last();
+            probes.push(probe);
+            if last {
+                break;
+            }
+        }
+        Ok(probes)
+    }
+}
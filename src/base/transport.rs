@@ -0,0 +1,113 @@
+use crate::base::error::PingError;
+use crate::base::linux::{ExtendedError, LinuxError};
+use crate::base::protocol::Ipv4Header;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+mod mock;
+mod replay;
+mod simulator;
+mod socket;
+
+pub use mock::{MockReply, MockTransport};
+pub use replay::{Recording, RecordingTransport, ReplayTransport};
+pub use simulator::{Hop, IcmpRateLimit, Latency, Link, SimulatedNetwork};
+pub use socket::SocketTransport;
+
+// opens a channel for every ping, set it as `transport` of the builder,
+// SocketTransport (raw or DGRAM_SOCKET sockets) is used when it is None
+pub trait Transport: Send + Sync {
+    fn open(&self, config: &ChannelConfig) -> Result<Box<dyn Channel>, PingError>;
+}
+
+// what one ping sends and receives through, e.g. a socket
+pub trait Channel: Send {
+    // returns when the data left, the ping duration is counted from it
+    fn send_to(&mut self, data: &[u8], target: IpAddr) -> Result<Instant, PingError>;
+
+    // waits at most `timeout` for the next ICMP message or queued ICMP error,
+    // SharedError::Timeout when nothing came
+    fn recv(&mut self, buff: &mut [u8], timeout: Duration) -> Result<Received, PingError>;
+
+    // recv before the IPv4 header of raw sockets is stripped, true when the buff starts with one,
+    // for the recorder
+    #[inline]
+    fn recv_raw(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(Received, bool), PingError> {
+        self.recv(buff, timeout).map(|received| (received, false))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelConfig {
+    pub family: Family,
+    pub ttl: Option<u8>,
+    pub bind_addr: Option<IpAddr>,
+    pub scope_id: u32,
+    // SO_BROADCAST, IPv4 only
+    pub broadcast: bool,
+    #[cfg(target_os = "linux")]
+    pub netns: Option<crate::base::netns::Netns>,
+    #[cfg(target_os = "linux")]
+    pub interface: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Received {
+    // bytes written to the buff, the ICMP message without IP header
+    pub len: usize,
+    // the buff was too small, the checksum can't be verified
+    pub truncated: bool,
+    pub source: Option<IpAddr>,
+    // our own address, needed for the ICMPv6 checksum
+    pub destination: Option<IpAddr>,
+    // TTL or hop limit of the reply, None when the channel can't tell
+    pub ttl: Option<u8>,
+    pub timestamp: Instant,
+    pub kind: ReceivedKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum ReceivedKind {
+    // sent to us by `source`
+    Icmp,
+    // queued by the kernel for one of our sends (IP_RECVERR), the buff holds the ICMP message we sent
+    Error(ExtendedError),
+}
+
+// reads the IPv4 header recv_raw left in front of the ICMP message into `received`,
+// returns its length, `received.len` is then the length of the ICMP message behind it
+pub(crate) fn split_ipv4_header(buff: &[u8], received: &mut Received) -> Result<usize, PingError> {
+    let header =
+        Ipv4Header::from_slice(&buff[..received.len]).ok_or(LinuxError::ResolveRecvFailed)?;
+    received.destination = Some(IpAddr::V4(header.get_destination_address()));
+    received.ttl = Some(header.get_ttl());
+    let header_length = header.get_header_length() as usize;
+    received.len -= header_length;
+    Ok(header_length)
+}
+
+// the unspecified address of the family of `target`, for addresses a channel couldn't tell
+#[inline]
+pub(crate) fn unspecified(target: IpAddr) -> IpAddr {
+    match target {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    }
+}
+
+// turns what recv_raw gave with an IPv4 header into what recv gives
+pub(crate) fn strip_ipv4_header(buff: &mut [u8], received: &mut Received) -> Result<(), PingError> {
+    let header_length = split_ipv4_header(buff, received)?;
+    buff.copy_within(header_length..header_length + received.len, 0);
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use crate::base::clock::{Clock, SystemClock};
+use crate::base::error::{PingError, SharedError};
+use crate::base::linux::ExtendedError;
+use crate::base::protocol::checksum;
+use crate::base::transport::{
+    Channel, ChannelConfig, Received, ReceivedKind, Transport, strip_ipv4_header,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type Responder = Box<dyn FnMut(&[u8], IpAddr) -> Vec<MockReply> + Send>;
+
+// answers every send with the replies returned by the responder,
+// for tests without CAP_NET_RAW or network access
+#[derive(Clone)]
+pub struct MockTransport {
+    responder: Arc<Mutex<Responder>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MockTransport {
+    // the responder gets the sent ICMP message and its target
+    pub fn new<F>(responder: F) -> Self
+    where
+        F: FnMut(&[u8], IpAddr) -> Vec<MockReply> + Send + 'static,
+    {
+        MockTransport {
+            responder: Arc::new(Mutex::new(Box::new(responder))),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    // replies are waited for on `clock`, give the builder the same one
+    #[inline]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // every target answers echo requests after `delay`
+    pub fn echo(delay: Duration) -> Self {
+        Self::new(move |data, target| vec![MockReply::echo_reply(data, target, delay)])
+    }
+}
+
+impl Transport for MockTransport {
+    fn open(&self, _config: &ChannelConfig) -> Result<Box<dyn Channel>, PingError> {
+        Ok(Box::new(MockChannel {
+            responder: self.responder.clone(),
+            queue: ReplyQueue::new(self.clock.clone()),
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockReply {
+    pub delay: Duration, // counted from the send
+    pub source: IpAddr,
+    pub destination: Option<IpAddr>,
+    // the ICMP message, or for an error the message we sent
+    pub data: Vec<u8>,
+    pub error: Option<ExtendedError>,
+    // data starts with an IPv4 header, as raw sockets deliver it
+    pub ip_header: bool,
+    // data was cut short before it reached us
+    pub truncated: bool,
+}
+
+impl MockReply {
+    #[inline]
+    pub fn icmp(data: Vec<u8>, source: IpAddr, delay: Duration) -> Self {
+        MockReply {
+            delay,
+            source,
+            destination: Some(loopback(source)),
+            data,
+            error: None,
+            ip_header: false,
+            truncated: false,
+        }
+    }
+
+    // the echo reply a host sends back for `request`, with a valid checksum
+    pub fn echo_reply(request: &[u8], source: IpAddr, delay: Duration) -> Self {
+        let mut data = request.to_vec();
+        match source {
+            IpAddr::V4(_) => {
+                let sum = u16::from_be_bytes([data[2], data[3]]);
+                data[0] = 0;
+                let sum = checksum::update(sum, u16::from_be_bytes([request[0], request[1]]), 0);
+                data[2..4].copy_from_slice(&sum.to_be_bytes());
+            }
+            IpAddr::V6(source) => {
+                data[0] = 129;
+                data[2..4].copy_from_slice(&[0, 0]);
+                let sum = checksum::checksum_v6(source, Ipv6Addr::LOCALHOST, &data);
+                data[2..4].copy_from_slice(&sum.to_be_bytes());
+            }
+        }
+        Self::icmp(data, source, delay)
+    }
+
+    // what IP_RECVERR queues when `router` answers `sent` with time exceeded
+    #[inline]
+    pub fn time_exceeded(sent: &[u8], router: IpAddr, delay: Duration) -> Self {
+        let icmp_type = if router.is_ipv4() { 11 } else { 3 };
+        Self::error(sent, router, icmp_type, 0, delay)
+    }
+
+    // same for destination unreachable with `code`
+    #[inline]
+    pub fn unreachable(sent: &[u8], router: IpAddr, code: u8, delay: Duration) -> Self {
+        let icmp_type = if router.is_ipv4() { 3 } else { 1 };
+        Self::error(sent, router, icmp_type, code, delay)
+    }
+
+    // fragmentation needed (IPv4, DF is set) or packet too big (IPv6), `mtu` of the next link
+    #[inline]
+    pub fn packet_too_big(sent: &[u8], router: IpAddr, mtu: u32, delay: Duration) -> Self {
+        let (icmp_type, icmp_code) = if router.is_ipv4() { (3, 4) } else { (2, 0) };
+        let mut reply = Self::error(sent, router, icmp_type, icmp_code, delay);
+        if let Some(error) = &mut reply.error {
+            error.errno = libc::EMSGSIZE as u32;
+            error.info = mtu;
+        }
+        reply
+    }
+
+    fn error(sent: &[u8], router: IpAddr, icmp_type: u8, icmp_code: u8, delay: Duration) -> Self {
+        let origin = match router {
+            IpAddr::V4(_) => libc::SO_EE_ORIGIN_ICMP,
+            IpAddr::V6(_) => libc::SO_EE_ORIGIN_ICMP6,
+        };
+        // port unreachable is the only one the kernel reports as refused
+        let errno = match (router, icmp_type, icmp_code) {
+            (IpAddr::V4(_), 3, 3) | (IpAddr::V6(_), 1, 4) => libc::ECONNREFUSED,
+            _ => libc::EHOSTUNREACH,
+        };
+        MockReply {
+            delay,
+            source: router,
+            destination: None,
+            data: sent.to_vec(),
+            error: Some(ExtendedError {
+                errno: errno as u32,
+                origin,
+                icmp_type,
+                icmp_code,
+                info: 0,
+                offender: Some(router),
+            }),
+            ip_header: false,
+            truncated: false,
+        }
+    }
+}
+
+#[inline]
+fn loopback(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+    }
+}
+
+struct MockChannel {
+    responder: Arc<Mutex<Responder>>,
+    queue: ReplyQueue,
+}
+
+impl Channel for MockChannel {
+    fn send_to(&mut self, data: &[u8], target: IpAddr) -> Result<Instant, PingError> {
+        let sent_at = self.queue.clock.now();
+        let replies = (self.responder.lock().unwrap())(data, target);
+        self.queue.push(sent_at, replies);
+        Ok(sent_at)
+    }
+
+    #[inline]
+    fn recv(&mut self, buff: &mut [u8], timeout: Duration) -> Result<Received, PingError> {
+        self.queue.pop(buff, timeout)
+    }
+
+    #[inline]
+    fn recv_raw(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(Received, bool), PingError> {
+        self.queue.pop_raw(buff, timeout)
+    }
+}
+
+// replies waiting for their time, shared with the simulator
+pub(super) struct ReplyQueue {
+    pub(super) clock: Arc<dyn Clock>,
+    queue: Vec<(Instant, MockReply)>,
+}
+
+impl ReplyQueue {
+    #[inline]
+    pub(super) fn new(clock: Arc<dyn Clock>) -> Self {
+        ReplyQueue {
+            clock,
+            queue: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn push(&mut self, sent_at: Instant, replies: Vec<MockReply>) {
+        self.queue.extend(
+            replies
+                .into_iter()
+                .map(|reply| (sent_at + reply.delay, reply)),
+        );
+    }
+
+    pub(super) fn pop(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<Received, PingError> {
+        let (mut received, ip_header) = self.pop_raw(buff, timeout)?;
+        if ip_header {
+            strip_ipv4_header(buff, &mut received)?;
+        }
+        Ok(received)
+    }
+
+    pub(super) fn pop_raw(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(Received, bool), PingError> {
+        let until = self.clock.now() + timeout;
+        // the first one pushed wins a tie, as on a real link
+        let next = (0..self.queue.len()).min_by_key(|i| (self.queue[*i].0, *i));
+        match next {
+            Some(i) if self.queue[i].0 <= until => {
+                let (due, reply) = self.queue.remove(i);
+                self.clock.sleep_until(due);
+                let len = reply.data.len().min(buff.len());
+                buff[..len].copy_from_slice(&reply.data[..len]);
+                let ip_header = reply.ip_header && reply.error.is_none();
+                let received = Received {
+                    len,
+                    truncated: reply.truncated || len < reply.data.len(),
+                    source: Some(reply.source),
+                    destination: reply.destination,
+                    ttl: None,
+                    timestamp: self.clock.now(),
+                    kind: match reply.error {
+                        Some(extended) => ReceivedKind::Error(extended),
+                        None => ReceivedKind::Icmp,
+                    },
+                };
+                Ok((received, ip_header))
+            }
+            _ => {
+                self.clock.sleep_until(until);
+                Err(SharedError::Timeout.into())
+            }
+        }
+    }
+}
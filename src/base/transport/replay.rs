@@ -0,0 +1,366 @@
+use crate::base::clock::{Clock, SystemClock};
+use crate::base::error::PingError;
+use crate::base::linux::{ExtendedError, LinuxError};
+use crate::base::transport::mock::{MockReply, ReplyQueue};
+use crate::base::transport::{
+    Channel, ChannelConfig, Family, Received, ReceivedKind, Transport, strip_ipv4_header,
+    unspecified,
+};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// what went through the channels of a session, kept as a text file:
+//
+//   open v4
+//   send <target> <hex>
+//   recv <delay ns> icmp <source> <destination|-> <flags|-> <hex>
+//   recv <delay ns> error:<errno>,<origin>,<type>,<code>,<info>,<offender|-> <source> - <flags|-> <hex>
+//
+// flags are ip_header and truncated, failed recvs (timeouts) aren't kept
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    channels: Vec<RecordedChannel>,
+}
+
+#[derive(Debug, Clone)]
+struct RecordedChannel {
+    family: Family,
+    exchanges: Vec<Exchange>,
+}
+
+#[derive(Debug, Clone)]
+struct Exchange {
+    target: IpAddr,
+    data: Vec<u8>,
+    replies: Vec<MockReply>,
+}
+
+// wraps another transport and records everything its channels send and receive,
+// raw IPv4 replies are kept with their IP header
+#[derive(Clone)]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn Transport>) -> Self {
+        RecordingTransport {
+            inner,
+            recording: Arc::new(Mutex::new(Recording::default())),
+        }
+    }
+
+    #[inline]
+    pub fn recording(&self) -> Recording {
+        self.recording.lock().unwrap().clone()
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn open(&self, config: &ChannelConfig) -> Result<Box<dyn Channel>, PingError> {
+        let inner = self.inner.open(config)?;
+        let mut recording = self.recording.lock().unwrap();
+        recording.channels.push(RecordedChannel {
+            family: config.family,
+            exchanges: Vec::new(),
+        });
+        Ok(Box::new(RecordingChannel {
+            inner,
+            recording: self.recording.clone(),
+            index: recording.channels.len() - 1,
+            sent_at: None,
+        }))
+    }
+}
+
+struct RecordingChannel {
+    inner: Box<dyn Channel>,
+    recording: Arc<Mutex<Recording>>,
+    index: usize,
+    sent_at: Option<Instant>,
+}
+
+impl Channel for RecordingChannel {
+    fn send_to(&mut self, data: &[u8], target: IpAddr) -> Result<Instant, PingError> {
+        let sent_at = self.inner.send_to(data, target)?;
+        self.sent_at = Some(sent_at);
+        self.recording.lock().unwrap().channels[self.index]
+            .exchanges
+            .push(Exchange {
+                target,
+                data: data.to_vec(),
+                replies: Vec::new(),
+            });
+        Ok(sent_at)
+    }
+
+    fn recv(&mut self, buff: &mut [u8], timeout: Duration) -> Result<Received, PingError> {
+        let (mut received, ip_header) = self.recv_raw(buff, timeout)?;
+        if ip_header {
+            strip_ipv4_header(buff, &mut received)?;
+        }
+        Ok(received)
+    }
+
+    fn recv_raw(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(Received, bool), PingError> {
+        let (received, ip_header) = self.inner.recv_raw(buff, timeout)?;
+        let mut recording = self.recording.lock().unwrap();
+        if let Some(sent_at) = self.sent_at
+            && let Some(exchange) = recording.channels[self.index].exchanges.last_mut()
+        {
+            exchange.replies.push(MockReply {
+                delay: received.timestamp.saturating_duration_since(sent_at),
+                source: received.source.unwrap_or(unspecified(exchange.target)),
+                destination: received.destination,
+                data: buff[..received.len].to_vec(),
+                error: match &received.kind {
+                    ReceivedKind::Icmp => None,
+                    ReceivedKind::Error(extended) => Some(extended.clone()),
+                },
+                ip_header,
+                truncated: received.truncated,
+            });
+        }
+        Ok((received, ip_header))
+    }
+}
+
+// feeds a recording back, every channel has to send exactly what was recorded,
+// so build the pings with the same `seed` as the recorded session,
+// otherwise the send fails with LinuxError::ReplayMismatch
+#[derive(Clone)]
+pub struct ReplayTransport {
+    channels: Arc<Mutex<VecDeque<RecordedChannel>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ReplayTransport {
+    pub fn new(recording: Recording) -> Self {
+        ReplayTransport {
+            channels: Arc::new(Mutex::new(recording.channels.into())),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    // recorded delays are waited for on `clock`, give the builder the same one
+    #[inline]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn open(&self, config: &ChannelConfig) -> Result<Box<dyn Channel>, PingError> {
+        match self.channels.lock().unwrap().pop_front() {
+            Some(channel) if channel.family == config.family => Ok(Box::new(ReplayChannel {
+                exchanges: channel.exchanges.into(),
+                queue: ReplyQueue::new(self.clock.clone()),
+            })),
+            _ => Err(LinuxError::ReplayMismatch.into()),
+        }
+    }
+}
+
+struct ReplayChannel {
+    exchanges: VecDeque<Exchange>,
+    queue: ReplyQueue,
+}
+
+impl Channel for ReplayChannel {
+    fn send_to(&mut self, data: &[u8], target: IpAddr) -> Result<Instant, PingError> {
+        match self.exchanges.pop_front() {
+            Some(exchange) if exchange.target == target && exchange.data == data => {
+                let sent_at = self.queue.clock.now();
+                self.queue.push(sent_at, exchange.replies);
+                Ok(sent_at)
+            }
+            _ => Err(LinuxError::ReplayMismatch.into()),
+        }
+    }
+
+    #[inline]
+    fn recv(&mut self, buff: &mut [u8], timeout: Duration) -> Result<Received, PingError> {
+        self.queue.pop(buff, timeout)
+    }
+
+    #[inline]
+    fn recv_raw(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(Received, bool), PingError> {
+        self.queue.pop_raw(buff, timeout)
+    }
+}
+
+impl Recording {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        std::fs::read_to_string(path)?.parse()
+    }
+}
+
+impl std::fmt::Display for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for channel in &self.channels {
+            let family = match channel.family {
+                Family::V4 => "v4",
+                Family::V6 => "v6",
+            };
+            writeln!(f, "open {family}")?;
+            for exchange in &channel.exchanges {
+                writeln!(f, "send {} {}", exchange.target, hex(&exchange.data))?;
+                for reply in &exchange.replies {
+                    let kind = match &reply.error {
+                        None => String::from("icmp"),
+                        Some(e) => format!(
+                            "error:{},{},{},{},{},{}",
+                            e.errno,
+                            e.origin,
+                            e.icmp_type,
+                            e.icmp_code,
+                            e.info,
+                            optional(e.offender)
+                        ),
+                    };
+                    let flags = match (reply.ip_header, reply.truncated) {
+                        (false, false) => "-",
+                        (true, false) => "ip_header",
+                        (false, true) => "truncated",
+                        (true, true) => "ip_header,truncated",
+                    };
+                    writeln!(
+                        f,
+                        "recv {} {kind} {} {} {flags} {}",
+                        reply.delay.as_nanos(),
+                        reply.source,
+                        optional(reply.destination),
+                        hex(&reply.data)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Recording {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut recording = Recording::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {line}", number + 1),
+                )
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["open", family] => recording.channels.push(RecordedChannel {
+                    family: match family {
+                        "v4" => Family::V4,
+                        "v6" => Family::V6,
+                        _ => return Err(invalid()),
+                    },
+                    exchanges: Vec::new(),
+                }),
+                ["send", target, data] => {
+                    let channel = recording.channels.last_mut().ok_or_else(invalid)?;
+                    channel.exchanges.push(Exchange {
+                        target: target.parse().map_err(|_| invalid())?,
+                        data: unhex(data).ok_or_else(invalid)?,
+                        replies: Vec::new(),
+                    });
+                }
+                ["recv", delay, kind, source, destination, flags, data] => {
+                    let exchange = recording
+                        .channels
+                        .last_mut()
+                        .and_then(|channel| channel.exchanges.last_mut())
+                        .ok_or_else(invalid)?;
+                    let error = match kind.strip_prefix("error:") {
+                        None if kind == "icmp" => None,
+                        None => return Err(invalid()),
+                        Some(fields) => Some(parse_error(fields).ok_or_else(invalid)?),
+                    };
+                    let flags: Vec<&str> = flags.split(',').collect();
+                    exchange.replies.push(MockReply {
+                        delay: Duration::from_nanos(delay.parse().map_err(|_| invalid())?),
+                        source: source.parse().map_err(|_| invalid())?,
+                        destination: match destination {
+                            "-" => None,
+                            addr => Some(addr.parse().map_err(|_| invalid())?),
+                        },
+                        data: unhex(data).ok_or_else(invalid)?,
+                        error,
+                        ip_header: flags.contains(&"ip_header"),
+                        truncated: flags.contains(&"truncated"),
+                    });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(recording)
+    }
+}
+
+fn parse_error(fields: &str) -> Option<ExtendedError> {
+    let fields: Vec<&str> = fields.split(',').collect();
+    let [errno, origin, icmp_type, icmp_code, info, offender] = fields[..] else {
+        return None;
+    };
+    Some(ExtendedError {
+        errno: errno.parse().ok()?,
+        origin: origin.parse().ok()?,
+        icmp_type: icmp_type.parse().ok()?,
+        icmp_code: icmp_code.parse().ok()?,
+        info: info.parse().ok()?,
+        offender: match offender {
+            "-" => None,
+            addr => Some(addr.parse().ok()?),
+        },
+    })
+}
+
+#[inline]
+fn optional(addr: Option<IpAddr>) -> String {
+    addr.map_or(String::from("-"), |addr| addr.to_string())
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len() * 2);
+    for byte in data {
+        let _ = write!(text, "{byte:02x}");
+    }
+    text
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
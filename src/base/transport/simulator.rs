@@ -0,0 +1,322 @@
+use crate::base::clock::{Clock, VirtualClock};
+use crate::base::error::{PingError, SharedError};
+use crate::base::linux::LinuxError;
+use crate::base::transport::mock::{MockReply, ReplyQueue};
+use crate::base::transport::{Channel, ChannelConfig, Received, Transport};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// routes of hops joined by lossy links, on a virtual clock,
+// the same seed always gives the same losses, latencies and corruptions
+//
+// give the builder this transport and `clock()`, every ping then takes no real time
+#[derive(Clone)]
+pub struct SimulatedNetwork {
+    state: Arc<Mutex<State>>,
+    clock: Arc<VirtualClock>,
+}
+
+struct State {
+    rng: ChaCha8Rng,
+    routes: Vec<Vec<HopState>>,
+}
+
+struct HopState {
+    hop: Hop,
+    // ICMP rate limiting token bucket
+    tokens: u32,
+    refilled_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hop {
+    pub addr: IpAddr,
+    pub link: Link, // from the previous hop, or from us for the first one
+    // false for a router that never sends ICMP errors, or a host that ignores pings
+    pub responds: bool,
+    pub icmp_rate_limit: Option<IcmpRateLimit>,
+    // answers every probe it would forward or take with destination unreachable of this code
+    pub unreachable: Option<u8>,
+}
+
+impl Hop {
+    #[inline]
+    pub fn new(addr: IpAddr, link: Link) -> Self {
+        Hop {
+            addr,
+            link,
+            responds: true,
+            icmp_rate_limit: None,
+            unreachable: None,
+        }
+    }
+}
+
+// the probabilities are from 0.0 to 1.0 and apply in both directions
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub latency: Latency, // one way
+    pub loss: f64,
+    pub duplicate: f64, // replies only
+    pub reorder: f64,   // held back by reorder_delay
+    pub reorder_delay: Duration,
+    pub corrupt: f64, // a flipped bit, a corrupted request is dropped by its receiver
+    pub mtu: usize,   // DF is always set, as Linux does for pings
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            latency: Latency::Fixed(Duration::ZERO),
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            corrupt: 0.0,
+            mtu: 1500,
+        }
+    }
+}
+
+impl Link {
+    #[inline]
+    pub fn new(latency: Latency) -> Self {
+        Self {
+            latency,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Latency {
+    Fixed(Duration),
+    Uniform(Duration, Duration),
+    Normal { mean: Duration, std_dev: Duration }, // negative samples become 0
+}
+
+impl Latency {
+    fn sample(&self, rng: &mut ChaCha8Rng) -> Duration {
+        match *self {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform(min, max) if min >= max => min,
+            Latency::Uniform(min, max) => rng.random_range(min..max),
+            Latency::Normal { mean, std_dev } => {
+                // Box-Muller
+                let (u1, u2): (f64, f64) = (1.0 - rng.random::<f64>(), rng.random());
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                Duration::from_secs_f64((mean.as_secs_f64() + z * std_dev.as_secs_f64()).max(0.0))
+            }
+        }
+    }
+}
+
+// `burst` errors at once, then one per `interval`, like icmp_ratelimit of Linux
+#[derive(Debug, Clone, Copy)]
+pub struct IcmpRateLimit {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+impl SimulatedNetwork {
+    pub fn new(seed: u64) -> Self {
+        SimulatedNetwork {
+            state: Arc::new(Mutex::new(State {
+                rng: ChaCha8Rng::seed_from_u64(seed),
+                routes: Vec::new(),
+            })),
+            clock: Arc::new(VirtualClock::new()),
+        }
+    }
+
+    #[inline]
+    pub fn clock(&self) -> Arc<VirtualClock> {
+        self.clock.clone()
+    }
+
+    // the last hop is the destination, a target without route is unreachable
+    pub fn add_route(&self, hops: Vec<Hop>) {
+        assert!(!hops.is_empty());
+        let now = self.clock.now();
+        let route = hops
+            .into_iter()
+            .map(|hop| HopState {
+                tokens: hop.icmp_rate_limit.map_or(0, |limit| limit.burst),
+                refilled_at: now,
+                hop,
+            })
+            .collect();
+        self.state.lock().unwrap().routes.push(route);
+    }
+
+    // what comes back for `data` sent at `now`, each reply delayed by its round trip
+    fn simulate(
+        &self,
+        data: &[u8],
+        target: IpAddr,
+        mut ttl: u8,
+        now: Instant,
+    ) -> Result<Vec<MockReply>, PingError> {
+        let mut state = self.state.lock().unwrap();
+        let State { rng, routes } = &mut *state;
+        let route = routes
+            .iter_mut()
+            .find(|route| route.last().unwrap().hop.addr == target)
+            .ok_or(SharedError::Unreachable)?;
+        let size = data.len() + if target.is_ipv4() { 20 } else { 40 };
+
+        let mut packet = data.to_vec();
+        let mut corrupted = false;
+        let mut forward = Duration::ZERO;
+        for i in 0..route.len() {
+            let link = &route[i].hop.link;
+            if size > link.mtu {
+                if i == 0 {
+                    return Err(LinuxError::SendtoFailed(libc::EMSGSIZE).into());
+                }
+                let router = route[i - 1].hop.addr;
+                let reply = MockReply::packet_too_big(&packet, router, link.mtu as u32, forward);
+                return Ok(send_back(rng, &mut route[..i], reply, now));
+            }
+            if rng.random_bool(link.loss) {
+                return Ok(Vec::new());
+            }
+            forward += link.latency.sample(rng);
+            if rng.random_bool(link.reorder) {
+                forward += link.reorder_delay;
+            }
+            if rng.random_bool(link.corrupt) {
+                flip_bit(rng, &mut packet);
+                corrupted = true;
+            }
+            if i + 1 < route.len() {
+                if ttl <= 1 {
+                    // routers don't check ICMP checksums, a corrupted probe is quoted as it is
+                    let reply = MockReply::time_exceeded(&packet, route[i].hop.addr, forward);
+                    return Ok(send_back(rng, &mut route[..=i], reply, now));
+                }
+                ttl -= 1;
+            }
+            if let Some(code) = route[i].hop.unreachable {
+                let reply = MockReply::unreachable(&packet, route[i].hop.addr, code, forward);
+                return Ok(send_back(rng, &mut route[..=i], reply, now));
+            }
+        }
+
+        let is_echo_request = matches!(
+            (target, packet[0]),
+            (IpAddr::V4(_), 8) | (IpAddr::V6(_), 128)
+        );
+        if corrupted || !is_echo_request {
+            return Ok(Vec::new());
+        }
+        let reply = MockReply::echo_reply(&packet, target, forward);
+        Ok(send_back(rng, route, reply, now))
+    }
+}
+
+// carries `reply` from the last hop of `route` back to us, it may be rate limited, lost, duplicated...
+fn send_back(
+    rng: &mut ChaCha8Rng,
+    route: &mut [HopState],
+    reply: MockReply,
+    now: Instant,
+) -> Vec<MockReply> {
+    let from = route.last_mut().unwrap();
+    if !from.hop.responds {
+        return Vec::new();
+    }
+    if reply.error.is_some()
+        && let Some(limit) = from.hop.icmp_rate_limit
+    {
+        let now = now + reply.delay;
+        if !limit.interval.is_zero() {
+            let refills = (now.saturating_duration_since(from.refilled_at).as_nanos()
+                / limit.interval.as_nanos())
+            .min(u32::MAX as u128) as u32;
+            from.tokens = from.tokens.saturating_add(refills).min(limit.burst);
+            from.refilled_at += limit.interval * refills;
+        }
+        if from.tokens == 0 {
+            return Vec::new();
+        }
+        from.tokens -= 1;
+    }
+
+    let size = reply.data.len() + if reply.source.is_ipv4() { 20 } else { 40 };
+    let mut replies = vec![reply];
+    for hop in route.iter().rev() {
+        let link = &hop.hop.link;
+        let mut carried = Vec::with_capacity(replies.len());
+        for mut reply in replies {
+            if size > link.mtu || rng.random_bool(link.loss) {
+                continue;
+            }
+            reply.delay += link.latency.sample(rng);
+            if rng.random_bool(link.reorder) {
+                reply.delay += link.reorder_delay;
+            }
+            if rng.random_bool(link.corrupt) {
+                // the kernel drops ICMP errors with a bad checksum before queueing them
+                if reply.error.is_some() {
+                    continue;
+                }
+                flip_bit(rng, &mut reply.data);
+            }
+            if rng.random_bool(link.duplicate) {
+                carried.push(reply.clone());
+            }
+            carried.push(reply);
+        }
+        replies = carried;
+    }
+    replies
+}
+
+#[inline]
+fn flip_bit(rng: &mut ChaCha8Rng, data: &mut [u8]) {
+    let at = rng.random_range(0..data.len());
+    data[at] ^= 1 << rng.random_range(0..8);
+}
+
+impl Transport for SimulatedNetwork {
+    fn open(&self, config: &ChannelConfig) -> Result<Box<dyn Channel>, PingError> {
+        Ok(Box::new(SimulatedChannel {
+            network: self.clone(),
+            ttl: config.ttl.unwrap_or(64),
+            queue: ReplyQueue::new(self.clock.clone()),
+        }))
+    }
+}
+
+struct SimulatedChannel {
+    network: SimulatedNetwork,
+    ttl: u8,
+    queue: ReplyQueue,
+}
+
+impl Channel for SimulatedChannel {
+    fn send_to(&mut self, data: &[u8], target: IpAddr) -> Result<Instant, PingError> {
+        let sent_at = self.queue.clock.now();
+        let replies = self.network.simulate(data, target, self.ttl, sent_at)?;
+        self.queue.push(sent_at, replies);
+        Ok(sent_at)
+    }
+
+    #[inline]
+    fn recv(&mut self, buff: &mut [u8], timeout: Duration) -> Result<Received, PingError> {
+        self.queue.pop(buff, timeout)
+    }
+
+    #[inline]
+    fn recv_raw(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(Received, bool), PingError> {
+        self.queue.pop_raw(buff, timeout)
+    }
+}
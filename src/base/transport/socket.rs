@@ -0,0 +1,497 @@
+use crate::base::error::PingError;
+use crate::base::linux::{ExtendedError, LinuxError};
+use crate::base::transport::{
+    Channel, ChannelConfig, Family, Received, ReceivedKind, Transport, strip_ipv4_header,
+};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+// raw sockets, or ICMP DGRAM sockets with the DGRAM_SOCKET feature
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketTransport;
+
+impl Transport for SocketTransport {
+    fn open(&self, config: &ChannelConfig) -> Result<Box<dyn Channel>, PingError> {
+        let (domain, protocol) = match config.family {
+            Family::V4 => (libc::AF_INET, libc::IPPROTO_ICMP),
+            Family::V6 => (libc::AF_INET6, libc::IPPROTO_ICMPV6),
+        };
+        #[cfg(not(feature = "DGRAM_SOCKET"))]
+        let new_socket = || match unsafe { libc::socket(domain, libc::SOCK_RAW, protocol) } {
+            -1 => Err(LinuxError::get_errno()),
+            sock => Ok(Socket(sock)),
+        };
+        #[cfg(feature = "DGRAM_SOCKET")]
+        let new_socket = || match unsafe { libc::socket(domain, libc::SOCK_DGRAM, protocol) } {
+            -1 => Err(LinuxError::get_errno()),
+            sock => Ok(Socket(sock)),
+        };
+        // a socket stays in the namespace it was created in, whichever thread uses it
+        #[cfg(target_os = "linux")]
+        let sock = match &config.netns {
+            Some(netns) => netns.run(new_socket)?,
+            None => new_socket(),
+        };
+        #[cfg(not(target_os = "linux"))]
+        let sock = new_socket();
+        let sock = sock.map_err(LinuxError::convert_setup_failed)?;
+        #[cfg(target_os = "linux")]
+        if let Some(interface) = &config.interface {
+            let err = unsafe {
+                libc::setsockopt(
+                    sock.0,
+                    libc::SOL_SOCKET,
+                    libc::SO_BINDTODEVICE,
+                    interface.as_ptr() as *const libc::c_void,
+                    interface.len() as libc::socklen_t,
+                )
+            };
+            if err == -1 {
+                return Err(LinuxError::SetSockOptError(LinuxError::get_errno()).into());
+            }
+        }
+
+        match config.family {
+            Family::V4 => {
+                set_flag(sock.0, libc::SOL_IP, libc::IP_RECVERR)?;
+                // raw sockets have it in the IP header, DGRAM ones need the cmsg
+                set_flag(sock.0, libc::SOL_IP, libc::IP_RECVTTL)?;
+                if let Some(IpAddr::V4(addr)) = config.bind_addr {
+                    let sock_addr = libc::sockaddr_in {
+                        sin_family: libc::AF_INET as u16,
+                        sin_port: 0,
+                        sin_addr: libc::in_addr {
+                            s_addr: u32::from_ne_bytes(addr.octets()),
+                        },
+                        sin_zero: Default::default(),
+                    };
+                    bind(&sock, &sock_addr)?;
+                }
+                if let Some(ttl) = config.ttl {
+                    let err = unsafe {
+                        libc::setsockopt(
+                            sock.0,
+                            libc::SOL_IP,
+                            libc::IP_TTL,
+                            &ttl as *const _ as *const libc::c_void,
+                            size_of::<u8>() as libc::socklen_t,
+                        )
+                    };
+                    if err == -1 {
+                        return Err(LinuxError::SetSockOptError(LinuxError::get_errno()).into());
+                    }
+                    set_int(sock.0, libc::SOL_IP, libc::IP_MULTICAST_TTL, ttl.into())?;
+                }
+                // sendto() a broadcast address is EACCES without it
+                if config.broadcast {
+                    set_flag(sock.0, libc::SOL_SOCKET, libc::SO_BROADCAST)?;
+                }
+            }
+            Family::V6 => {
+                set_flag(sock.0, libc::SOL_IPV6, libc::IPV6_RECVERR)?;
+                // the local address is part of the ICMPv6 checksum
+                set_flag(sock.0, libc::SOL_IPV6, libc::IPV6_RECVPKTINFO)?;
+                set_flag(sock.0, libc::SOL_IPV6, libc::IPV6_RECVHOPLIMIT)?;
+                // for multicast targets, unicast ones use the scope id of the address
+                if config.scope_id != 0 {
+                    set_int(
+                        sock.0,
+                        libc::SOL_IPV6,
+                        libc::IPV6_MULTICAST_IF,
+                        config.scope_id as libc::c_int,
+                    )?;
+                }
+                if let Some(ttl) = config.ttl {
+                    set_int(
+                        sock.0,
+                        libc::SOL_IPV6,
+                        libc::IPV6_MULTICAST_HOPS,
+                        ttl.into(),
+                    )?;
+                }
+                let addr = match config.bind_addr {
+                    Some(IpAddr::V6(addr)) => addr,
+                    _ => std::net::Ipv6Addr::UNSPECIFIED,
+                };
+                bind(&sock, &get_addr_v6(addr, config.scope_id))?;
+            }
+        }
+        Ok(Box::new(SocketChannel {
+            sock,
+            family: config.family,
+            ttl: config.ttl,
+            scope_id: config.scope_id,
+        }))
+    }
+}
+
+struct SocketChannel {
+    sock: Socket,
+    family: Family,
+    ttl: Option<u8>,
+    scope_id: u32,
+}
+
+impl Channel for SocketChannel {
+    fn send_to(&mut self, data: &[u8], target: IpAddr) -> Result<Instant, PingError> {
+        match target {
+            IpAddr::V4(target) => send_to_v4(self.sock.0, data, target)?,
+            // the hop limit goes with every message, IPV6_UNICAST_HOPS isn't used
+            IpAddr::V6(target) => send_to_v6(self.sock.0, data, target, self.scope_id, self.ttl)?,
+        }
+        Ok(Instant::now())
+    }
+
+    fn recv(&mut self, buff: &mut [u8], timeout: Duration) -> Result<Received, PingError> {
+        let (mut received, ip_header) = self.recv_raw(buff, timeout)?;
+        if ip_header {
+            strip_ipv4_header(buff, &mut received)?;
+        }
+        Ok(received)
+    }
+
+    fn recv_raw(
+        &mut self,
+        buff: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(Received, bool), PingError> {
+        // a signal must not restart the full wait, only what is left of it
+        let deadline = Instant::now() + timeout;
+        set_timeout(self.sock.0, timeout)?;
+        let received = loop {
+            match recv_msg(self.sock.0, buff, 0) {
+                Ok(received) => break received,
+                Err(libc::EINTR) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(LinuxError::convert_recv_failed(libc::EAGAIN));
+                    }
+                    set_timeout(self.sock.0, left)?;
+                }
+                Err(libc::EAGAIN) => return Err(LinuxError::convert_recv_failed(libc::EAGAIN)),
+                // IP_RECVERR makes recv fail for every queued ICMP error, net unreachable too
+                Err(errno) => {
+                    return match recv_msg(
+                        self.sock.0,
+                        buff,
+                        libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+                    ) {
+                        Ok(received) => Ok((received, false)),
+                        // nothing (more) queued, fall back to the errno of the failed recv
+                        Err(_) => Err(LinuxError::convert_recv_failed(errno)),
+                    };
+                }
+            }
+        };
+        // raw IPv4 sockets get the IP header too, DGRAM ones and IPv6 don't
+        let ip_header = cfg!(not(feature = "DGRAM_SOCKET"))
+            && self.family == Family::V4
+            && matches!(received.kind, ReceivedKind::Icmp);
+        Ok((received, ip_header))
+    }
+}
+
+#[inline]
+fn bind<T>(sock: &Socket, addr: &T) -> Result<(), PingError> {
+    let err = unsafe {
+        libc::bind(
+            sock.0,
+            addr as *const _ as *const libc::sockaddr,
+            size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if err == -1 {
+        return Err(LinuxError::BindFailed(LinuxError::get_errno()).into());
+    }
+    Ok(())
+}
+
+// recvmsg into `buff`, Err is the errno
+fn recv_msg(
+    sock: libc::c_int,
+    buff: &mut [u8],
+    flags: libc::c_int,
+) -> Result<Received, libc::c_int> {
+    let mut addr = std::mem::MaybeUninit::<libc::sockaddr_storage>::zeroed();
+    let mut control_buff = [0_u8; 512];
+    let mut iovec = [libc::iovec {
+        iov_base: buff.as_mut_ptr() as *mut _,
+        iov_len: buff.len(),
+    }];
+    let mut msg = libc::msghdr {
+        msg_name: addr.as_mut_ptr() as *mut _,
+        msg_namelen: size_of::<libc::sockaddr_storage>() as libc::socklen_t,
+        msg_iov: &mut iovec as *mut _ as *mut _,
+        msg_iovlen: 1,
+        msg_control: &mut control_buff as *mut _ as *mut _,
+        msg_controllen: control_buff.len(),
+        msg_flags: 0,
+    };
+    // if you don't use recvmsg, you can't get source socketaddr
+    let len = unsafe { libc::recvmsg(sock, &mut msg as *mut _, flags) };
+    let timestamp = Instant::now();
+    if len == -1 {
+        return Err(LinuxError::get_errno());
+    }
+    let mut received = Received {
+        len: (len as usize).min(buff.len()),
+        truncated: msg.msg_flags & libc::MSG_TRUNC != 0,
+        source: match msg.msg_namelen {
+            0 => None,
+            _ => unsafe { parse_sockaddr(addr.as_ptr() as *const libc::sockaddr) },
+        },
+        destination: None,
+        ttl: None,
+        timestamp,
+        kind: ReceivedKind::Icmp,
+    };
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let (level, cmsg_type) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+        if level == libc::SOL_IPV6 && cmsg_type == libc::IPV6_PKTINFO {
+            let info =
+                unsafe { (libc::CMSG_DATA(cmsg) as *const libc::in6_pktinfo).read_unaligned() };
+            received.destination =
+                Some(IpAddr::V6(std::net::Ipv6Addr::from(info.ipi6_addr.s6_addr)));
+        }
+        if (level == libc::SOL_IP && cmsg_type == libc::IP_TTL)
+            || (level == libc::SOL_IPV6 && cmsg_type == libc::IPV6_HOPLIMIT)
+        {
+            let ttl = unsafe { (libc::CMSG_DATA(cmsg) as *const libc::c_int).read_unaligned() };
+            received.ttl = u8::try_from(ttl).ok();
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+    if flags & libc::MSG_ERRQUEUE != 0 {
+        // an entry without the extended error can't be told apart, so it is dropped
+        let Some(extended) = (unsafe { parse_extended_error(&msg) }) else {
+            return recv_msg(sock, buff, flags);
+        };
+        received.source = extended.offender;
+        received.kind = ReceivedKind::Error(extended);
+    }
+    Ok(received)
+}
+
+unsafe fn parse_sockaddr(addr: *const libc::sockaddr) -> Option<IpAddr> {
+    match unsafe { addr.read_unaligned() }.sa_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = unsafe { (addr as *const libc::sockaddr_in).read_unaligned() };
+            Some(IpAddr::V4(std::net::Ipv4Addr::from(
+                addr.sin_addr.s_addr.to_ne_bytes(),
+            )))
+        }
+        libc::AF_INET6 => {
+            let addr = unsafe { (addr as *const libc::sockaddr_in6).read_unaligned() };
+            Some(IpAddr::V6(std::net::Ipv6Addr::from(addr.sin6_addr.s6_addr)))
+        }
+        _ => None,
+    }
+}
+
+// closes the socket when the ping is finished
+struct Socket(libc::c_int);
+
+impl Drop for Socket {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+#[inline]
+fn set_timeout(sock: libc::c_int, timeout: std::time::Duration) -> Result<(), PingError> {
+    // a zero timeval means blocking forever, so round sub-microsecond waits up
+    let micros = match timeout.as_secs() {
+        0 => timeout.subsec_micros().max(1),
+        _ => timeout.subsec_micros(),
+    };
+    let timeval = libc::timeval {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_usec: micros as libc::suseconds_t,
+    };
+    let err = unsafe {
+        libc::setsockopt(
+            sock,
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO_NEW,
+            &timeval as *const _ as *const libc::c_void,
+            size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+    if err == -1 {
+        return Err(LinuxError::SetSockOptError(LinuxError::get_errno()).into());
+    }
+    Ok(())
+}
+
+#[inline]
+fn set_flag(sock: libc::c_int, level: libc::c_int, name: libc::c_int) -> Result<(), PingError> {
+    set_int(sock, level, name, 1)
+}
+
+#[inline]
+fn set_int(
+    sock: libc::c_int,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> Result<(), PingError> {
+    let err = unsafe {
+        libc::setsockopt(
+            sock,
+            level,
+            name,
+            &value as *const _ as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if err == -1 {
+        return Err(LinuxError::SetSockOptError(LinuxError::get_errno()).into());
+    }
+    Ok(())
+}
+
+unsafe fn parse_extended_error(msg: &libc::msghdr) -> Option<ExtendedError> {
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg) };
+    while !cmsg.is_null() {
+        let (level, cmsg_type) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+        if (level == libc::SOL_IP && cmsg_type == libc::IP_RECVERR)
+            || (level == libc::SOL_IPV6 && cmsg_type == libc::IPV6_RECVERR)
+        {
+            let ee = unsafe { libc::CMSG_DATA(cmsg) } as *const libc::sock_extended_err;
+            let ee_value = unsafe { ee.read_unaligned() };
+            let offender = unsafe { libc::SO_EE_OFFENDER(ee) } as *const libc::sockaddr;
+            let offender = unsafe { parse_sockaddr(offender) };
+            return Some(ExtendedError {
+                errno: ee_value.ee_errno,
+                origin: ee_value.ee_origin,
+                icmp_type: ee_value.ee_type,
+                icmp_code: ee_value.ee_code,
+                info: ee_value.ee_info,
+                offender,
+            });
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(msg, cmsg) };
+    }
+    None
+}
+
+fn send_to_v4(sock: libc::c_int, data: &[u8], target: std::net::Ipv4Addr) -> Result<(), PingError> {
+    let addr = libc::sockaddr_in {
+        sin_family: libc::AF_INET as u16,
+        sin_port: 0,
+        sin_addr: libc::in_addr {
+            s_addr: u32::from_ne_bytes(target.octets()),
+        },
+        sin_zero: Default::default(),
+    };
+    let err = unsafe {
+        libc::sendto(
+            sock,
+            data.as_ptr() as *const _,
+            data.len(),
+            0,
+            &addr as *const _ as *const libc::sockaddr,
+            size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if err == -1 {
+        return Err(LinuxError::SendtoFailed(LinuxError::get_errno()).into());
+    }
+    Ok(())
+}
+
+fn send_to_v6(
+    sock: libc::c_int,
+    data: &[u8],
+    target: std::net::Ipv6Addr,
+    scope_id: u32,
+    ttl: Option<u8>,
+) -> Result<(), PingError> {
+    let mut addr_v6 = get_addr_v6(target, scope_id);
+
+    match ttl {
+        // 没错, ipv6设置ttl(HopLimit)就是这么繁琐
+        None => {
+            let err = unsafe {
+                libc::sendto(
+                    sock,
+                    data.as_ptr() as *mut _,
+                    data.len(),
+                    0,
+                    &mut addr_v6 as *mut _ as *mut _,
+                    size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                )
+            };
+            if err == -1 {
+                return Err(LinuxError::SendtoFailed(LinuxError::get_errno()).into());
+            }
+        }
+        Some(ttl) => {
+            let ttl = ttl as Ttl; // use u32, instead you will have to deal with problem in CMSG_LEN API
+            type Ttl = u32;
+
+            let mut iovec = [libc::iovec {
+                iov_base: data.as_ptr() as *mut _,
+                iov_len: data.len(),
+            }];
+
+            const CONTROL_BUFF_LEN: usize =
+                unsafe { libc::CMSG_SPACE(size_of::<Ttl>() as _) as usize };
+            let mut control_buff = [0_u8; CONTROL_BUFF_LEN];
+            let msghdr = libc::msghdr {
+                msg_name: &mut addr_v6 as *mut _ as *mut _,
+                msg_namelen: size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                msg_iov: &mut iovec as *mut _ as *mut _,
+                msg_iovlen: 1,
+                msg_control: &mut control_buff as *mut _ as *mut _,
+                msg_controllen: CONTROL_BUFF_LEN,
+                msg_flags: 0,
+            };
+            let ttl_cmsghdr: volatile::VolatilePtr<libc::cmsghdr> = unsafe {
+                volatile::VolatilePtr::new(
+                    std::ptr::NonNull::new(libc::CMSG_FIRSTHDR(&msghdr))
+                        .ok_or(LinuxError::NullPtr)?,
+                ) // use VolatilePtr to avoid being optimized
+            };
+
+            ttl_cmsghdr.update(|mut cmsg| {
+                cmsg.cmsg_level = libc::SOL_IPV6;
+                cmsg.cmsg_type = libc::IPV6_HOPLIMIT;
+                cmsg.cmsg_len = unsafe { libc::CMSG_LEN(size_of::<Ttl>() as _) } as libc::size_t;
+                cmsg
+            });
+            let _ = unsafe {
+                volatile::VolatilePtr::new(
+                    std::ptr::NonNull::new(libc::CMSG_DATA(ttl_cmsghdr.as_raw_ptr().as_ptr()))
+                        .ok_or(LinuxError::NullPtr)?,
+                )
+                .map(|data_ptr| {
+                    data_ptr
+                        .as_ptr()
+                        .copy_from_nonoverlapping(&ttl as *const _ as *const _, size_of::<Ttl>());
+                    data_ptr
+                })
+            };
+            let err = unsafe { libc::sendmsg(sock, &msghdr as *const _ as *const _, 0) };
+            if err == -1 {
+                return Err(LinuxError::SendMessageFailed(LinuxError::get_errno()).into());
+            }
+        }
+    };
+    Ok(())
+}
+
+#[inline]
+fn get_addr_v6(target: std::net::Ipv6Addr, sin6_scope_id: u32) -> libc::sockaddr_in6 {
+    libc::sockaddr_in6 {
+        sin6_family: libc::AF_INET6 as u16,
+        sin6_port: 0,
+        sin6_flowinfo: 0,
+        sin6_addr: libc::in6_addr {
+            s6_addr: target.octets(),
+        },
+        sin6_scope_id,
+    }
+}
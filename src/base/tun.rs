@@ -0,0 +1,463 @@
+use crate::base::protocol::{IcmpMessage, Ipv4Header, Ipv6Header, checksum};
+use std::ffi::CString;
+use std::io;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// for end to end tests: a TUN interface whose far side is answered by a thread of ours,
+// so the real sockets of PingV4/PingV6 can be tested on an offline box (needs CAP_NET_ADMIN)
+//
+// by default the calling thread moves into a new network namespace until the TunNetwork is dropped,
+// so ping from the thread that created it, and drop it on that thread too
+pub struct TunNetwork {
+    name: String,
+    ifindex: libc::c_int,
+    local_v4: Ipv4Addr,
+    local_v6: Ipv6Addr,
+    routes: Arc<Mutex<Vec<Vec<TunHop>>>>,
+    stop: Arc<AtomicBool>,
+    responder: Option<JoinHandle<()>>,
+    control_v4: OwnedFd,
+    control_v6: OwnedFd,
+    // the namespace to go back to, None when no new one was entered
+    origin_netns: Option<OwnedFd>,
+    // setns only moves the calling thread
+    _not_send: PhantomData<*const ()>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TunConfig {
+    // falls back to the current namespace when unshare fails,
+    // then give every TunNetwork its own addresses
+    pub private_netns: bool,
+    pub name: String, // "%d" is filled in by the kernel
+    pub local_v4: Ipv4Addr,
+    pub local_v6: Ipv6Addr,
+}
+
+impl Default for TunConfig {
+    fn default() -> Self {
+        TunConfig {
+            private_netns: true,
+            name: String::from("rpstun%d"),
+            local_v4: Ipv4Addr::new(198, 18, 0, 1),
+            local_v6: Ipv6Addr::new(0xfd00, 0x198, 0x18, 0, 0, 0, 0, 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TunHop {
+    pub addr: IpAddr,
+    // added to the round trip of every probe reaching this hop
+    pub delay: Duration,
+    pub reply: HopReply,
+}
+
+impl TunHop {
+    #[inline]
+    pub fn new(addr: IpAddr) -> Self {
+        TunHop {
+            addr,
+            delay: Duration::ZERO,
+            reply: HopReply::Normal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HopReply {
+    // time exceeded as a router, echo reply (or port unreachable for UDP) as the destination
+    Normal,
+    // forwards, but never answers
+    Silent,
+    // answers every probe reaching it with destination unreachable of this code
+    Unreachable(u8),
+}
+
+// how a probe is answered
+enum Answer {
+    Reached,
+    TimeExceeded,
+    Unreachable(u8),
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+impl TunNetwork {
+    pub fn new(config: TunConfig) -> io::Result<Self> {
+        let origin_netns = match config.private_netns {
+            true => enter_new_netns().ok(),
+            false => None,
+        };
+        match Self::setup(&config, origin_netns.is_some()) {
+            Ok((tun, mut network)) => {
+                let routes = network.routes.clone();
+                let stop = network.stop.clone();
+                network.responder = Some(std::thread::spawn(move || respond(tun, routes, stop)));
+                network.origin_netns = origin_netns;
+                Ok(network)
+            }
+            Err(e) => {
+                if let Some(origin) = origin_netns {
+                    unsafe { libc::setns(origin.as_raw_fd(), libc::CLONE_NEWNET) };
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn setup(config: &TunConfig, private: bool) -> io::Result<(OwnedFd, Self)> {
+        let tun = cvt(unsafe {
+            libc::open(
+                c"/dev/net/tun".as_ptr(),
+                libc::O_RDWR | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        })?;
+        let tun = unsafe { OwnedFd::from_raw_fd(tun) };
+        let mut request = ifreq(&config.name)?;
+        request.ifr_ifru.ifru_flags = (libc::IFF_TUN | libc::IFF_NO_PI) as libc::c_short;
+        cvt(unsafe { libc::ioctl(tun.as_raw_fd(), libc::TUNSETIFF, &mut request) })?;
+        let name = unsafe { std::ffi::CStr::from_ptr(request.ifr_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+
+        let control_v4 = control_socket(libc::AF_INET)?;
+        let control_v6 = control_socket(libc::AF_INET6)?;
+        if private {
+            set_up(&control_v4, "lo")?;
+            // a new namespace allows no ping socket, DGRAM_SOCKET needs it
+            std::fs::write("/proc/sys/net/ipv4/ping_group_range", "0 2147483647")?;
+        }
+        set_up(&control_v4, &name)?;
+
+        let mut request = ifreq(&name)?;
+        request.ifr_ifru.ifru_addr = sockaddr(config.local_v4);
+        cvt(unsafe { libc::ioctl(control_v4.as_raw_fd(), libc::SIOCSIFADDR, &request) })?;
+        request.ifr_ifru.ifru_netmask = sockaddr(Ipv4Addr::BROADCAST);
+        cvt(unsafe { libc::ioctl(control_v4.as_raw_fd(), libc::SIOCSIFNETMASK, &request) })?;
+        cvt(unsafe { libc::ioctl(control_v4.as_raw_fd(), libc::SIOCGIFINDEX, &mut request) })?;
+        let ifindex = unsafe { request.ifr_ifru.ifru_ifindex };
+
+        let request = libc::in6_ifreq {
+            ifr6_addr: libc::in6_addr {
+                s6_addr: config.local_v6.octets(),
+            },
+            ifr6_prefixlen: 128,
+            ifr6_ifindex: ifindex,
+        };
+        cvt(unsafe { libc::ioctl(control_v6.as_raw_fd(), libc::SIOCSIFADDR, &request) })?;
+
+        let network = TunNetwork {
+            name,
+            ifindex,
+            local_v4: config.local_v4,
+            local_v6: config.local_v6,
+            routes: Arc::new(Mutex::new(Vec::new())),
+            stop: Arc::new(AtomicBool::new(false)),
+            responder: None,
+            control_v4,
+            control_v6,
+            origin_netns: None,
+            _not_send: PhantomData,
+        };
+        Ok((tun, network))
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn local_v4(&self) -> Ipv4Addr {
+        self.local_v4
+    }
+
+    #[inline]
+    pub fn local_v6(&self) -> Ipv6Addr {
+        self.local_v6
+    }
+
+    #[inline]
+    pub fn in_private_netns(&self) -> bool {
+        self.origin_netns.is_some()
+    }
+
+    // the last hop is the destination, pinging a router on the way works too
+    pub fn add_route(&self, hops: Vec<TunHop>) -> io::Result<()> {
+        for hop in &hops {
+            match hop.addr {
+                IpAddr::V4(addr) => self.add_host_route_v4(addr)?,
+                IpAddr::V6(addr) => self.add_host_route_v6(addr)?,
+            }
+        }
+        self.routes.lock().unwrap().push(hops);
+        Ok(())
+    }
+
+    fn add_host_route_v4(&self, addr: Ipv4Addr) -> io::Result<()> {
+        let name = CString::new(self.name.as_str())?;
+        let mut route: libc::rtentry = unsafe { std::mem::zeroed() };
+        route.rt_dst = sockaddr(addr);
+        route.rt_genmask = sockaddr(Ipv4Addr::BROADCAST);
+        route.rt_flags = libc::RTF_UP | libc::RTF_HOST;
+        route.rt_dev = name.as_ptr() as *mut libc::c_char;
+        add_route(&self.control_v4, &route)
+    }
+
+    fn add_host_route_v6(&self, addr: Ipv6Addr) -> io::Result<()> {
+        let route = in6_rtmsg {
+            rtmsg_dst: libc::in6_addr {
+                s6_addr: addr.octets(),
+            },
+            rtmsg_src: libc::in6_addr { s6_addr: [0; 16] },
+            rtmsg_gateway: libc::in6_addr { s6_addr: [0; 16] },
+            rtmsg_type: 0,
+            rtmsg_dst_len: 128,
+            rtmsg_src_len: 0,
+            rtmsg_metric: 1,
+            rtmsg_info: 0,
+            rtmsg_flags: (libc::RTF_UP | libc::RTF_HOST) as u32,
+            rtmsg_ifindex: self.ifindex,
+        };
+        add_route(&self.control_v6, &route)
+    }
+}
+
+impl Drop for TunNetwork {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // the responder owns the TUN fd, the interface is gone once it returns
+        if let Some(responder) = self.responder.take() {
+            let _ = responder.join();
+        }
+        if let Some(origin) = &self.origin_netns {
+            unsafe { libc::setns(origin.as_raw_fd(), libc::CLONE_NEWNET) };
+        }
+    }
+}
+
+// linux/ipv6_route.h, not in libc
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct in6_rtmsg {
+    rtmsg_dst: libc::in6_addr,
+    rtmsg_src: libc::in6_addr,
+    rtmsg_gateway: libc::in6_addr,
+    rtmsg_type: u32,
+    rtmsg_dst_len: u16,
+    rtmsg_src_len: u16,
+    rtmsg_metric: u32,
+    rtmsg_info: libc::c_ulong,
+    rtmsg_flags: u32,
+    rtmsg_ifindex: libc::c_int,
+}
+
+#[inline]
+fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(ret),
+    }
+}
+
+fn enter_new_netns() -> io::Result<OwnedFd> {
+    let origin = std::fs::File::open("/proc/thread-self/ns/net")?;
+    cvt(unsafe { libc::unshare(libc::CLONE_NEWNET) })?;
+    Ok(origin.into())
+}
+
+fn control_socket(family: libc::c_int) -> io::Result<OwnedFd> {
+    let sock = cvt(unsafe { libc::socket(family, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) })?;
+    Ok(unsafe { OwnedFd::from_raw_fd(sock) })
+}
+
+fn ifreq(name: &str) -> io::Result<libc::ifreq> {
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    if name.len() >= request.ifr_name.len() {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+    for (to, from) in request.ifr_name.iter_mut().zip(name.bytes()) {
+        *to = from as libc::c_char;
+    }
+    Ok(request)
+}
+
+fn set_up(control: &OwnedFd, name: &str) -> io::Result<()> {
+    let mut request = ifreq(name)?;
+    cvt(unsafe { libc::ioctl(control.as_raw_fd(), libc::SIOCGIFFLAGS, &mut request) })?;
+    unsafe { request.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short };
+    cvt(unsafe { libc::ioctl(control.as_raw_fd(), libc::SIOCSIFFLAGS, &request) })?;
+    Ok(())
+}
+
+fn add_route<T>(control: &OwnedFd, route: &T) -> io::Result<()> {
+    match cvt(unsafe { libc::ioctl(control.as_raw_fd(), libc::SIOCADDRT, route) }) {
+        // a router shared by two routes
+        Err(e) if e.raw_os_error() == Some(libc::EEXIST) => Ok(()),
+        other => other.map(|_| ()),
+    }
+}
+
+fn sockaddr(addr: Ipv4Addr) -> libc::sockaddr {
+    let addr = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr {
+            s_addr: u32::from_ne_bytes(addr.octets()),
+        },
+        sin_zero: [0; 8],
+    };
+    unsafe { std::mem::transmute(addr) }
+}
+
+// reads what the kernel routes into the TUN interface and writes the answers back when they are due
+fn respond(tun: OwnedFd, routes: Arc<Mutex<Vec<Vec<TunHop>>>>, stop: Arc<AtomicBool>) {
+    let mut buff = vec![0u8; 65536];
+    let mut pending: Vec<(Instant, Vec<u8>)> = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        let mut due: Vec<_> = pending.extract_if(.., |(at, _)| *at <= now).collect();
+        due.sort_by_key(|(at, _)| *at);
+        for (_, packet) in due {
+            unsafe { libc::write(tun.as_raw_fd(), packet.as_ptr() as *const _, packet.len()) };
+        }
+
+        let wait = pending
+            .iter()
+            .map(|(at, _)| at.saturating_duration_since(now))
+            .min()
+            .unwrap_or(POLL_INTERVAL)
+            .min(POLL_INTERVAL);
+        let mut fd = libc::pollfd {
+            fd: tun.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = wait.as_micros().div_ceil(1000) as libc::c_int;
+        if unsafe { libc::poll(&mut fd, 1, timeout) } <= 0 {
+            continue;
+        }
+        loop {
+            let len =
+                unsafe { libc::read(tun.as_raw_fd(), buff.as_mut_ptr() as *mut _, buff.len()) };
+            if len <= 0 {
+                break;
+            }
+            let now = Instant::now();
+            if let Some((delay, packet)) = answer(&routes.lock().unwrap(), &buff[..len as usize]) {
+                pending.push((now + delay, packet));
+            }
+        }
+    }
+}
+
+fn answer(routes: &[Vec<TunHop>], packet: &[u8]) -> Option<(Duration, Vec<u8>)> {
+    match packet.first()? >> 4 {
+        4 => answer_v4(routes, packet),
+        6 => answer_v6(routes, packet),
+        _ => None,
+    }
+}
+
+// who answers a probe to `destination` sent with `ttl`, how and after which delay
+fn walk(
+    routes: &[Vec<TunHop>],
+    destination: IpAddr,
+    mut ttl: u8,
+) -> Option<(IpAddr, Answer, Duration)> {
+    let path = routes.iter().find_map(|route| {
+        let at = route.iter().position(|hop| hop.addr == destination)?;
+        Some(&route[..=at])
+    })?;
+    let mut delay = Duration::ZERO;
+    for (i, hop) in path.iter().enumerate() {
+        delay += hop.delay;
+        let last = i + 1 == path.len();
+        match hop.reply {
+            HopReply::Unreachable(code) => {
+                return Some((hop.addr, Answer::Unreachable(code), delay));
+            }
+            HopReply::Silent if last || ttl <= 1 => return None,
+            HopReply::Normal if last => return Some((hop.addr, Answer::Reached, delay)),
+            HopReply::Normal if ttl <= 1 => return Some((hop.addr, Answer::TimeExceeded, delay)),
+            _ => ttl -= 1,
+        }
+    }
+    None
+}
+
+fn answer_v4(routes: &[Vec<TunHop>], packet: &[u8]) -> Option<(Duration, Vec<u8>)> {
+    let header = Ipv4Header::from_slice(packet)?;
+    let destination = header.get_destination_address();
+    let (from, answer, delay) = walk(routes, destination.into(), header.get_ttl())?;
+    let IpAddr::V4(from) = from else {
+        return None;
+    };
+    let payload = header.get_payload();
+    // as much of the probe as fits in 576 bytes, like Linux
+    let quote = &packet[..packet.len().min(548)];
+    let message = match (answer, header.get_protocol(), payload) {
+        (Answer::Reached, 1, [8, 0, _, _, a, b, c, d, body @ ..]) => {
+            IcmpMessage::new_v4(0, 0, [*a, *b, *c, *d], body)
+        }
+        (Answer::Reached, 17, _) => IcmpMessage::new_v4(3, 3, [0; 4], quote),
+        (Answer::Reached, _, _) => return None,
+        // never an error about an error
+        (_, 1, [3 | 11 | 12, ..]) => return None,
+        (Answer::TimeExceeded, _, _) => IcmpMessage::new_v4(11, 0, [0; 4], quote),
+        (Answer::Unreachable(code), _, _) => IcmpMessage::new_v4(3, code, [0; 4], quote),
+    };
+
+    let data = message.get_inner();
+    let mut reply = Vec::with_capacity(20 + data.len());
+    reply.extend_from_slice(&[0x45, 0]);
+    reply.extend_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+    reply.extend_from_slice(&[0, 0, 0, 0, 64, 1, 0, 0]);
+    reply.extend_from_slice(&from.octets());
+    reply.extend_from_slice(&header.get_source_address().octets());
+    let sum = checksum::checksum(&reply);
+    reply[10..12].copy_from_slice(&sum.to_be_bytes());
+    reply.extend_from_slice(data);
+    Some((delay, reply))
+}
+
+fn answer_v6(routes: &[Vec<TunHop>], packet: &[u8]) -> Option<(Duration, Vec<u8>)> {
+    let header = Ipv6Header::from_slice(packet)?;
+    let destination = header.get_destination_address();
+    let (from, answer, delay) = walk(routes, destination.into(), header.get_hop_limit())?;
+    let IpAddr::V6(from) = from else {
+        return None;
+    };
+    let to = header.get_source_address();
+    // as much of the probe as fits in the minimum MTU
+    let quote = &packet[..packet.len().min(1232)];
+    let message = match (answer, header.get_type()?, header.get_payload()?) {
+        (Answer::Reached, 58, [128, 0, _, _, a, b, c, d, body @ ..]) => {
+            IcmpMessage::new_v6(129, 0, [*a, *b, *c, *d], body)
+        }
+        (Answer::Reached, 17, _) => IcmpMessage::new_v6(1, 4, [0; 4], quote),
+        (Answer::Reached, _, _) => return None,
+        (_, 58, [0..128, ..]) => return None,
+        (Answer::TimeExceeded, _, _) => IcmpMessage::new_v6(3, 0, [0; 4], quote),
+        (Answer::Unreachable(code), _, _) => IcmpMessage::new_v6(1, code, [0; 4], quote),
+    };
+
+    let mut data = message.get_inner().to_vec();
+    let sum = checksum::checksum_v6(from, to, &data);
+    data[2..4].copy_from_slice(&sum.to_be_bytes());
+    let mut reply = Vec::with_capacity(40 + data.len());
+    reply.extend_from_slice(&[0x60, 0, 0, 0]);
+    reply.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    reply.extend_from_slice(&[58, 64]);
+    reply.extend_from_slice(&from.octets());
+    reply.extend_from_slice(&to.octets());
+    reply.extend_from_slice(&data);
+    Some((delay, reply))
+}
@@ -1,5 +1,6 @@
 use crate::base::builder::{PingV4Builder, PingV6Builder};
 use crate::base::error::{PingError, SharedError};
+use crate::base::timeout;
 use crate::base::utils::un_mut::UnMut;
 use crate::{PingV4Result, PingV6Result};
 use rand::Rng;
@@ -18,16 +19,29 @@ pub enum WindowsError {
 pub struct PingV4 {
     builder: PingV4Builder,
     info: common::IpOptionInformation,
+    session_end: Option<std::time::Instant>,
 }
 
 pub struct PingV6 {
     builder: PingV6Builder,
     info: common::IpOptionInformation,
+    session_end: Option<std::time::Instant>,
 }
 
 mod common {
     use super::*;
 
+    // IcmpSendEcho2 only knows milliseconds, round up so short waits don't become 0
+    #[inline]
+    pub(super) fn timeout_millis(
+        timeout: std::time::Duration,
+        session_end: Option<std::time::Instant>,
+    ) -> Result<u32, PingError> {
+        let until = timeout::reply_until(timeout, session_end, std::time::Instant::now())?;
+        let remaining = timeout::remaining(until, std::time::Instant::now())?;
+        Ok(remaining.as_micros().div_ceil(1000).min(u32::MAX as u128) as u32)
+    }
+
     #[inline]
     pub(super) fn check_reply_count(
         count: u32,
@@ -99,14 +113,17 @@ mod common {
 impl PingV4 {
     #[inline]
     pub fn new(builder: PingV4Builder) -> PingV4 {
+        let session_end = timeout::session_end(builder.deadline, std::time::Instant::now());
         match builder.ttl {
             Some(ttl) => PingV4 {
                 builder,
                 info: common::IpOptionInformation::new(ttl),
+                session_end,
             },
             None => PingV4 {
                 builder,
                 info: common::IpOptionInformation(None),
+                session_end,
             },
         }
     }
@@ -118,6 +135,7 @@ impl PingV4 {
         target: std::net::Ipv4Addr,
         buf: &mut [u8; Self::REPLY_BUFFER_SIZE],
     ) -> Result<std::time::Duration, PingError> {
+        let timeout = common::timeout_millis(self.builder.timeout, self.session_end)?;
         unsafe {
             let handler: Foundation::HANDLE = match IpHelper::IcmpCreateFile() {
                 Ok(v) => v,
@@ -149,7 +167,7 @@ impl PingV4 {
                     request_options,
                     buf.as_ptr() as *mut _,
                     buf.len() as _,
-                    self.builder.timeout,
+                    timeout,
                 ),
                 Some(addr) => IpHelper::IcmpSendEcho2Ex(
                     handler,
@@ -163,7 +181,7 @@ impl PingV4 {
                     request_options,
                     buf.as_ptr() as *mut _,
                     buf.len() as _,
-                    self.builder.timeout,
+                    timeout,
                 ),
             };
             let reply_time = std::time::Instant::now().duration_since(start_time);
@@ -193,14 +211,17 @@ impl PingV4 {
 impl PingV6 {
     #[inline]
     pub fn new(builder: PingV6Builder) -> PingV6 {
+        let session_end = timeout::session_end(builder.deadline, std::time::Instant::now());
         match builder.ttl {
             Some(ttl) => PingV6 {
                 builder,
                 info: common::IpOptionInformation::new(ttl),
+                session_end,
             },
             None => PingV6 {
                 builder,
                 info: common::IpOptionInformation(None),
+                session_end,
             },
         }
     }
@@ -213,6 +234,7 @@ impl PingV6 {
         target: std::net::Ipv6Addr,
         buf: &mut [u8; Self::REPLY_BUFFER_SIZE],
     ) -> Result<std::time::Duration, PingError> {
+        let timeout = common::timeout_millis(self.builder.timeout, self.session_end)?;
         unsafe {
             let handler: Foundation::HANDLE = match IpHelper::Icmp6CreateFile() {
                 Ok(v) => v,
@@ -276,7 +298,7 @@ impl PingV6 {
                 request_options,
                 buf.as_ptr() as *mut _,
                 buf.len() as _,
-                self.builder.timeout,
+                timeout,
             );
             let reply_time = std::time::Instant::now().duration_since(start_time);
             common::check_reply_count(reply_count, handler)?;
@@ -0,0 +1,8 @@
+// the ping command, iputils compatible options on top of the library
+pub mod args;
+#[cfg(feature = "EXPORTER")]
+pub mod exporter;
+pub mod mtr;
+pub mod output;
+pub mod ping;
+pub mod sweep;
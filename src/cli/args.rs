@@ -0,0 +1,342 @@
+use crate::cli::output::Format;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: ping [options] <destination>
+       ping --sweep [options] [<target>...]
+       ping --exporter <addr> [options] [<target>...]
+
+Options:
+  -c <count>         stop after <count> probes, or <count> replies with -w
+  -i <interval>      seconds between sending each packet
+  -W <timeout>       seconds to wait for each reply
+  -w <deadline>      reply wait in seconds for the whole session
+  -t <ttl>           define time to live
+  -s <size>          use <size> as number of data bytes to be sent
+  -p <pattern>       contents of padding byte, up to 16 bytes of hex
+  -I <interface>     either interface name or address
+  -4                 use IPv4
+  -6                 use IPv6
+  -q                 quiet output
+  -D                 print timestamps
+  -O                 report outstanding replies
+  -b                 allow pinging broadcast
+  -h                 print help and exit
+  --format <format>  text, json, jsonl or csv
+
+Modes:
+  --mtr              traceroute continuously and keep statistics of every hop,
+                     -c counts rounds, -i is the time between them
+  --report           with --mtr, only print the report at the end
+  --max-hops <hops>  with --mtr, the largest TTL probed, 30 by default
+  --sweep            fping style, probe addresses, CIDR blocks (10.0.0.0/24), ranges
+                     (10.0.0.1-50) and names, from stdin without targets or files,
+                     -c is the attempts per target
+  --file <file>      with --sweep, one target per line, - for stdin
+  --rate <pps>       with --sweep, packets per second over all targets,
+                     100 by default, 0 for no limit
+  --concurrency <n>  with --sweep, targets probed at the same time, 64 by default
+  --exporter <addr>  serve Prometheus metrics on http://<addr>/metrics, probing the
+                     targets every -i seconds, which are given as with --sweep";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersion {
+    Any,
+    V4,
+    V6,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Interface {
+    Addr(IpAddr),
+    Name(String),
+}
+
+// the options of iputils ping we have
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    pub count: Option<u64>,
+    pub interval: Duration,
+    pub timeout: Option<Duration>, // None to wait until the next send, at least a second
+    pub deadline: Option<Duration>,
+    pub ttl: Option<u8>,
+    pub size: usize,
+    pub pattern: Vec<u8>,
+    pub interface: Option<Interface>,
+    pub version: IpVersion,
+    pub quiet: bool,
+    pub timestamps: bool,
+    pub outstanding: bool,
+    pub broadcast: bool,
+    pub format: Format,
+    pub mtr: bool,
+    pub report: bool,
+    pub max_hops: u8,
+    pub sweep: bool,
+    pub files: Vec<String>,
+    pub rate: u32,
+    pub concurrency: usize,
+    pub exporter: Option<SocketAddr>,
+    pub destination: String,  // empty with --sweep and --exporter
+    pub targets: Vec<String>, // with --sweep and --exporter
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            count: None,
+            interval: Duration::from_secs(1),
+            timeout: None,
+            deadline: None,
+            ttl: None,
+            size: 56,
+            pattern: Vec::new(),
+            interface: None,
+            version: IpVersion::Any,
+            quiet: false,
+            timestamps: false,
+            outstanding: false,
+            broadcast: false,
+            format: Format::Text,
+            mtr: false,
+            report: false,
+            max_hops: 30,
+            sweep: false,
+            files: Vec::new(),
+            rate: 100,
+            concurrency: 64,
+            exporter: None,
+            destination: String::new(),
+            targets: Vec::new(),
+        }
+    }
+}
+
+// the largest ICMP payload in an IPv4 packet
+const MAX_SIZE: usize = 65507;
+
+// getopt style, "-c 3", "-c3" and "-qc3" are the same, None for -h
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Args>, String> {
+    let mut result = Args::default();
+    let mut destinations = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(option) = arg.strip_prefix("--") {
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (option, None),
+            };
+            match name {
+                "help" => return Ok(None),
+                "mtr" => result.mtr = true,
+                "report" => result.report = true,
+                "sweep" => result.sweep = true,
+                "format" | "max-hops" | "file" | "rate" | "concurrency" | "exporter" => {
+                    let value = value
+                        .or_else(|| args.next())
+                        .ok_or(format!("option '--{name}' requires an argument"))?;
+                    let invalid = || format!("invalid argument: '{value}'");
+                    match name {
+                        "format" => result.format = value.parse()?,
+                        "file" => result.files.push(value),
+                        "rate" => result.rate = value.parse().map_err(|_| invalid())?,
+                        "exporter" => result.exporter = Some(value.parse().map_err(|_| invalid())?),
+                        "concurrency" => match value.parse() {
+                            Ok(0) | Err(_) => return Err(invalid()),
+                            Ok(concurrency) => result.concurrency = concurrency,
+                        },
+                        _ => match value.parse() {
+                            Ok(0) | Err(_) => return Err(invalid()),
+                            Ok(max_hops) => result.max_hops = max_hops,
+                        },
+                    }
+                }
+                _ => return Err(format!("unrecognized option '--{name}'")),
+            }
+            continue;
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            destinations.push(arg);
+            continue;
+        };
+        for (i, flag) in flags.char_indices() {
+            match flag {
+                '4' => result.version = IpVersion::V4,
+                '6' => result.version = IpVersion::V6,
+                'q' => result.quiet = true,
+                'D' => result.timestamps = true,
+                'O' => result.outstanding = true,
+                'b' => result.broadcast = true,
+                'h' => return Ok(None),
+                'c' | 'i' | 'W' | 'w' | 't' | 's' | 'p' | 'I' => {
+                    let value = match &flags[i + 1..] {
+                        "" => args
+                            .next()
+                            .ok_or(format!("option requires an argument -- '{flag}'"))?,
+                        rest => rest.to_string(),
+                    };
+                    set_value(&mut result, flag, &value)?;
+                    break;
+                }
+                _ => return Err(format!("invalid option -- '{flag}'")),
+            }
+        }
+    }
+    if result.sweep || result.exporter.is_some() {
+        result.targets = destinations;
+        return Ok(Some(result));
+    }
+    match destinations.len() {
+        0 => Err(String::from("usage error: Destination address required")),
+        1 => {
+            result.destination = destinations.remove(0);
+            Ok(Some(result))
+        }
+        _ => Err(String::from("only one destination can be pinged")),
+    }
+}
+
+fn set_value(args: &mut Args, flag: char, value: &str) -> Result<(), String> {
+    let invalid = || format!("invalid argument: '{value}'");
+    match flag {
+        'c' => match value.parse() {
+            Ok(0) | Err(_) => return Err(invalid()),
+            Ok(count) => args.count = Some(count),
+        },
+        'i' => args.interval = seconds(value).ok_or_else(invalid)?,
+        'W' => args.timeout = Some(seconds(value).ok_or_else(invalid)?),
+        'w' => args.deadline = Some(seconds(value).ok_or_else(invalid)?),
+        't' => match value.parse() {
+            Ok(0) | Err(_) => return Err(format!("ttl {value} out of range")),
+            Ok(ttl) => args.ttl = Some(ttl),
+        },
+        's' => match value.parse() {
+            Ok(size) if size <= MAX_SIZE => args.size = size,
+            _ => return Err(invalid()),
+        },
+        'p' => {
+            args.pattern = pattern(value)
+                .ok_or(format!("patterns must be specified as hex digits: {value}"))?
+        }
+        'I' => {
+            args.interface = Some(match value.parse() {
+                Ok(addr) => Interface::Addr(addr),
+                Err(_) => Interface::Name(value.to_string()),
+            })
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+#[inline]
+fn seconds(value: &str) -> Option<Duration> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+}
+
+// hex digits, two for each byte
+fn pattern(value: &str) -> Option<Vec<u8>> {
+    if value.is_empty() || value.len() > 32 || !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(line: &str) -> Result<Option<Args>, String> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse() {
+        let args = parse_str("-qc3 -i 0.2 -W1 -t 5 -s 100 -p ff00 -I eth0 -6 -D -Ob ::1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            args,
+            Args {
+                count: Some(3),
+                interval: Duration::from_millis(200),
+                timeout: Some(Duration::from_secs(1)),
+                ttl: Some(5),
+                size: 100,
+                pattern: vec![0xff, 0],
+                interface: Some(Interface::Name(String::from("eth0"))),
+                version: IpVersion::V6,
+                quiet: true,
+                timestamps: true,
+                outstanding: true,
+                broadcast: true,
+                destination: String::from("::1"),
+                ..Args::default()
+            }
+        );
+        let args = parse_str("example.com --format=jsonl -w 2.5 -I 10.0.0.2")
+            .unwrap()
+            .unwrap();
+        assert_eq!(args.deadline, Some(Duration::from_millis(2500)));
+        assert_eq!(
+            args.interface,
+            Some(Interface::Addr("10.0.0.2".parse().unwrap()))
+        );
+        assert_eq!(args.size, 56);
+        assert_eq!(args.format, Format::Jsonl);
+        assert_eq!(
+            parse_str("--format csv ::1").unwrap().unwrap().format,
+            Format::Csv
+        );
+        let args = parse_str("--mtr --report --max-hops 8 -c 5 ::1")
+            .unwrap()
+            .unwrap();
+        assert!(args.mtr && args.report);
+        assert_eq!((args.max_hops, args.count), (8, Some(5)));
+        let args = parse_str("10.0.0.0/30 --sweep --rate 0 --file hosts --concurrency=4 ::1")
+            .unwrap()
+            .unwrap();
+        assert!(args.sweep && args.destination.is_empty());
+        assert_eq!(args.targets, ["10.0.0.0/30", "::1"]);
+        assert_eq!((args.rate, args.concurrency), (0, 4));
+        assert_eq!(args.files, ["hosts"]);
+        assert!(parse_str("--sweep").unwrap().unwrap().targets.is_empty());
+        let args = parse_str("--exporter [::1]:9427 -i 10 192.0.2.1 ::1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(args.exporter, Some("[::1]:9427".parse().unwrap()));
+        assert_eq!(args.targets, ["192.0.2.1", "::1"]);
+        assert!(parse_str("-h").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for line in [
+            "",
+            "-c",
+            "-c 0 ::1",
+            "-x ::1",
+            "-t 256 ::1",
+            "-s 65508 ::1",
+            "-p abc ::1",
+            "-i -1 ::1",
+            "::1 ::2",
+            "--format xml ::1",
+            "--format",
+            "--color ::1",
+            "--max-hops 0 ::1",
+            "--sweep --concurrency 0",
+            "--sweep --rate -1",
+            "--exporter localhost ::1",
+        ] {
+            assert!(parse_str(line).is_err(), "{line}");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::cli::args::Args;
+use crate::cli::ping::INTERRUPTED;
+use crate::cli::sweep;
+use rps_ping_lib::clock::{Clock, SystemClock};
+use rps_ping_lib::exporter::{Exporter, ExporterBuilder, ExporterTarget};
+use rps_ping_lib::transport::Transport;
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+// --exporter
+pub struct ExporterSession {
+    listen: SocketAddr,
+    targets: usize,
+    exporter: Exporter,
+}
+
+impl ExporterSession {
+    // `targets` are labelled with their name, `transport` and `clock` are None for sockets
+    // and the system clock
+    pub fn new(
+        args: Args,
+        targets: Vec<(String, IpAddr)>,
+        transport: Option<Arc<dyn Transport>>,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Result<Self, String> {
+        let clock = clock.unwrap_or(Arc::new(SystemClock));
+        let Some(listen) = args.exporter else {
+            return Err(String::from("usage error: --exporter needs an address"));
+        };
+        let addrs: Vec<IpAddr> = targets.iter().map(|(_, addr)| *addr).collect();
+        let builder = ExporterBuilder {
+            sweep: sweep::builder(&args, &addrs, transport, clock)?,
+            interval: args.interval,
+            targets: targets
+                .into_iter()
+                .map(|(name, addr)| ExporterTarget { name, addr })
+                .collect(),
+            ..Default::default()
+        };
+        Ok(ExporterSession {
+            listen,
+            targets: addrs.len(),
+            exporter: builder.build(),
+        })
+    }
+
+    // until Ctrl-C, 0 then, 2 when the server failed
+    pub fn run(&self, out: &mut dyn Write) -> io::Result<i32> {
+        let listener = TcpListener::bind(self.listen)?;
+        writeln!(
+            out,
+            "exporting {} targets on http://{}/metrics",
+            self.targets,
+            listener.local_addr()?
+        )?;
+        out.flush()?;
+        std::thread::scope(|scope| {
+            let server = scope.spawn(|| self.exporter.run(&listener));
+            while !server.is_finished() && !INTERRUPTED.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            self.exporter.stop();
+            match server.join() {
+                Ok(Ok(())) => Ok(0),
+                Ok(Err(e)) => Err(e),
+                Err(_) => Ok(2),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rps_ping_lib::transport::MockTransport;
+
+    #[test]
+    fn test_exporter_session() {
+        let args = Args {
+            exporter: Some("127.0.0.1:0".parse().unwrap()),
+            targets: vec![
+                String::from("192.0.2.1-2"),
+                String::from("::1"),
+                String::from("localhost"),
+            ],
+            ..Args::default()
+        };
+        let targets = sweep::named_targets(&args, &mut io::empty()).unwrap();
+        let transport = MockTransport::echo(Duration::from_millis(1));
+        let session = ExporterSession::new(args, targets, Some(Arc::new(transport)), None).unwrap();
+        session.exporter.round();
+        let text = session.exporter.render(false);
+        for labels in [
+            r#"target="192.0.2.2",address="192.0.2.2",family="ipv4""#,
+            r#"target="::1",address="::1",family="ipv6""#,
+        ] {
+            assert!(
+                text.contains(&format!("rps_ping_up{{{labels}}} 1\n")),
+                "{text}"
+            );
+        }
+        // a name is the label, not the address it resolved to
+        assert!(
+            text.contains(r#"rps_ping_up{target="localhost",address=""#),
+            "{text}"
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use crate::cli::args::Args;
+use crate::cli::output::{Format, RecordWriter, Value};
+use crate::cli::ping::{self, Builder, INTERRUPTED};
+use rps_ping_lib::clock::{Clock, SystemClock};
+use rps_ping_lib::traceroute::{HopProbe, Traceroute};
+use rps_ping_lib::transport::Transport;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime};
+
+// --mtr, traceroute rounds one after another with statistics of every hop
+pub struct Mtr {
+    args: Args,
+    traceroute: Traceroute,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Default)]
+struct HopStatistics {
+    sent: u64,
+    received: u64,
+    // in milliseconds
+    last: f64,
+    best: f64,
+    worst: f64,
+    sum: f64,
+    sum_squares: f64,
+    // every responder, in the order they were seen
+    addresses: Vec<IpAddr>,
+}
+
+impl HopStatistics {
+    fn add(&mut self, probe: &HopProbe) {
+        self.sent += 1;
+        if let Some(ip) = probe.ip()
+            && !self.addresses.contains(&ip)
+        {
+            self.addresses.push(ip);
+        }
+        let Some(duration) = probe.duration() else {
+            return;
+        };
+        let rtt = duration.as_secs_f64() * 1000.0;
+        if self.received == 0 || rtt < self.best {
+            self.best = rtt;
+        }
+        self.worst = self.worst.max(rtt);
+        self.last = rtt;
+        self.sum += rtt;
+        self.sum_squares += rtt * rtt;
+        self.received += 1;
+    }
+
+    #[inline]
+    fn loss_percent(&self) -> f64 {
+        match self.sent {
+            0 => 0.0,
+            sent => (sent - self.received) as f64 * 100.0 / sent as f64,
+        }
+    }
+
+    #[inline]
+    fn avg(&self) -> f64 {
+        match self.received {
+            0 => 0.0,
+            received => self.sum / received as f64,
+        }
+    }
+
+    #[inline]
+    fn stdev(&self) -> f64 {
+        match self.received {
+            0 => 0.0,
+            received => {
+                let avg = self.avg();
+                (self.sum_squares / received as f64 - avg * avg)
+                    .max(0.0)
+                    .sqrt()
+            }
+        }
+    }
+}
+
+// the hops of every round so far
+#[derive(Debug, Default)]
+struct Table {
+    hops: Vec<HopStatistics>,
+    // hops of the latest round, the route may have been longer before
+    shown: usize,
+    // hops probed so far in the running round
+    probed: usize,
+    rounds: u64,
+    reached: bool,
+}
+
+impl Table {
+    #[inline]
+    fn start_round(&mut self) {
+        self.probed = 0;
+        self.rounds += 1;
+    }
+
+    // the hops of the previous round stay shown until this one gets past them or ends
+    fn add(&mut self, probe: &HopProbe) {
+        let index = probe.ttl as usize - 1;
+        if self.hops.len() <= index {
+            self.hops.resize_with(index + 1, HopStatistics::default);
+        }
+        self.hops[index].add(probe);
+        self.reached |= probe.ip().is_some() && probe.is_last() && probe.duration().is_some();
+        self.probed = probe.ttl as usize;
+        self.shown = self.shown.max(self.probed);
+    }
+
+    #[inline]
+    fn end_round(&mut self) {
+        self.shown = self.probed;
+    }
+
+    // mtr --report, without the Start line
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "HOST: {:<28}{:>6} {:>5} {:>6} {:>5} {:>5} {:>5} {:>5}",
+            hostname(),
+            "Loss%",
+            "Snt",
+            "Last",
+            "Avg",
+            "Best",
+            "Wrst",
+            "StDev"
+        )];
+        for (i, hop) in self.hops[..self.shown].iter().enumerate() {
+            let first = hop
+                .addresses
+                .first()
+                .map_or(String::from("???"), |ip| ip.to_string());
+            lines.push(format!(
+                "{:>3}.|-- {first:<25} {:>5.1}% {:>5} {:>6.1} {:>5.1} {:>5.1} {:>5.1} {:>5.1}",
+                i + 1,
+                hop.loss_percent(),
+                hop.sent,
+                hop.last,
+                hop.avg(),
+                hop.best,
+                hop.worst,
+                hop.stdev()
+            ));
+            // more than one router answered for this TTL, e.g. load balancing
+            for ip in hop.addresses.iter().skip(1) {
+                lines.push(format!("    |-- {ip}"));
+            }
+        }
+        lines
+    }
+}
+
+impl Mtr {
+    // `transport` and `clock` are None for sockets and the system clock
+    pub fn new(
+        args: Args,
+        target: IpAddr,
+        transport: Option<Arc<dyn Transport>>,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Result<Self, String> {
+        let clock = clock.unwrap_or(Arc::new(SystemClock));
+        let traceroute = match (
+            ping::builder(&args, target, transport, clock.clone())?,
+            target,
+        ) {
+            (Builder::V4(builder), IpAddr::V4(target)) => Traceroute::v4(builder, target),
+            (Builder::V6(builder), IpAddr::V6(target)) => Traceroute::v6(builder, target),
+            _ => unreachable!(),
+        };
+        let traceroute = traceroute.with_max_hops(args.max_hops);
+        Ok(Mtr {
+            args,
+            traceroute,
+            clock,
+        })
+    }
+
+    // `live` redraws the table in place after every hop (a terminal, without --report),
+    // the report is printed at the end, returns 0 when the target answered, 1 if not, 2 on errors
+    pub fn run(&self, out: &mut dyn Write, live: bool) -> io::Result<i32> {
+        let args = &self.args;
+        let started = SystemTime::now();
+        let start = self.clock.now();
+        let end = args.deadline.map(|deadline| start + deadline);
+        let mut table = Table::default();
+        let mut drawn = 0;
+        let mut code = None;
+        loop {
+            let now = self.clock.now();
+            if INTERRUPTED.load(Ordering::Relaxed) || end.is_some_and(|end| now >= end) {
+                break;
+            }
+            let next = now + args.interval;
+            // TTL 1, 2, ... as Traceroute::round does, one hop at a time to redraw after each
+            table.start_round();
+            for ttl in 1..=self.traceroute.max_hops() {
+                let probe = match self.traceroute.probe(ttl) {
+                    Ok(probe) => probe,
+                    Err(e) => {
+                        eprintln!("ping: {e}");
+                        code = Some(2);
+                        break;
+                    }
+                };
+                table.add(&probe);
+                if live {
+                    drawn = self.redraw(out, &table, drawn)?;
+                }
+                if probe.is_last() || INTERRUPTED.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+            table.end_round();
+            if code.is_some() {
+                break;
+            }
+            if args.count.is_some_and(|count| table.rounds >= count) {
+                break;
+            }
+            ping::sleep_until(self.clock.as_ref(), next);
+        }
+        if live && drawn > 0 {
+            // the report takes the place of the live table
+            write!(out, "\x1b[{drawn}A\x1b[J")?;
+        }
+        match args.format {
+            Format::Text => {
+                writeln!(out, "Start: {}", utc(started))?;
+                for line in table.lines() {
+                    writeln!(out, "{line}")?;
+                }
+            }
+            format => self.records(out, format, &table)?,
+        }
+        Ok(code.unwrap_or(if table.reached { 0 } else { 1 }))
+    }
+
+    // returns how many lines are on the screen now
+    fn redraw(&self, out: &mut dyn Write, table: &Table, drawn: usize) -> io::Result<usize> {
+        if drawn > 0 {
+            write!(out, "\x1b[{drawn}A\x1b[J")?;
+        }
+        let mut lines = vec![format!(
+            "mtr to {} ({}), round {}, Ctrl-C to stop",
+            self.args.destination,
+            self.traceroute.target(),
+            table.rounds
+        )];
+        lines.extend(table.lines());
+        for line in &lines {
+            writeln!(out, "{line}")?;
+        }
+        out.flush()?;
+        Ok(lines.len())
+    }
+
+    fn records(&self, out: &mut dyn Write, format: Format, table: &Table) -> io::Result<()> {
+        let mut writer = RecordWriter::new(format, COLUMNS, out)?;
+        let target = Value::Str(self.args.destination.clone());
+        let address = Value::Str(self.traceroute.target().to_string());
+        for (i, hop) in table.hops[..table.shown].iter().enumerate() {
+            let addresses: Vec<String> = hop.addresses.iter().map(|ip| ip.to_string()).collect();
+            let rtt = |value: f64| match hop.received {
+                0 => Value::Null,
+                _ => Value::Float((value * 1000.0).round() / 1000.0),
+            };
+            writer.write(&[
+                ("type", Value::Str(String::from("hop"))),
+                ("target", target.clone()),
+                ("address", address.clone()),
+                ("hop", Value::Int(i as u64 + 1)),
+                ("addresses", Value::Str(addresses.join(" "))),
+                ("loss_percent", Value::Float(hop.loss_percent())),
+                ("sent", Value::Int(hop.sent)),
+                ("last_ms", rtt(hop.last)),
+                ("avg_ms", rtt(hop.avg())),
+                ("best_ms", rtt(hop.best)),
+                ("worst_ms", rtt(hop.worst)),
+                ("stdev_ms", rtt(hop.stdev())),
+            ])?;
+        }
+        writer.finish()
+    }
+}
+
+// the fields of --mtr --format json, jsonl and csv, addresses are separated by spaces
+const COLUMNS: &[&str] = &[
+    "type",
+    "target",
+    "address",
+    "hop",
+    "addresses",
+    "loss_percent",
+    "sent",
+    "last_ms",
+    "avg_ms",
+    "best_ms",
+    "worst_ms",
+    "stdev_ms",
+];
+
+fn hostname() -> String {
+    let mut buff = [0_u8; 256];
+    let err = unsafe { libc::gethostname(buff.as_mut_ptr() as *mut libc::c_char, buff.len()) };
+    if err == -1 {
+        return String::from("localhost");
+    }
+    let len = buff
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(buff.len());
+    String::from_utf8_lossy(&buff[..len]).into_owned()
+}
+
+// 2026-01-31T12:00:00+0000
+fn utc(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}+0000",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rps_ping_lib::transport::{Hop, Latency, Link, SimulatedNetwork};
+
+    // us -1ms- 10.0.0.1 -2ms- (silent) -3ms- 198.51.100.7
+    fn network() -> SimulatedNetwork {
+        let network = SimulatedNetwork::new(0);
+        let link = |ms| Link::new(Latency::Fixed(Duration::from_millis(ms)));
+        let mut silent = Hop::new("10.0.1.1".parse().unwrap(), link(2));
+        silent.responds = false;
+        network.add_route(vec![
+            Hop::new("10.0.0.1".parse().unwrap(), link(1)),
+            silent,
+            Hop::new("198.51.100.7".parse().unwrap(), link(3)),
+        ]);
+        network
+    }
+
+    fn run(args: Args) -> (i32, String) {
+        run_live(args, false)
+    }
+
+    fn run_live(args: Args, live: bool) -> (i32, String) {
+        let network = network();
+        let args = Args {
+            count: Some(3),
+            timeout: Some(Duration::from_millis(500)),
+            mtr: true,
+            destination: String::from("198.51.100.7"),
+            ..args
+        };
+        let mtr = Mtr::new(
+            args,
+            "198.51.100.7".parse().unwrap(),
+            Some(Arc::new(network.clone())),
+            Some(network.clock()),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let code = mtr.run(&mut out, live).unwrap();
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_mtr_report() {
+        let (code, out) = run(Args::default());
+        assert_eq!(code, 0);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("Start: "));
+        assert!(lines[1].ends_with("Loss%   Snt   Last   Avg  Best  Wrst StDev"));
+        assert_eq!(
+            lines[2],
+            "  1.|-- 10.0.0.1                    0.0%     3    2.0   2.0   2.0   2.0   0.0"
+        );
+        assert_eq!(
+            lines[3],
+            "  2.|-- ???                       100.0%     3    0.0   0.0   0.0   0.0   0.0"
+        );
+        assert_eq!(
+            lines[4],
+            "  3.|-- 198.51.100.7                0.0%     3   12.0  12.0  12.0  12.0   0.0"
+        );
+        // the columns are right aligned
+        assert_eq!(
+            lines[1].find("Loss%").unwrap() + 5,
+            lines[2].find("0.0%").unwrap() + 4
+        );
+    }
+
+    #[test]
+    fn test_mtr_records() {
+        let (_, out) = run(Args {
+            format: Format::Jsonl,
+            ..Args::default()
+        });
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            r#"{"type":"hop","target":"198.51.100.7","address":"198.51.100.7","hop":2,"addresses":"","loss_percent":100,"sent":3,"last_ms":null,"avg_ms":null,"best_ms":null,"worst_ms":null,"stdev_ms":null}"#
+        );
+        assert!(lines[2].contains(r#""hop":3,"addresses":"198.51.100.7","loss_percent":0,"sent":3,"last_ms":12,"avg_ms":12"#));
+    }
+
+    #[test]
+    fn test_mtr_live() {
+        let (code, out) = run_live(Args::default(), true);
+        assert_eq!(code, 0);
+        // redrawn after each of the 3 hops, the silent one too, in each of the 3 rounds
+        let rounds: Vec<&str> = out
+            .lines()
+            .filter_map(|line| line.split(", round ").nth(1))
+            .map(|rest| &rest[..1])
+            .collect();
+        assert_eq!(rounds, ["1", "1", "1", "2", "2", "2", "3", "3", "3"]);
+    }
+
+    #[test]
+    fn test_utc() {
+        assert_eq!(utc(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00+0000");
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(utc(time), "2024-02-29T12:34:56+0000");
+    }
+}
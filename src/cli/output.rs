@@ -0,0 +1,209 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+// --format, text is what iputils prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Jsonl,
+    Csv,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "invalid format: '{text}', use text, json, jsonl or csv"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(u64),
+    Float(f64),
+    Str(String),
+}
+
+impl From<Option<u64>> for Value {
+    #[inline]
+    fn from(value: Option<u64>) -> Self {
+        value.map_or(Value::Null, Value::Int)
+    }
+}
+
+impl From<Option<f64>> for Value {
+    #[inline]
+    fn from(value: Option<f64>) -> Self {
+        value.map_or(Value::Null, Value::Float)
+    }
+}
+
+impl From<Option<String>> for Value {
+    #[inline]
+    fn from(value: Option<String>) -> Self {
+        value.map_or(Value::Null, Value::Str)
+    }
+}
+
+// writes records with stable field names as one JSON array, JSON Lines or CSV,
+// `columns` is the CSV header, the fields a record doesn't have stay empty
+pub struct RecordWriter<'a> {
+    format: Format,
+    columns: &'static [&'static str],
+    out: &'a mut dyn Write,
+    written: usize,
+}
+
+impl<'a> RecordWriter<'a> {
+    pub fn new(
+        format: Format,
+        columns: &'static [&'static str],
+        out: &'a mut dyn Write,
+    ) -> io::Result<Self> {
+        match format {
+            Format::Json => write!(out, "[")?,
+            Format::Csv => writeln!(out, "{}", columns.join(","))?,
+            Format::Text | Format::Jsonl => {}
+        }
+        Ok(RecordWriter {
+            format,
+            columns,
+            out,
+            written: 0,
+        })
+    }
+
+    pub fn write(&mut self, fields: &[(&str, Value)]) -> io::Result<()> {
+        match self.format {
+            Format::Json | Format::Jsonl | Format::Text => {
+                let mut line = String::from("{");
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        line.push(',');
+                    }
+                    json_string(&mut line, name);
+                    line.push(':');
+                    json_value(&mut line, value);
+                }
+                line.push('}');
+                match self.format {
+                    Format::Json if self.written > 0 => write!(self.out, ",\n{line}")?,
+                    Format::Json => write!(self.out, "\n{line}")?,
+                    _ => writeln!(self.out, "{line}")?,
+                }
+            }
+            Format::Csv => {
+                let row: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        fields
+                            .iter()
+                            .find(|(name, _)| name == column)
+                            .map_or(String::new(), |(_, value)| csv_value(value))
+                    })
+                    .collect();
+                writeln!(self.out, "{}", row.join(","))?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    // closes the JSON array
+    pub fn finish(self) -> io::Result<()> {
+        if self.format == Format::Json {
+            writeln!(self.out, "\n]")?;
+        }
+        self.out.flush()
+    }
+}
+
+fn json_value(text: &mut String, value: &Value) {
+    match value {
+        Value::Null => text.push_str("null"),
+        Value::Int(value) => {
+            let _ = write!(text, "{value}");
+        }
+        Value::Float(value) if value.is_finite() => {
+            let _ = write!(text, "{value}");
+        }
+        Value::Float(_) => text.push_str("null"),
+        Value::Str(value) => json_string(text, value),
+    }
+}
+
+fn json_string(text: &mut String, value: &str) {
+    text.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(text, "\\u{:04x}", c as u32);
+            }
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+}
+
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Int(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::Str(value) if value.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        }
+        Value::Str(value) => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(format: Format) -> String {
+        let mut out = Vec::new();
+        let mut writer = RecordWriter::new(format, &["a", "b", "c"], &mut out).unwrap();
+        writer
+            .write(&[
+                ("a", Value::Int(1)),
+                ("b", Value::Str(String::from("x,\"y\"\n"))),
+            ])
+            .unwrap();
+        writer
+            .write(&[("c", Value::Float(0.5)), ("a", Value::Null)])
+            .unwrap();
+        writer.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_records() {
+        assert_eq!(
+            records(Format::Jsonl),
+            "{\"a\":1,\"b\":\"x,\\\"y\\\"\\n\"}\n{\"c\":0.5,\"a\":null}\n"
+        );
+        assert_eq!(
+            records(Format::Json),
+            "[\n{\"a\":1,\"b\":\"x,\\\"y\\\"\\n\"},\n{\"c\":0.5,\"a\":null}\n]\n"
+        );
+        assert_eq!(records(Format::Csv), "a,b,c\n1,\"x,\"\"y\"\"\n\",\n,,0.5\n");
+    }
+}
@@ -0,0 +1,1116 @@
+use crate::cli::args::{Args, Interface, IpVersion};
+use crate::cli::output::{Format, RecordWriter, Value};
+use rps_ping_lib::clock::{Clock, SystemClock};
+use rps_ping_lib::statistics::{DelayVariation, Histogram, Loss, Z_95};
+use rps_ping_lib::transport::Transport;
+use rps_ping_lib::{
+    ExtendedError, LinuxError, Payload, PingError, PingV4, PingV4Builder, PingV6, PingV6Builder,
+    SharedError,
+};
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+// set on SIGINT, the session stops and still prints its statistics
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// "ff02::1%eth0" is the address and the interface of its scope
+#[inline]
+pub fn split_scope(destination: &str) -> (&str, Option<&str>) {
+    match destination.split_once('%') {
+        Some((addr, scope)) => (addr, Some(scope)),
+        None => (destination, None),
+    }
+}
+
+// an interface name or index
+fn scope_id(scope: &str) -> Result<u32, String> {
+    if let Ok(index) = scope.parse() {
+        return Ok(index);
+    }
+    let name = std::ffi::CString::new(scope).map_err(|_| format!("{scope}: no such interface"))?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(format!("{scope}: no such interface")),
+        index => Ok(index),
+    }
+}
+
+// 255.255.255.255 or the broadcast address of a local interface, as ping(8) warns about
+fn is_broadcast(addr: Ipv4Addr) -> bool {
+    if addr.is_broadcast() {
+        return true;
+    }
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut list) } == -1 {
+        return false;
+    }
+    let mut found = false;
+    let mut entry = list;
+    while let Some(ifaddr) = unsafe { entry.as_ref() } {
+        let broadcast = ifaddr.ifa_ifu as *const libc::sockaddr_in;
+        if ifaddr.ifa_flags & libc::IFF_BROADCAST as u32 != 0
+            && !broadcast.is_null()
+            && unsafe { (*broadcast).sin_family } == libc::AF_INET as libc::sa_family_t
+            && unsafe { (*broadcast).sin_addr.s_addr } == u32::from_ne_bytes(addr.octets())
+        {
+            found = true;
+            break;
+        }
+        entry = ifaddr.ifa_next;
+    }
+    unsafe { libc::freeifaddrs(list) };
+    found
+}
+
+// an IP literal, or the first address of the name in the wanted family
+pub fn resolve(destination: &str, version: IpVersion) -> Result<IpAddr, String> {
+    let (destination, _) = split_scope(destination);
+    let addrs: Vec<IpAddr> = match destination.parse() {
+        Ok(addr) => vec![addr],
+        Err(_) => (destination, 0)
+            .to_socket_addrs()
+            .map_err(|e| format!("{destination}: {e}"))?
+            .map(|addr| addr.ip())
+            .collect(),
+    };
+    addrs
+        .into_iter()
+        .find(|addr| match version {
+            IpVersion::Any => true,
+            IpVersion::V4 => addr.is_ipv4(),
+            IpVersion::V6 => addr.is_ipv6(),
+        })
+        .ok_or(format!(
+            "{destination}: Address family for hostname not supported"
+        ))
+}
+
+enum Pinger {
+    V4(PingV4),
+    V6(PingV6),
+}
+
+// what ping_seq returned, for both families
+pub struct Echo {
+    pub ip: IpAddr,
+    pub duration: Duration,
+    pub ttl: Option<u8>,
+    pub size: usize,
+}
+
+impl Pinger {
+    fn ping_seq(&self, target: IpAddr, sequence: u16) -> Result<Echo, PingError> {
+        match (self, target) {
+            (Pinger::V4(ping), IpAddr::V4(target)) => {
+                ping.ping_seq(target, sequence).map(|reply| Echo {
+                    ip: reply.ip.into(),
+                    duration: reply.duration,
+                    ttl: reply.ttl,
+                    size: reply.size,
+                })
+            }
+            (Pinger::V6(ping), IpAddr::V6(target)) => {
+                ping.ping_seq(target, sequence).map(|reply| Echo {
+                    ip: reply.ip.into(),
+                    duration: reply.duration,
+                    ttl: reply.ttl,
+                    size: reply.size,
+                })
+            }
+            _ => Err(LinuxError::MissRespondAddr.into()),
+        }
+    }
+
+    // every responder of a broadcast address or a multicast group,
+    // and the ones whose reply had a wrong checksum
+    fn ping_all(
+        &self,
+        target: IpAddr,
+        sequence: u16,
+    ) -> Result<(Vec<Echo>, Vec<IpAddr>), PingError> {
+        match (self, target) {
+            (Pinger::V4(ping), IpAddr::V4(target)) => ping.ping_all(target, sequence).map(|all| {
+                let replies = all
+                    .replies
+                    .into_iter()
+                    .map(|reply| Echo {
+                        ip: reply.ip.into(),
+                        duration: reply.duration,
+                        ttl: reply.ttl,
+                        size: reply.size,
+                    })
+                    .collect();
+                (
+                    replies,
+                    all.corrupted.into_iter().map(IpAddr::from).collect(),
+                )
+            }),
+            (Pinger::V6(ping), IpAddr::V6(target)) => ping.ping_all(target, sequence).map(|all| {
+                let replies = all
+                    .replies
+                    .into_iter()
+                    .map(|reply| Echo {
+                        ip: reply.ip.into(),
+                        duration: reply.duration,
+                        ttl: reply.ttl,
+                        size: reply.size,
+                    })
+                    .collect();
+                (
+                    replies,
+                    all.corrupted.into_iter().map(IpAddr::from).collect(),
+                )
+            }),
+            _ => Err(LinuxError::MissRespondAddr.into()),
+        }
+    }
+}
+
+pub struct Session {
+    args: Args,
+    target: IpAddr,
+    ping: Pinger,
+    clock: Arc<dyn Clock>,
+    broadcast: bool, // the target is a broadcast address
+}
+
+// a builder of the family of the target
+pub enum Builder {
+    V4(PingV4Builder),
+    V6(PingV6Builder),
+}
+
+// the builder with the options of `args`, also used by the other modes
+pub fn builder(
+    args: &Args,
+    target: IpAddr,
+    transport: Option<Arc<dyn Transport>>,
+    clock: Arc<dyn Clock>,
+) -> Result<Builder, String> {
+    // the library waits for one reply at a time, so by default not past the next send
+    let timeout = args
+        .timeout
+        .unwrap_or(args.interval.max(Duration::from_secs(1)));
+    let payload = Some(Payload {
+        size: args.size,
+        pattern: args.pattern.clone(),
+    });
+    let (bind_addr, interface) = match &args.interface {
+        Some(Interface::Addr(addr)) if addr.is_ipv4() != target.is_ipv4() => {
+            return Err(format!("{addr}: address family of -I doesn't match"));
+        }
+        Some(Interface::Addr(addr)) => (Some(*addr), None),
+        Some(Interface::Name(name)) => (None, Some(name.clone())),
+        None => (None, None),
+    };
+    Ok(match target {
+        IpAddr::V4(_) => {
+            let mut builder = PingV4Builder::new(timeout);
+            builder.deadline = args.deadline;
+            builder.ttl = args.ttl;
+            builder.bind_addr = bind_addr.and_then(|addr| match addr {
+                IpAddr::V4(addr) => Some(addr),
+                IpAddr::V6(_) => None,
+            });
+            builder.transport = transport;
+            builder.clock = Some(clock);
+            builder.payload = payload;
+            builder.broadcast = args.broadcast;
+            #[cfg(target_os = "linux")]
+            {
+                builder.interface = interface;
+            }
+            Builder::V4(builder)
+        }
+        IpAddr::V6(_) => {
+            let mut builder = PingV6Builder::new(timeout);
+            builder.deadline = args.deadline;
+            builder.ttl = args.ttl;
+            builder.bind_addr = bind_addr.and_then(|addr| match addr {
+                IpAddr::V6(addr) => Some(addr),
+                IpAddr::V4(_) => None,
+            });
+            if let Some(scope) = split_scope(&args.destination).1 {
+                builder.scope_id_option = Some(scope_id(scope)?);
+            }
+            builder.transport = transport;
+            builder.clock = Some(clock);
+            builder.payload = payload;
+            #[cfg(target_os = "linux")]
+            {
+                builder.interface = interface;
+            }
+            Builder::V6(builder)
+        }
+    })
+}
+
+impl Session {
+    // `transport` and `clock` are None for sockets and the system clock
+    pub fn new(
+        args: Args,
+        target: IpAddr,
+        transport: Option<Arc<dyn Transport>>,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Result<Self, String> {
+        let clock = clock.unwrap_or(Arc::new(SystemClock));
+        let ping = match builder(&args, target, transport, clock.clone())? {
+            Builder::V4(builder) => Pinger::V4(builder.build()),
+            Builder::V6(builder) => Pinger::V6(builder.build()),
+        };
+        Ok(Session {
+            args,
+            target,
+            ping,
+            clock,
+            broadcast: matches!(target, IpAddr::V4(addr) if is_broadcast(addr)),
+        })
+    }
+
+    // writes in the --format of the args, returns the exit code:
+    // 0 with a reply, 1 without any, 2 when a ping failed for another reason
+    pub fn run(&self, out: &mut dyn Write) -> io::Result<i32> {
+        match self.args.format {
+            Format::Text => {
+                if self.broadcast {
+                    eprintln!("WARNING: pinging broadcast address");
+                }
+                self.run_report(&mut TextReport::new(out, &self.args))
+            }
+            format => {
+                let writer = RecordWriter::new(format, COLUMNS, out)?;
+                let mut report = RecordReport {
+                    writer: Some(writer),
+                    args: &self.args,
+                    target: self.target,
+                };
+                self.run_report(&mut report)
+            }
+        }
+    }
+
+    pub fn run_report(&self, report: &mut dyn Report) -> io::Result<i32> {
+        let args = &self.args;
+        report.start(self.target)?;
+        let start = self.clock.now();
+        let end = args.deadline.map(|deadline| start + deadline);
+        let mut statistics = Statistics::default();
+        let mut sequence: u16 = 1;
+        let code = loop {
+            let now = self.clock.now();
+            if INTERRUPTED.load(Ordering::Relaxed) || end.is_some_and(|end| now >= end) {
+                break None;
+            }
+            let next = now + args.interval;
+            statistics.transmitted += 1;
+            let probes = self.send(sequence);
+            for probe in &probes {
+                match probe {
+                    Probe::Reply(echo) => statistics.add(echo.duration),
+                    Probe::Duplicate(_) => statistics.duplicates += 1,
+                    Probe::BadChecksum(_) => statistics.corrupted += 1,
+                    Probe::IcmpError(_) | Probe::Unreachable(_) | Probe::Error(_) => {
+                        statistics.errors += 1
+                    }
+                    Probe::Timeout | Probe::Failed(_) => {}
+                }
+                report.probe(sequence, probe)?;
+            }
+            if let [Probe::Failed(_)] = probes[..] {
+                break Some(2);
+            }
+            // by the first reply, like the statistics
+            statistics
+                .variation
+                .record(probes.iter().find_map(|probe| match probe {
+                    Probe::Reply(echo) => Some(echo.duration),
+                    _ => None,
+                }));
+            // -c counts the probes, or the replies when -w gives the end, as iputils does
+            let counted = match end {
+                Some(_) => statistics.received,
+                None => statistics.transmitted,
+            };
+            if args.count.is_some_and(|count| counted >= count) {
+                break None;
+            }
+            sleep_until(self.clock.as_ref(), next);
+            sequence = sequence.wrapping_add(1);
+        };
+        statistics.time = self.clock.now() - start;
+        report.finish(&statistics)?;
+        Ok(code.unwrap_or(if statistics.received > 0 { 0 } else { 1 }))
+    }
+
+    // a broadcast address or multicast group answers with every member,
+    // the replies after the first are duplicates
+    fn send(&self, sequence: u16) -> Vec<Probe> {
+        let all = match self.target {
+            IpAddr::V4(target) => self.broadcast || target.is_multicast(),
+            IpAddr::V6(target) => target.is_multicast(),
+        };
+        let result = match all {
+            true => self
+                .ping
+                .ping_all(self.target, sequence)
+                .map(|(echoes, corrupted)| {
+                    let mut probes = Vec::new();
+                    for (i, echo) in echoes.into_iter().enumerate() {
+                        probes.push(match i {
+                            0 => Probe::Reply(echo),
+                            _ => Probe::Duplicate(echo),
+                        });
+                    }
+                    probes.extend(corrupted.into_iter().map(Probe::BadChecksum));
+                    if probes.is_empty() {
+                        probes.push(Probe::Timeout);
+                    }
+                    probes
+                }),
+            false => self
+                .ping
+                .ping_seq(self.target, sequence)
+                .map(|echo| vec![Probe::Reply(echo)]),
+        };
+        match result {
+            Ok(probes) => probes,
+            Err(PingError::LinuxError(LinuxError::SendtoFailed(libc::EACCES)))
+                if self.broadcast && !self.args.broadcast =>
+            {
+                vec![Probe::Failed(String::from(
+                    "Do you want to ping broadcast? Then -b. If not, check your local firewall rules",
+                ))]
+            }
+            Err(PingError::SharedError(SharedError::Timeout)) => vec![Probe::Timeout],
+            Err(PingError::LinuxError(LinuxError::IcmpError(e))) => vec![Probe::IcmpError(e)],
+            Err(PingError::SharedError(SharedError::BadChecksum)) => {
+                vec![Probe::BadChecksum(self.target)]
+            }
+            Err(PingError::SharedError(SharedError::Unreachable)) => {
+                vec![Probe::Unreachable(strerror(libc::ENETUNREACH))]
+            }
+            Err(PingError::LinuxError(LinuxError::SendtoFailed(
+                errno @ (libc::ENETUNREACH | libc::EHOSTUNREACH),
+            ))) => vec![Probe::Unreachable(format!("sendmsg: {}", strerror(errno)))],
+            // a reply that couldn't be read, the next probe may do better
+            Err(
+                e @ PingError::LinuxError(
+                    LinuxError::ResolveRecvFailed
+                    | LinuxError::MissRespondAddr
+                    | LinuxError::RecvFailed(_),
+                ),
+            ) => vec![Probe::Error(e.to_string())],
+            Err(e) => vec![Probe::Failed(e.to_string())],
+        }
+    }
+}
+
+// in slices, so Ctrl-C doesn't wait for the whole interval
+pub fn sleep_until(clock: &dyn Clock, until: Instant) {
+    while !INTERRUPTED.load(Ordering::Relaxed) && clock.now() < until {
+        let slice = clock.now() + Duration::from_millis(100);
+        clock.sleep_until(until.min(slice));
+    }
+}
+
+// what one echo request got
+pub enum Probe {
+    Reply(Echo),
+    // another reply to the same probe, e.g. from a second member of a multicast group
+    Duplicate(Echo),
+    Timeout,
+    IcmpError(ExtendedError),
+    // an answer from this address whose checksum is wrong, not counted as received
+    BadChecksum(IpAddr),
+    // no route for the probe, e.g. ENETUNREACH, the session goes on
+    Unreachable(String),
+    // the probe failed on its own, e.g. an unreadable reply, the session goes on
+    Error(String),
+    // the ping itself failed, e.g. no privilege, the session stops
+    Failed(String),
+}
+
+// where run_report puts the probes and the summary
+pub trait Report {
+    fn start(&mut self, target: IpAddr) -> io::Result<()>;
+
+    fn probe(&mut self, sequence: u16, probe: &Probe) -> io::Result<()>;
+
+    fn finish(&mut self, statistics: &Statistics) -> io::Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct Statistics {
+    pub transmitted: u64,
+    pub received: u64,
+    pub duplicates: u64,
+    pub corrupted: u64, // replies with a bad checksum
+    pub errors: u64,
+    pub time: Duration,
+    pub histogram: Histogram, // every RTT, for min/avg/max/mdev, the percentiles and PDV
+    pub variation: DelayVariation,
+}
+
+impl Statistics {
+    #[inline]
+    pub fn add(&mut self, duration: Duration) {
+        self.received += 1;
+        self.histogram.record(duration);
+    }
+
+    #[inline]
+    pub fn loss_percent(&self) -> f64 {
+        match self.transmitted {
+            0 => 0.0,
+            transmitted => (transmitted - self.received) as f64 * 100.0 / transmitted as f64,
+        }
+    }
+
+    // RFC 3550 jitter, mean and largest RFC 5481 IPDV and the PDV at 99.9% in milliseconds,
+    // None below two replies, the IPDV also without two in a row
+    pub fn variation(&self) -> Option<(f64, Option<[f64; 2]>, f64)> {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let jitter = self.variation.jitter()?;
+        let ipdv = self.variation.ipdv().map(|(avg, max)| [ms(avg), ms(max)]);
+        Some((ms(jitter), ipdv, ms(self.histogram.pdv(99.9)?)))
+    }
+
+    // min, avg, max and mdev in milliseconds, None without replies
+    pub fn rtt(&self) -> Option<[f64; 4]> {
+        let histogram = &self.histogram;
+        let rtt = [
+            histogram.min()?,
+            histogram.mean()?,
+            histogram.max()?,
+            histogram.mdev()?,
+        ];
+        Some(rtt.map(|duration| duration.as_secs_f64() * 1000.0))
+    }
+}
+
+// what iputils prints
+pub struct TextReport<'a> {
+    out: &'a mut dyn Write,
+    args: &'a Args,
+}
+
+impl<'a> TextReport<'a> {
+    #[inline]
+    pub fn new(out: &'a mut dyn Write, args: &'a Args) -> Self {
+        TextReport { out, args }
+    }
+
+    // -D, the wall clock time of the line
+    fn prefix(&mut self) -> io::Result<()> {
+        if self.args.timestamps {
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO);
+            write!(self.out, "[{}.{:06}] ", now.as_secs(), now.subsec_micros())?;
+        }
+        Ok(())
+    }
+}
+
+impl Report for TextReport<'_> {
+    fn start(&mut self, target: IpAddr) -> io::Result<()> {
+        let args = self.args;
+        match target {
+            IpAddr::V4(_) => writeln!(
+                self.out,
+                "PING {} ({target}) {}({}) bytes of data.",
+                args.destination,
+                args.size,
+                args.size + 28
+            ),
+            IpAddr::V6(_) => writeln!(
+                self.out,
+                "PING {} ({target}) {} data bytes",
+                args.destination, args.size
+            ),
+        }
+    }
+
+    fn probe(&mut self, sequence: u16, probe: &Probe) -> io::Result<()> {
+        if let Probe::Unreachable(e) | Probe::Error(e) | Probe::Failed(e) = probe {
+            eprintln!("ping: {e}");
+            return Ok(());
+        }
+        if self.args.quiet || (matches!(probe, Probe::Timeout) && !self.args.outstanding) {
+            return Ok(());
+        }
+        self.prefix()?;
+        match probe {
+            Probe::Reply(echo) | Probe::Duplicate(echo) => {
+                write!(
+                    self.out,
+                    "{} bytes from {}: icmp_seq={sequence}",
+                    echo.size, echo.ip
+                )?;
+                if let Some(ttl) = echo.ttl {
+                    write!(self.out, " ttl={ttl}")?;
+                }
+                write!(self.out, " time={} ms", format_rtt(echo.duration))?;
+                match probe {
+                    Probe::Duplicate(_) => writeln!(self.out, " (DUP!)"),
+                    _ => writeln!(self.out),
+                }
+            }
+            Probe::Timeout => writeln!(self.out, "no answer yet for icmp_seq={sequence}"),
+            Probe::IcmpError(e) => match e.offender {
+                Some(offender) => writeln!(
+                    self.out,
+                    "From {offender} icmp_seq={sequence} {}",
+                    describe(e)
+                ),
+                None => writeln!(self.out, "ping: local error: {}", describe(e)),
+            },
+            Probe::BadChecksum(ip) => {
+                writeln!(self.out, "From {ip} icmp_seq={sequence} (BAD CHECKSUM!)")
+            }
+            Probe::Unreachable(_) | Probe::Error(_) | Probe::Failed(_) => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, statistics: &Statistics) -> io::Result<()> {
+        let out = &mut self.out;
+        writeln!(out)?;
+        writeln!(out, "--- {} ping statistics ---", self.args.destination)?;
+        write!(
+            out,
+            "{} packets transmitted, {} received, ",
+            statistics.transmitted, statistics.received
+        )?;
+        if statistics.duplicates > 0 {
+            write!(out, "+{} duplicates, ", statistics.duplicates)?;
+        }
+        if statistics.corrupted > 0 {
+            write!(out, "+{} corrupted, ", statistics.corrupted)?;
+        }
+        if statistics.errors > 0 {
+            write!(out, "+{} errors, ", statistics.errors)?;
+        }
+        writeln!(
+            out,
+            "{}% packet loss, time {}ms",
+            loss(statistics.loss_percent()),
+            statistics.time.as_millis()
+        )?;
+        if let Some([min, avg, max, mdev]) = statistics.rtt() {
+            writeln!(
+                out,
+                "rtt min/avg/max/mdev = {min:.3}/{avg:.3}/{max:.3}/{mdev:.3} ms"
+            )?;
+        }
+        if let Some((jitter, ipdv, pdv)) = statistics.variation() {
+            write!(out, "jitter = {jitter:.3} ms, ")?;
+            if let Some([avg, max]) = ipdv {
+                write!(out, "ipdv avg/max = {avg:.3}/{max:.3} ms, ")?;
+            }
+            writeln!(out, "pdv = {pdv:.3} ms")?;
+        }
+        Ok(())
+    }
+}
+
+// the fields of --format json, jsonl and csv, probe records first, then the summary
+const COLUMNS: &[&str] = &[
+    "type",
+    "target",
+    "address",
+    "seq",
+    "kind",
+    "from",
+    "ttl",
+    "size",
+    "rtt_us",
+    "error",
+    "transmitted",
+    "received",
+    "duplicates",
+    "corrupted",
+    "errors",
+    "loss_percent",
+    "time_ms",
+    "rtt_min_us",
+    "rtt_avg_us",
+    "rtt_max_us",
+    "rtt_mdev_us",
+    "rtt_p50_us",
+    "rtt_p90_us",
+    "rtt_p99_us",
+    "rtt_p999_us",
+    "jitter_us",
+    "ipdv_avg_us",
+    "ipdv_max_us",
+    "pdv_us",
+    "loss_low_percent",
+    "loss_high_percent",
+];
+
+// a record for every probe (unless -q) and one for the summary
+pub struct RecordReport<'a> {
+    writer: Option<RecordWriter<'a>>,
+    args: &'a Args,
+    target: IpAddr,
+}
+
+impl Report for RecordReport<'_> {
+    #[inline]
+    fn start(&mut self, target: IpAddr) -> io::Result<()> {
+        self.target = target;
+        Ok(())
+    }
+
+    fn probe(&mut self, sequence: u16, probe: &Probe) -> io::Result<()> {
+        if self.args.quiet && !matches!(probe, Probe::Failed(_)) {
+            return Ok(());
+        }
+        let (kind, from, error) = match probe {
+            Probe::Reply(echo) => ("reply", Some(echo.ip), None),
+            Probe::Duplicate(echo) => ("duplicate", Some(echo.ip), None),
+            Probe::Timeout => ("timeout", None, None),
+            Probe::IcmpError(e) if e.is_time_exceeded() => {
+                ("time_exceeded", e.offender, Some(describe(e)))
+            }
+            Probe::IcmpError(e) if e.is_unreachable() => {
+                ("unreachable", e.offender, Some(describe(e)))
+            }
+            Probe::IcmpError(e) => ("icmp_error", e.offender, Some(describe(e))),
+            Probe::BadChecksum(ip) => ("bad_checksum", Some(*ip), None),
+            Probe::Unreachable(e) => ("unreachable", None, Some(e.clone())),
+            Probe::Error(e) | Probe::Failed(e) => ("error", None, Some(e.clone())),
+        };
+        let echo = match probe {
+            Probe::Reply(echo) | Probe::Duplicate(echo) => Some(echo),
+            _ => None,
+        };
+        let fields = [
+            ("type", Value::Str(String::from("probe"))),
+            ("target", Value::Str(self.args.destination.clone())),
+            ("address", Value::Str(self.target.to_string())),
+            ("seq", Value::Int(sequence as u64)),
+            ("kind", Value::Str(String::from(kind))),
+            ("from", from.map(|ip| ip.to_string()).into()),
+            ("ttl", echo.and_then(|echo| echo.ttl).map(u64::from).into()),
+            ("size", echo.map(|echo| echo.size as u64).into()),
+            (
+                "rtt_us",
+                echo.map(|echo| echo.duration.as_micros() as u64).into(),
+            ),
+            ("error", error.into()),
+        ];
+        match &mut self.writer {
+            Some(writer) => writer.write(&fields),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, statistics: &Statistics) -> io::Result<()> {
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let rtt = statistics.rtt();
+        // microseconds, rounded to nanoseconds
+        let rtt_us = |i: usize| -> Value {
+            rtt.map(|rtt| (rtt[i] * 1_000_000.0).round() / 1000.0)
+                .into()
+        };
+        let percentile = |percentile: f64| -> Value {
+            let rtt = statistics.histogram.percentile(percentile);
+            rtt.map(|rtt| rtt.as_nanos() as f64 / 1000.0).into()
+        };
+        let variation = statistics.variation().map(|(jitter, ipdv, pdv)| {
+            let us = |ms: f64| (ms * 1_000_000.0).round() / 1000.0;
+            (us(jitter), ipdv.map(|ipdv| ipdv.map(us)), us(pdv))
+        });
+        let ipdv_us = |i: usize| -> Value {
+            variation
+                .and_then(|(_, ipdv, _)| ipdv)
+                .map(|ipdv| ipdv[i])
+                .into()
+        };
+        // the 95% interval of the loss, two decimals
+        let loss = Loss {
+            sent: statistics.transmitted,
+            received: statistics.received,
+        }
+        .wilson(Z_95)
+        .map(|(low, high)| {
+            (
+                (low * 100.0).round() / 100.0,
+                (high * 100.0).round() / 100.0,
+            )
+        });
+        writer.write(&[
+            ("type", Value::Str(String::from("summary"))),
+            ("target", Value::Str(self.args.destination.clone())),
+            ("address", Value::Str(self.target.to_string())),
+            ("transmitted", Value::Int(statistics.transmitted)),
+            ("received", Value::Int(statistics.received)),
+            ("duplicates", Value::Int(statistics.duplicates)),
+            ("corrupted", Value::Int(statistics.corrupted)),
+            ("errors", Value::Int(statistics.errors)),
+            ("loss_percent", Value::Float(statistics.loss_percent())),
+            ("time_ms", Value::Int(statistics.time.as_millis() as u64)),
+            ("rtt_min_us", rtt_us(0)),
+            ("rtt_avg_us", rtt_us(1)),
+            ("rtt_max_us", rtt_us(2)),
+            ("rtt_mdev_us", rtt_us(3)),
+            ("rtt_p50_us", percentile(50.0)),
+            ("rtt_p90_us", percentile(90.0)),
+            ("rtt_p99_us", percentile(99.0)),
+            ("rtt_p999_us", percentile(99.9)),
+            ("jitter_us", variation.map(|variation| variation.0).into()),
+            ("ipdv_avg_us", ipdv_us(0)),
+            ("ipdv_max_us", ipdv_us(1)),
+            ("pdv_us", variation.map(|variation| variation.2).into()),
+            ("loss_low_percent", loss.map(|loss| loss.0).into()),
+            ("loss_high_percent", loss.map(|loss| loss.1).into()),
+        ])?;
+        writer.finish()
+    }
+}
+
+// the precision iputils prints a round trip time with
+pub fn format_rtt(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    let millis = duration.as_secs_f64() * 1000.0;
+    match micros {
+        100_000.. => format!("{}", micros / 1000),
+        10_000.. => format!("{millis:.1}"),
+        1_000.. => format!("{millis:.2}"),
+        _ => format!("{millis:.3}"),
+    }
+}
+
+// "%g" of the lost percentage
+fn loss(percent: f64) -> String {
+    let digits = match percent {
+        100.0.. => 3,
+        10.0.. => 2,
+        _ => 1,
+    };
+    let text = format!("{percent:.*}", 6 - digits);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[inline]
+fn strerror(errno: libc::c_int) -> String {
+    LinuxError::errno_to_str(errno).unwrap_or(format!("errno {errno}"))
+}
+
+// the words iputils uses for ICMP errors
+pub fn describe(e: &ExtendedError) -> String {
+    match (e.origin, e.icmp_type, e.icmp_code) {
+        (libc::SO_EE_ORIGIN_ICMP, 3, code) => match code {
+            0 => String::from("Destination Net Unreachable"),
+            1 => String::from("Destination Host Unreachable"),
+            2 => String::from("Destination Protocol Unreachable"),
+            3 => String::from("Destination Port Unreachable"),
+            4 => format!("Frag needed and DF set (mtu = {})", e.info),
+            5 => String::from("Source Route Failed"),
+            6 => String::from("Destination Net Unknown"),
+            7 => String::from("Destination Host Unknown"),
+            8 => String::from("Source Host Isolated"),
+            9 => String::from("Destination Net Prohibited"),
+            10 => String::from("Destination Host Prohibited"),
+            11 => String::from("Destination Net Unreachable for Type of Service"),
+            12 => String::from("Destination Host Unreachable for Type of Service"),
+            13 => String::from("Packet filtered"),
+            14 => String::from("Precedence Violation"),
+            15 => String::from("Precedence Cutoff"),
+            _ => format!("Dest Unreachable, Bad Code: {code}"),
+        },
+        (libc::SO_EE_ORIGIN_ICMP, 11, 0) => String::from("Time to live exceeded"),
+        (libc::SO_EE_ORIGIN_ICMP, 11, 1) => String::from("Frag reassembly time exceeded"),
+        (libc::SO_EE_ORIGIN_ICMP, 12, _) => String::from("Parameter problem"),
+        (libc::SO_EE_ORIGIN_ICMP6, 1, code) => match code {
+            0 => String::from("Destination unreachable: No route"),
+            1 => String::from("Destination unreachable: Administratively prohibited"),
+            2 => String::from("Destination unreachable: Beyond scope of source address"),
+            3 => String::from("Destination unreachable: Address unreachable"),
+            4 => String::from("Destination unreachable: Port unreachable"),
+            5 => {
+                String::from("Destination unreachable: Source address failed ingress/egress policy")
+            }
+            6 => String::from("Destination unreachable: Reject route to destination"),
+            _ => format!("Destination unreachable: Unknown code {code}"),
+        },
+        (libc::SO_EE_ORIGIN_ICMP6, 2, _) => format!("Packet too big: mtu={}", e.info),
+        (libc::SO_EE_ORIGIN_ICMP6, 3, 0) => String::from("Time exceeded: Hop limit"),
+        (libc::SO_EE_ORIGIN_ICMP6, 3, 1) => String::from("Time exceeded: Defragmentation failure"),
+        (libc::SO_EE_ORIGIN_ICMP6, 4, _) => String::from("Parameter problem"),
+        (libc::SO_EE_ORIGIN_ICMP | libc::SO_EE_ORIGIN_ICMP6, icmp_type, code) => {
+            format!("Bad ICMP type: {icmp_type}, code: {code}")
+        }
+        // e.g. EMSGSIZE for a probe bigger than the MTU
+        _ => {
+            let errno = e.errno as libc::c_int;
+            let text = strerror(errno);
+            match e.info {
+                0 => text,
+                mtu => format!("{text}, mtu={mtu}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rps_ping_lib::clock::VirtualClock;
+    use rps_ping_lib::transport::{MockReply, MockTransport, SimulatedNetwork};
+
+    // echo replies after 5ms, nothing for sequence 2, time exceeded for 4
+    fn run(args: Args) -> (i32, String) {
+        let clock = Arc::new(VirtualClock::new());
+        let transport = MockTransport::new(|sent, target| {
+            let delay = Duration::from_millis(5);
+            match u16::from_be_bytes([sent[6], sent[7]]) {
+                2 => Vec::new(),
+                4 => vec![MockReply::time_exceeded(
+                    sent,
+                    "10.0.0.1".parse().unwrap(),
+                    delay,
+                )],
+                _ => vec![MockReply::echo_reply(sent, target, delay)],
+            }
+        })
+        .with_clock(clock.clone());
+        let args = Args {
+            count: Some(4),
+            timeout: Some(Duration::from_secs(1)),
+            destination: String::from("192.0.2.10"),
+            ..args
+        };
+        let session = Session::new(
+            args,
+            "192.0.2.10".parse().unwrap(),
+            Some(Arc::new(transport)),
+            Some(clock),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let code = session.run(&mut out).unwrap();
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_session_output() {
+        let (code, out) = run(Args {
+            outstanding: true,
+            ..Args::default()
+        });
+        assert_eq!(code, 0);
+        assert_eq!(
+            out,
+            "\
+PING 192.0.2.10 (192.0.2.10) 56(84) bytes of data.
+64 bytes from 192.0.2.10: icmp_seq=1 time=5.00 ms
+no answer yet for icmp_seq=2
+64 bytes from 192.0.2.10: icmp_seq=3 time=5.00 ms
+From 10.0.0.1 icmp_seq=4 Time to live exceeded
+
+--- 192.0.2.10 ping statistics ---
+4 packets transmitted, 2 received, +1 errors, 50% packet loss, time 3005ms
+rtt min/avg/max/mdev = 5.000/5.000/5.000/0.000 ms
+jitter = 0.000 ms, pdv = 0.000 ms
+"
+        );
+    }
+
+    #[test]
+    fn test_session_records() {
+        let (_, out) = run(Args {
+            format: Format::Jsonl,
+            ..Args::default()
+        });
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            r#"{"type":"probe","target":"192.0.2.10","address":"192.0.2.10","seq":1,"kind":"reply","from":"192.0.2.10","ttl":null,"size":64,"rtt_us":5000,"error":null}"#
+        );
+        assert!(lines[1].contains(r#""seq":2,"kind":"timeout","from":null"#));
+        assert!(lines[3].contains(
+            r#""seq":4,"kind":"time_exceeded","from":"10.0.0.1","ttl":null,"size":null,"rtt_us":null,"error":"Time to live exceeded""#
+        ));
+        assert_eq!(
+            lines[4],
+            r#"{"type":"summary","target":"192.0.2.10","address":"192.0.2.10","transmitted":4,"received":2,"duplicates":0,"corrupted":0,"errors":1,"loss_percent":50,"time_ms":3005,"rtt_min_us":5000,"rtt_avg_us":5000,"rtt_max_us":5000,"rtt_mdev_us":0,"rtt_p50_us":5000,"rtt_p90_us":5000,"rtt_p99_us":5000,"rtt_p999_us":5000,"jitter_us":0,"ipdv_avg_us":null,"ipdv_max_us":null,"pdv_us":0,"loss_low_percent":15,"loss_high_percent":85}"#
+        );
+
+        let (_, out) = run(Args {
+            format: Format::Csv,
+            quiet: true,
+            ..Args::default()
+        });
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        assert!(lines[1].starts_with("summary,192.0.2.10,192.0.2.10,,,,,,,,4,2,0,0,1,50,3005,"));
+    }
+
+    #[test]
+    fn test_session_multicast() {
+        let clock = Arc::new(VirtualClock::new());
+        let transport = MockTransport::new(|sent, _| {
+            let member = |last| IpAddr::V6(std::net::Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, last));
+            let mut corrupted = MockReply::echo_reply(sent, member(3), Duration::from_millis(3));
+            corrupted.data[2] ^= 0xff;
+            vec![
+                MockReply::echo_reply(sent, member(1), Duration::from_millis(1)),
+                MockReply::echo_reply(sent, member(2), Duration::from_millis(2)),
+                corrupted,
+            ]
+        })
+        .with_clock(clock.clone());
+        let args = Args {
+            count: Some(1),
+            destination: String::from("ff02::1%1"),
+            ..Args::default()
+        };
+        let target = resolve(&args.destination, IpVersion::Any).unwrap();
+        let session = Session::new(args, target, Some(Arc::new(transport)), Some(clock)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(session.run(&mut out).unwrap(), 0);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "PING ff02::1%1 (ff02::1) 56 data bytes");
+        assert_eq!(lines[1], "64 bytes from fe80::1: icmp_seq=1 time=1.00 ms");
+        assert_eq!(
+            lines[2],
+            "64 bytes from fe80::2: icmp_seq=1 time=2.00 ms (DUP!)"
+        );
+        assert_eq!(lines[3], "From fe80::3 icmp_seq=1 (BAD CHECKSUM!)");
+        assert_eq!(
+            lines[6],
+            "1 packets transmitted, 1 received, +1 duplicates, +1 corrupted, 0% packet loss, time 1000ms"
+        );
+    }
+
+    #[test]
+    fn test_session_variation() {
+        let clock = Arc::new(VirtualClock::new());
+        let transport = MockTransport::new(|sent, target| {
+            let delay = match u16::from_be_bytes([sent[6], sent[7]]) {
+                1 | 3 => 10,
+                2 => 26,
+                _ => 14,
+            };
+            vec![MockReply::echo_reply(
+                sent,
+                target,
+                Duration::from_millis(delay),
+            )]
+        })
+        .with_clock(clock.clone());
+        let args = Args {
+            count: Some(4),
+            quiet: true,
+            destination: String::from("192.0.2.10"),
+            ..Args::default()
+        };
+        let target = args.destination.parse().unwrap();
+        let session = Session::new(args, target, Some(Arc::new(transport)), Some(clock)).unwrap();
+        let mut out = Vec::new();
+        session.run(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.ends_with("jitter = 2.066 ms, ipdv avg/max = 12.000/16.000 ms, pdv = 16.000 ms\n"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_session_broadcast() {
+        let transport = MockTransport::new(|sent, _| {
+            vec![
+                MockReply::echo_reply(sent, [192, 0, 2, 7].into(), Duration::from_millis(3)),
+                MockReply::echo_reply(sent, [192, 0, 2, 1].into(), Duration::from_millis(5)),
+            ]
+        });
+        let args = Args {
+            count: Some(1),
+            timeout: Some(Duration::from_millis(50)),
+            broadcast: true,
+            destination: String::from("255.255.255.255"),
+            ..Args::default()
+        };
+        let target = resolve(&args.destination, IpVersion::Any).unwrap();
+        let session = Session::new(args, target, Some(Arc::new(transport)), None).unwrap();
+        assert!(session.broadcast);
+        let mut out = Vec::new();
+        assert_eq!(session.run(&mut out).unwrap(), 0);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[1].starts_with("64 bytes from 192.0.2.7: icmp_seq=1 "));
+        assert!(lines[2].starts_with("64 bytes from 192.0.2.1: icmp_seq=1 "));
+        assert!(lines[2].ends_with(" (DUP!)"));
+        assert!(!is_broadcast(Ipv4Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn test_session_keeps_going() {
+        let clock = Arc::new(VirtualClock::new());
+        let transport = MockTransport::new(|sent, target| {
+            let mut reply = MockReply::echo_reply(sent, target, Duration::from_millis(5));
+            if u16::from_be_bytes([sent[6], sent[7]]) == 2 {
+                reply.data[2] ^= 0xff;
+            }
+            vec![reply]
+        })
+        .with_clock(clock.clone());
+        // with -w, -c counts the replies
+        let args = Args {
+            count: Some(2),
+            deadline: Some(Duration::from_secs(10)),
+            destination: String::from("192.0.2.10"),
+            ..Args::default()
+        };
+        let target = args.destination.parse().unwrap();
+        let session = Session::new(args, target, Some(Arc::new(transport)), Some(clock)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(session.run(&mut out).unwrap(), 0);
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("From 192.0.2.10 icmp_seq=2 (BAD CHECKSUM!)\n"),
+            "{out}"
+        );
+        assert!(
+            out.contains("3 packets transmitted, 2 received, +1 corrupted, 33.3333% packet loss"),
+            "{out}"
+        );
+
+        // no route at all, every probe fails on its own
+        let network = SimulatedNetwork::new(1);
+        let args = Args {
+            count: Some(3),
+            quiet: true,
+            destination: String::from("192.0.2.10"),
+            ..Args::default()
+        };
+        let clock = network.clock();
+        let session = Session::new(args, target, Some(Arc::new(network)), Some(clock)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(session.run(&mut out).unwrap(), 1);
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("3 packets transmitted, 0 received, +3 errors, 100% packet loss"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_rtt(Duration::from_micros(45)), "0.045");
+        assert_eq!(format_rtt(Duration::from_micros(12_345)), "12.3");
+        assert_eq!(format_rtt(Duration::from_micros(123_456)), "123");
+        assert_eq!(loss(100.0 / 3.0), "33.3333");
+        assert_eq!(loss(0.0), "0");
+        assert_eq!(loss(100.0), "100");
+    }
+}
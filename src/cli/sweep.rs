@@ -0,0 +1,385 @@
+use crate::cli::args::{Args, IpVersion};
+use crate::cli::output::{Format, RecordWriter, Value};
+use crate::cli::ping::{self, Builder, INTERRUPTED, Statistics};
+use rps_ping_lib::clock::{Clock, SystemClock};
+use rps_ping_lib::sweep::{self, HostState, Sweep, SweepBuilder, SweepHost};
+use rps_ping_lib::transport::Transport;
+use std::io::{self, BufRead, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+// the targets of the command line and of --file in order, `stdin` is read for "-" or without any
+pub fn targets(args: &Args, stdin: &mut dyn BufRead) -> Result<Vec<IpAddr>, String> {
+    let targets = named_targets(args, stdin)?;
+    Ok(targets.into_iter().map(|(_, addr)| addr).collect())
+}
+
+// same with the name of each, the host name as given or the address
+pub fn named_targets(
+    args: &Args,
+    stdin: &mut dyn BufRead,
+) -> Result<Vec<(String, IpAddr)>, String> {
+    let mut targets = Vec::new();
+    for spec in &args.targets {
+        targets.extend(expand(spec, args.version)?);
+    }
+    let mut files: Vec<&str> = args.files.iter().map(String::as_str).collect();
+    if files.is_empty() && args.targets.is_empty() {
+        files.push("-");
+    }
+    for file in files {
+        let lines: Vec<String> = match file {
+            "-" => stdin.lines().collect(),
+            _ => std::fs::read_to_string(file).map(|text| text.lines().map(String::from).collect()),
+        }
+        .map_err(|e| format!("{file}: {e}"))?;
+        for line in &lines {
+            let spec = line.split('#').next().unwrap_or_default().trim();
+            if !spec.is_empty() {
+                targets.extend(expand(spec, args.version)?);
+            }
+        }
+    }
+    if targets.is_empty() {
+        return Err(String::from("usage error: no targets to sweep"));
+    }
+    Ok(targets)
+}
+
+// an address, block or range of -4/-6, anything else is a name
+fn expand(spec: &str, version: IpVersion) -> Result<Vec<(String, IpAddr)>, String> {
+    let Ok(first) = spec
+        .split(['/', '-'])
+        .next()
+        .unwrap_or_default()
+        .parse::<IpAddr>()
+    else {
+        return Ok(vec![(String::from(spec), ping::resolve(spec, version)?)]);
+    };
+    let addrs = match version {
+        IpVersion::V4 if !first.is_ipv4() => Err(format!("{spec}: not an IPv4 address")),
+        IpVersion::V6 if !first.is_ipv6() => Err(format!("{spec}: not an IPv6 address")),
+        _ => sweep::expand(spec).map_err(|e| e.to_string()),
+    }?;
+    Ok(addrs
+        .into_iter()
+        .map(|addr| (addr.to_string(), addr))
+        .collect())
+}
+
+// the builder with the options of `args`, also used by --exporter
+pub fn builder(
+    args: &Args,
+    targets: &[IpAddr],
+    transport: Option<Arc<dyn Transport>>,
+    clock: Arc<dyn Clock>,
+) -> Result<SweepBuilder, String> {
+    let attempts = match args.count.unwrap_or(1).try_into() {
+        Ok(attempts) => attempts,
+        Err(_) => return Err(String::from("-c: at most 255 attempts per target")),
+    };
+    let mut builder = SweepBuilder {
+        concurrency: args.concurrency,
+        rate: Some(args.rate).filter(|rate| *rate > 0),
+        attempts,
+        ..Default::default()
+    };
+    // the options are checked against a target of each family we have
+    for target in [
+        targets.iter().find(|target| target.is_ipv4()),
+        targets.iter().find(|target| target.is_ipv6()),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        match ping::builder(args, *target, transport.clone(), clock.clone())? {
+            Builder::V4(v4) => builder.v4 = v4,
+            Builder::V6(v6) => builder.v6 = v6,
+        }
+    }
+    // probes are paced on the clock of the IPv4 builder, also without IPv4 targets
+    builder.v4.clock = Some(clock);
+    Ok(builder)
+}
+
+// --sweep
+pub struct SweepSession {
+    args: Args,
+    targets: Vec<IpAddr>,
+    sweep: Sweep,
+    clock: Arc<dyn Clock>,
+}
+
+impl SweepSession {
+    // `transport` and `clock` are None for sockets and the system clock
+    pub fn new(
+        args: Args,
+        targets: Vec<IpAddr>,
+        transport: Option<Arc<dyn Transport>>,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Result<Self, String> {
+        let clock = clock.unwrap_or(Arc::new(SystemClock));
+        let sweep = builder(&args, &targets, transport, clock.clone())?.build();
+        Ok(SweepSession {
+            args,
+            targets,
+            sweep,
+            clock,
+        })
+    }
+
+    // a line or record for every target as it's done, then the summary,
+    // returns 0 when all are alive, 1 if some aren't, 2 when a probe failed
+    pub fn run(&self, out: &mut dyn Write) -> io::Result<i32> {
+        let start = self.clock.now();
+        let (mut writer, mut text) = match self.args.format {
+            Format::Text => (None, Some(out)),
+            format => (Some(RecordWriter::new(format, COLUMNS, out)?), None),
+        };
+        let mut statistics = Statistics::default();
+        let mut result = Ok(());
+        self.sweep.run(&self.targets, |host| {
+            statistics.transmitted += 1;
+            match &host.state {
+                HostState::Alive { duration, .. } => statistics.add(*duration),
+                HostState::Failed(_) => statistics.errors += 1,
+                _ => {}
+            }
+            if result.is_ok() {
+                result = match (&mut writer, &mut text) {
+                    (Some(writer), _) => writer.write(&record(host)),
+                    (None, _) if matches!(host.state, HostState::Failed(_)) => {
+                        eprintln!("ping: {}", line(host));
+                        Ok(())
+                    }
+                    (None, Some(out)) if !self.args.quiet => writeln!(out, "{}", line(host)),
+                    _ => Ok(()),
+                };
+            }
+            if result.is_err() || INTERRUPTED.load(Ordering::Relaxed) {
+                self.sweep.stop();
+            }
+        });
+        result?;
+        statistics.time = self.clock.now() - start;
+        let unreachable = statistics.transmitted - statistics.received - statistics.errors;
+        match (writer, text) {
+            (Some(mut writer), _) => {
+                let rtt = statistics.rtt();
+                let rtt_us = |i: usize| Value::from(rtt.map(|rtt| (rtt[i] * 1000.0).round()));
+                writer.write(&[
+                    ("type", Value::Str(String::from("summary"))),
+                    ("targets", Value::Int(statistics.transmitted)),
+                    ("alive", Value::Int(statistics.received)),
+                    ("unreachable", Value::Int(unreachable)),
+                    ("errors", Value::Int(statistics.errors)),
+                    ("time_ms", Value::Int(statistics.time.as_millis() as u64)),
+                    ("rtt_min_us", rtt_us(0)),
+                    ("rtt_avg_us", rtt_us(1)),
+                    ("rtt_max_us", rtt_us(2)),
+                ])?;
+                writer.finish()?;
+            }
+            (None, Some(out)) => {
+                writeln!(out)?;
+                writeln!(out, "--- sweep statistics ---")?;
+                write!(
+                    out,
+                    "{} targets, {} alive, {unreachable} unreachable, ",
+                    statistics.transmitted, statistics.received
+                )?;
+                if statistics.errors > 0 {
+                    write!(out, "{} errors, ", statistics.errors)?;
+                }
+                writeln!(out, "time {}ms", statistics.time.as_millis())?;
+                if let Some([min, avg, max, _]) = statistics.rtt() {
+                    writeln!(out, "rtt min/avg/max = {min:.3}/{avg:.3}/{max:.3} ms")?;
+                }
+            }
+            (None, None) => unreachable!(),
+        }
+        Ok(if statistics.errors > 0 {
+            2
+        } else if unreachable > 0 {
+            1
+        } else {
+            0
+        })
+    }
+}
+
+// like fping -e
+fn line(host: &SweepHost) -> String {
+    match &host.state {
+        HostState::Alive { duration, .. } => format!(
+            "{} is alive ({} ms)",
+            host.target,
+            ping::format_rtt(*duration)
+        ),
+        HostState::IcmpError(e) => match e.offender {
+            Some(from) => format!(
+                "{} is unreachable (From {from} {})",
+                host.target,
+                ping::describe(e)
+            ),
+            None => format!("{} is unreachable ({})", host.target, ping::describe(e)),
+        },
+        HostState::Timeout | HostState::Unreachable => format!("{} is unreachable", host.target),
+        HostState::Failed(e) => format!("{} error: {e}", host.target),
+    }
+}
+
+// the fields of --sweep --format json, jsonl and csv, host records first, then the summary
+const COLUMNS: &[&str] = &[
+    "type",
+    "target",
+    "state",
+    "sent",
+    "from",
+    "rtt_us",
+    "error",
+    "targets",
+    "alive",
+    "unreachable",
+    "errors",
+    "time_ms",
+    "rtt_min_us",
+    "rtt_avg_us",
+    "rtt_max_us",
+];
+
+// state is one of alive, timeout, no_route, icmp_error and error
+fn record(host: &SweepHost) -> Vec<(&'static str, Value)> {
+    let (state, from, rtt_us, error) = match &host.state {
+        HostState::Alive { ip, duration } => (
+            "alive",
+            Some(ip.to_string()),
+            Some(duration.as_micros() as u64),
+            None,
+        ),
+        HostState::IcmpError(e) => (
+            "icmp_error",
+            e.offender.map(|ip| ip.to_string()),
+            None,
+            Some(ping::describe(e)),
+        ),
+        HostState::Timeout => ("timeout", None, None, None),
+        HostState::Unreachable => ("no_route", None, None, None),
+        HostState::Failed(e) => ("error", None, None, Some(e.to_string())),
+    };
+    vec![
+        ("type", Value::Str(String::from("host"))),
+        ("target", Value::Str(host.target.to_string())),
+        ("state", Value::Str(String::from(state))),
+        ("sent", Value::Int(host.sent.into())),
+        ("from", from.into()),
+        ("rtt_us", rtt_us.into()),
+        ("error", error.into()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rps_ping_lib::clock::VirtualClock;
+    use rps_ping_lib::transport::{MockReply, MockTransport};
+    use std::time::Duration;
+
+    // .1 answers after 5ms, .2 gets host unreachable from .254, the rest stay silent
+    fn run(args: Args) -> (i32, String) {
+        let clock = Arc::new(VirtualClock::new());
+        let transport = MockTransport::new(|sent, target| {
+            let delay = Duration::from_millis(5);
+            match target.to_string().as_str() {
+                "192.0.2.1" => vec![MockReply::echo_reply(sent, target, delay)],
+                "192.0.2.2" => vec![MockReply::unreachable(
+                    sent,
+                    "192.0.2.254".parse().unwrap(),
+                    1,
+                    delay,
+                )],
+                _ => Vec::new(),
+            }
+        })
+        .with_clock(clock.clone());
+        let args = Args {
+            sweep: true,
+            timeout: Some(Duration::from_millis(500)),
+            concurrency: 1,
+            targets: vec![String::from("192.0.2.0/30"), String::from("192.0.2.9")],
+            ..args
+        };
+        let targets = targets(&args, &mut io::empty()).unwrap();
+        let session =
+            SweepSession::new(args, targets, Some(Arc::new(transport)), Some(clock)).unwrap();
+        let mut out = Vec::new();
+        let code = session.run(&mut out).unwrap();
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_sweep_output() {
+        let (code, out) = run(Args::default());
+        assert_eq!(code, 1);
+        // sent at 0, 10 and 20ms, 100 packets per second by default
+        assert_eq!(
+            out,
+            "192.0.2.1 is alive (5.00 ms)
+192.0.2.2 is unreachable (From 192.0.2.254 Destination Host Unreachable)
+192.0.2.9 is unreachable
+
+--- sweep statistics ---
+3 targets, 1 alive, 2 unreachable, time 520ms
+rtt min/avg/max = 5.000/5.000/5.000 ms
+"
+        );
+    }
+
+    #[test]
+    fn test_sweep_records() {
+        let (_, out) = run(Args {
+            format: Format::Jsonl,
+            count: Some(2),
+            ..Args::default()
+        });
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[1],
+            r#"{"type":"host","target":"192.0.2.2","state":"icmp_error","sent":2,"from":"192.0.2.254","rtt_us":null,"error":"Destination Host Unreachable"}"#
+        );
+        assert_eq!(
+            lines[2],
+            r#"{"type":"host","target":"192.0.2.9","state":"timeout","sent":2,"from":null,"rtt_us":null,"error":null}"#
+        );
+        assert!(lines[3].starts_with(
+            r#"{"type":"summary","targets":3,"alive":1,"unreachable":2,"errors":0,"time_ms":1030,"#
+        ));
+    }
+
+    #[test]
+    fn test_targets() {
+        let args = Args {
+            sweep: true,
+            targets: vec![String::from("10.0.0.1-3")],
+            ..Args::default()
+        };
+        let stdin = "# more\n2001:db8::1\n";
+        assert_eq!(targets(&args, &mut stdin.as_bytes()).unwrap().len(), 3);
+        let args = Args {
+            files: vec![String::from("-")],
+            ..args
+        };
+        assert_eq!(targets(&args, &mut stdin.as_bytes()).unwrap().len(), 4);
+        let args = Args {
+            version: IpVersion::V6,
+            ..args
+        };
+        assert!(targets(&args, &mut stdin.as_bytes()).is_err());
+        let args = Args {
+            sweep: true,
+            ..Args::default()
+        };
+        assert!(targets(&args, &mut "".as_bytes()).is_err());
+    }
+}
@@ -1,21 +1,110 @@
-#[allow(unused)]
-use rps_ping_lib::{PingV4Builder, PingV6Builder};
+#[cfg(not(target_os = "windows"))]
+mod cli;
 
+#[cfg(not(target_os = "windows"))]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    cli::ping::INTERRUPTED.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+#[cfg(not(target_os = "windows"))]
+enum Program {
+    Ping(cli::ping::Session),
+    // whether to redraw the table after every round
+    Mtr(cli::mtr::Mtr, bool),
+    Sweep(Box<cli::sweep::SweepSession>),
+    #[cfg(feature = "EXPORTER")]
+    Exporter(Box<cli::exporter::ExporterSession>),
+}
+
+#[cfg(not(target_os = "windows"))]
 fn main() {
-    let ping = PingV4Builder {
-        timeout: 200,
-        ttl: Some(5),
-        bind_addr: None,
-        #[cfg(target_os = "windows")]
-        window_addition: None,
+    let args = match cli::args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", cli::args::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("ping: {e}\n\n{}", cli::args::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let program = if args.exporter.is_some() {
+        exporter(args)
+    } else if args.sweep {
+        cli::sweep::targets(&args, &mut std::io::stdin().lock()).and_then(|targets| {
+            cli::sweep::SweepSession::new(args, targets, None, None)
+                .map(|sweep| Program::Sweep(Box::new(sweep)))
+        })
+    } else {
+        start(args)
+    };
+    let program = match program {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("ping: {e}");
+            std::process::exit(2);
+        }
+    };
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+    let mut out = std::io::stdout().lock();
+    let result = match program {
+        Program::Ping(session) => session.run(&mut out),
+        Program::Mtr(mtr, live) => mtr.run(&mut out, live),
+        Program::Sweep(sweep) => sweep.run(&mut out),
+        #[cfg(feature = "EXPORTER")]
+        Program::Exporter(exporter) => exporter.run(&mut out),
+    };
+    match result {
+        Ok(code) => std::process::exit(code),
+        // e.g. piped into head
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => {
+            eprintln!("ping: {e}");
+            std::process::exit(2);
+        }
+    }
+}
+
+// a single destination, ping or --mtr
+#[cfg(not(target_os = "windows"))]
+fn start(args: cli::args::Args) -> Result<Program, String> {
+    let target = cli::ping::resolve(&args.destination, args.version);
+    match (target, args.mtr) {
+        (Ok(target), true) => {
+            // the report replaces the live table, which only makes sense on a terminal
+            let live = !args.report
+                && args.format == cli::output::Format::Text
+                && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+            cli::mtr::Mtr::new(args, target, None, None).map(|mtr| Program::Mtr(mtr, live))
+        }
+        (Ok(target), false) => cli::ping::Session::new(args, target, None, None).map(Program::Ping),
+        (Err(e), _) => Err(e),
     }
-    .build();
-    let result = ping
-        .ping_in_detail(std::net::Ipv4Addr::new(1, 1, 1, 1))
-        .expect("ping_v4_in_detail error");
-    println!(
-        "{},{}",
-        result.ip,
-        result.duration.as_micros() as f64 / 1000.0
-    );
+}
+
+// --exporter, the targets are read like the ones of --sweep
+#[cfg(all(not(target_os = "windows"), feature = "EXPORTER"))]
+fn exporter(args: cli::args::Args) -> Result<Program, String> {
+    cli::sweep::named_targets(&args, &mut std::io::stdin().lock()).and_then(|targets| {
+        cli::exporter::ExporterSession::new(args, targets, None, None)
+            .map(|exporter| Program::Exporter(Box::new(exporter)))
+    })
+}
+
+#[cfg(all(not(target_os = "windows"), not(feature = "EXPORTER")))]
+fn exporter(_: cli::args::Args) -> Result<Program, String> {
+    Err(String::from("--exporter needs the EXPORTER feature"))
+}
+
+// the library has no ping_seq on Windows yet
+#[cfg(target_os = "windows")]
+fn main() {
+    eprintln!("ping: not supported on Windows yet");
+    std::process::exit(2);
 }
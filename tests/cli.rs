@@ -0,0 +1,139 @@
+#![cfg(all(target_os = "linux", feature = "TUN"))]
+
+use rps_ping_lib::tun::TunHop;
+use std::net::Ipv4Addr;
+use std::process::{Command, Output};
+
+// the ping binary against a TUN interface,
+// the child process starts in the namespace of the test thread
+mod common;
+
+use common::network;
+
+const R1: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const TARGET: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+
+fn ping(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rps_ping_lib"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_cli_replies() {
+    let network = network();
+    network
+        .add_route(vec![TunHop::new(R1.into()), TunHop::new(TARGET.into())])
+        .unwrap();
+    let output = ping(&[
+        "-c",
+        "2",
+        "-i",
+        "0.1",
+        "-s",
+        "100",
+        "-p",
+        "abcd",
+        "203.0.113.7",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "PING 203.0.113.7 (203.0.113.7) 100(128) bytes of data."
+    );
+    for (line, sequence) in lines[1..3].iter().zip(1..) {
+        assert!(
+            line.starts_with(&format!(
+                "108 bytes from 203.0.113.7: icmp_seq={sequence} ttl="
+            )),
+            "{line}"
+        );
+    }
+    assert!(
+        stdout.contains("2 packets transmitted, 2 received, 0% packet loss"),
+        "{stdout}"
+    );
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_cli_errors() {
+    let network = network();
+    network
+        .add_route(vec![TunHop::new(R1.into()), TunHop::new(TARGET.into())])
+        .unwrap();
+    let output = ping(&["-c", "1", "-t", "1", "-q", "203.0.113.7"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stdout.contains("1 packets transmitted, 0 received, +1 errors, 100% packet loss"),
+        "{stdout}"
+    );
+
+    let output = ping(&["-c", "1", "-t", "1", "203.0.113.7"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("From 10.0.0.1 icmp_seq=1 Time to live exceeded"),
+        "{stdout}"
+    );
+
+    let output = ping(&["-c", "1", "-t", "1", "--format", "jsonl", "203.0.113.7"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    assert!(lines[0].contains(r#""kind":"time_exceeded","from":"10.0.0.1""#));
+    assert!(lines[1].starts_with(r#"{"type":"summary""#));
+
+    let output = ping(&["-c", "1", "-x", "203.0.113.7"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_cli_mtr() {
+    let network = network();
+    network
+        .add_route(vec![TunHop::new(R1.into()), TunHop::new(TARGET.into())])
+        .unwrap();
+    let output = ping(&["--mtr", "--report", "-c", "2", "-i", "0.1", "203.0.113.7"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(0), "{stdout}");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{stdout}");
+    assert!(lines[0].starts_with("Start: "));
+    assert!(lines[2].starts_with("  1.|-- 10.0.0.1 "), "{stdout}");
+    assert!(lines[3].starts_with("  2.|-- 203.0.113.7 "), "{stdout}");
+    assert!(lines[3].contains("  0.0%     2"), "{stdout}");
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_cli_sweep() {
+    let network = network();
+    network
+        .add_route(vec![TunHop::new(R1.into()), TunHop::new(TARGET.into())])
+        .unwrap();
+    let output = ping(&[
+        "--sweep",
+        "-W",
+        "0.3",
+        "203.0.113.6-7",
+        "10.0.0.1",
+        // no route
+        "198.51.100.1",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    assert!(stdout.contains("203.0.113.6 is unreachable\n"), "{stdout}");
+    assert!(stdout.contains("203.0.113.7 is alive ("), "{stdout}");
+    assert!(stdout.contains("10.0.0.1 is alive ("), "{stdout}");
+    assert!(stdout.contains("198.51.100.1 is unreachable\n"), "{stdout}");
+    assert!(
+        stdout.contains("4 targets, 2 alive, 2 unreachable, time "),
+        "{stdout}"
+    );
+}
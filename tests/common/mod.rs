@@ -0,0 +1,6 @@
+use rps_ping_lib::tun::{TunConfig, TunNetwork};
+
+// the TUN tests are #[ignore]d, run them with `--features TUN -- --ignored` and CAP_NET_ADMIN
+pub fn network() -> TunNetwork {
+    TunNetwork::new(TunConfig::default()).expect("no TUN network, CAP_NET_ADMIN is required")
+}
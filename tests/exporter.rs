@@ -0,0 +1,60 @@
+#![cfg(all(not(target_os = "windows"), feature = "EXPORTER"))]
+
+use rps_ping_lib::clock::VirtualClock;
+use rps_ping_lib::exporter::ExporterBuilder;
+use rps_ping_lib::transport::MockTransport;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn get(addr: std::net::SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_exporter_serve() {
+    let target = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10));
+    let clock = Arc::new(VirtualClock::new());
+    let transport = MockTransport::echo(Duration::from_millis(2)).with_clock(clock.clone());
+    let mut builder = ExporterBuilder::new(Duration::from_millis(100));
+    builder.sweep.v4.transport = Some(Arc::new(transport));
+    builder.sweep.v4.clock = Some(clock.clone());
+    builder.targets = vec![target.into()];
+    let exporter = builder.build();
+    // the rounds run on the virtual clock, before any scrape
+    for _ in 0..3 {
+        exporter.round();
+    }
+    assert_eq!(clock.elapsed(), Duration::from_millis(6));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::scope(|scope| {
+        let server = scope.spawn(|| exporter.serve(&listener));
+        // a client that never finishes its request doesn't hold up the scrape
+        let mut slow = TcpStream::connect(addr).unwrap();
+        slow.write_all(b"GET /metrics HTTP/1.1\r\n").unwrap();
+        let start = Instant::now();
+        let response = get(addr);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        drop(slow);
+        exporter.stop();
+        server.join().unwrap().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        let labels = r#"target="192.0.2.10",address="192.0.2.10",family="ipv4""#;
+        for line in [
+            format!("rps_ping_probes_total{{{labels}}} 3\n"),
+            format!("rps_ping_lost_total{{{labels}}} 0\n"),
+            format!("rps_ping_up{{{labels}}} 1\n"),
+        ] {
+            assert!(response.contains(&line), "{response}");
+        }
+    });
+}
@@ -0,0 +1,48 @@
+use rps_ping_lib::PingV6Builder;
+use rps_ping_lib::protocol::IcmpMessage;
+
+// ICMP DGRAM sockets only send echo requests
+#[cfg(not(feature = "DGRAM_SOCKET"))]
+#[test]
+fn test_send_timestamp_request() {
+    let ping = rps_ping_lib::PingV4Builder::new(std::time::Duration::from_millis(200)).build();
+    let request = IcmpMessage::new_v4(13, 0, [0x12, 0x34, 0, 1], &[0; 12]);
+    let reply = ping
+        .send_icmp(std::net::Ipv4Addr::LOCALHOST, &request, |format| {
+            format.get_type() == 14 && format.get_identifier() == Some(0x1234)
+        })
+        .expect("send_icmp error");
+    assert_eq!(reply.get_format().get_sequence(), Some(1));
+    println!("{},{:?}", reply.ip, reply.duration);
+}
+
+#[test]
+fn test_send_icmp_v6() {
+    let ping = PingV6Builder::new(std::time::Duration::from_millis(200)).build();
+    let request = IcmpMessage::new_v6(128, 0, [0x12, 0x34, 0, 1], b"rps");
+    let reply = ping
+        .send_icmp(std::net::Ipv6Addr::LOCALHOST, &request, |format| {
+            format.get_type() == 129 && format.get_body() == b"rps"
+        })
+        .expect("send_icmp error");
+    println!("{},{:?}", reply.ip, reply.duration);
+}
+
+// a missing namespace fails before any socket exists
+#[cfg(target_os = "linux")]
+#[test]
+fn test_netns_missing() {
+    for (name, errno) in [
+        ("rps-no-such-netns", libc::ENOENT),
+        ("../net", libc::EINVAL),
+    ] {
+        let mut builder = rps_ping_lib::PingV4Builder::new(std::time::Duration::from_millis(200));
+        builder.netns = Some(rps_ping_lib::netns::Netns::Named(name.to_string()));
+        match builder.build().ping(std::net::Ipv4Addr::LOCALHOST) {
+            Err(rps_ping_lib::PingError::LinuxError(rps_ping_lib::LinuxError::SetNsFailed(e))) => {
+                assert_eq!(e, errno)
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+}
@@ -3,9 +3,28 @@ use rps_ping_lib::PingV4Builder;
 #[test]
 fn test_ping_v4() {
     let ping = PingV4Builder {
-        timeout: 200,
+        timeout: std::time::Duration::from_millis(200),
+        deadline: None,
         ttl: Some(50),
         bind_addr: None,
+        #[cfg(not(target_os = "windows"))]
+        transport: None,
+        #[cfg(not(target_os = "windows"))]
+        clock: None,
+        #[cfg(not(target_os = "windows"))]
+        seed: None,
+        #[cfg(not(target_os = "windows"))]
+        capture: None,
+        #[cfg(not(target_os = "windows"))]
+        payload: None,
+        #[cfg(not(target_os = "windows"))]
+        rate_limiter: None,
+        #[cfg(target_os = "linux")]
+        netns: None,
+        #[cfg(target_os = "linux")]
+        interface: None,
+        #[cfg(not(target_os = "windows"))]
+        broadcast: false,
         #[cfg(target_os = "windows")]
         window_addition: None,
     }
@@ -22,9 +41,28 @@ fn test_ping_v4() {
 #[test]
 fn test_ping_in_detail() {
     let ping = PingV4Builder {
-        timeout: 200,
+        timeout: std::time::Duration::from_millis(200),
+        deadline: None,
         ttl: Some(5),
         bind_addr: None,
+        #[cfg(not(target_os = "windows"))]
+        transport: None,
+        #[cfg(not(target_os = "windows"))]
+        clock: None,
+        #[cfg(not(target_os = "windows"))]
+        seed: None,
+        #[cfg(not(target_os = "windows"))]
+        capture: None,
+        #[cfg(not(target_os = "windows"))]
+        payload: None,
+        #[cfg(not(target_os = "windows"))]
+        rate_limiter: None,
+        #[cfg(target_os = "linux")]
+        netns: None,
+        #[cfg(target_os = "linux")]
+        interface: None,
+        #[cfg(not(target_os = "windows"))]
+        broadcast: false,
         #[cfg(target_os = "windows")]
         window_addition: None,
     }
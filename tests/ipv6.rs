@@ -3,10 +3,27 @@ use rps_ping_lib::PingV6Builder;
 #[test]
 fn test_ping_v6() {
     let ping = PingV6Builder {
-        timeout: 150,
+        timeout: std::time::Duration::from_millis(150),
+        deadline: None,
         ttl: None,
         bind_addr: None,
         scope_id_option: None,
+        #[cfg(not(target_os = "windows"))]
+        transport: None,
+        #[cfg(not(target_os = "windows"))]
+        clock: None,
+        #[cfg(not(target_os = "windows"))]
+        seed: None,
+        #[cfg(not(target_os = "windows"))]
+        capture: None,
+        #[cfg(not(target_os = "windows"))]
+        payload: None,
+        #[cfg(not(target_os = "windows"))]
+        rate_limiter: None,
+        #[cfg(target_os = "linux")]
+        netns: None,
+        #[cfg(target_os = "linux")]
+        interface: None,
         #[cfg(target_os = "windows")]
         window_addition: None,
     }
@@ -23,10 +40,27 @@ fn test_ping_v6() {
 #[test]
 fn test_ping_v6_in_detail() {
     let ping = PingV6Builder {
-        timeout: 200,
+        timeout: std::time::Duration::from_millis(200),
+        deadline: None,
         ttl: Some(100),
         bind_addr: None,
         scope_id_option: None,
+        #[cfg(not(target_os = "windows"))]
+        transport: None,
+        #[cfg(not(target_os = "windows"))]
+        clock: None,
+        #[cfg(not(target_os = "windows"))]
+        seed: None,
+        #[cfg(not(target_os = "windows"))]
+        capture: None,
+        #[cfg(not(target_os = "windows"))]
+        payload: None,
+        #[cfg(not(target_os = "windows"))]
+        rate_limiter: None,
+        #[cfg(target_os = "linux")]
+        netns: None,
+        #[cfg(target_os = "linux")]
+        interface: None,
         #[cfg(target_os = "windows")]
         window_addition: None,
     }
@@ -0,0 +1,65 @@
+use rps_ping_lib::protocol::{IcmpFormat, Ipv6Extension, Ipv6Header};
+
+// every file of tests/corpus/ipv6 holds a packet captured on a real interface as hex,
+// with the expected header chain in "# chain:" and "# quoted chain:" comments
+fn parse_chain(line: &str) -> Vec<u8> {
+    line.split_whitespace()
+        .map(|u| u.parse().unwrap())
+        .collect()
+}
+
+fn get_chain(header: &Ipv6Header) -> Vec<u8> {
+    header.get_header_chain().map(|(u, _)| u).collect()
+}
+
+#[test]
+fn test_ipv6_corpus() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/ipv6");
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let (mut chain, mut quoted_chain, mut hex) = (None, None, String::new());
+        for line in text.lines() {
+            if let Some(line) = line.strip_prefix("# chain:") {
+                chain = Some(parse_chain(line));
+            } else if let Some(line) = line.strip_prefix("# quoted chain:") {
+                quoted_chain = Some(parse_chain(line));
+            } else if !line.starts_with('#') {
+                hex.push_str(line.trim());
+            }
+        }
+        let packet: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+
+        let header = Ipv6Header::from_slice(&packet).unwrap_or_else(|| panic!("{path:?}"));
+        assert_eq!(Some(get_chain(&header)), chain, "{path:?}");
+        assert_eq!(header.get_version(), 6);
+        let mut fragmented = false;
+        for extension in header.get_extensions() {
+            if let Ipv6Extension::Fragment { identification, .. } = extension {
+                assert_ne!(identification, 0, "{path:?}");
+                fragmented = true;
+            }
+        }
+        // a fragment only carries part of the message the checksum covers
+        if header.get_type() == Some(58) && !fragmented {
+            let icmp = IcmpFormat::from_header_v6(&header).unwrap();
+            let (source, destination) = (
+                header.get_source_address(),
+                header.get_destination_address(),
+            );
+            assert!(icmp.is_checksum_valid_v6(source, destination), "{path:?}");
+            assert!(!icmp.is_checksum_valid_v6(destination, "fe80::1".parse().unwrap()));
+        }
+        if let Some(quoted_chain) = quoted_chain {
+            let icmp = IcmpFormat::from_header_v6(&header).unwrap();
+            let quoted = icmp.get_quoted_v6().unwrap_or_else(|| panic!("{path:?}"));
+            assert_eq!(get_chain(&quoted), quoted_chain, "{path:?}");
+        }
+        count += 1;
+    }
+    assert!(count > 0);
+}
@@ -0,0 +1,132 @@
+use rps_ping_lib::clock::VirtualClock;
+use rps_ping_lib::transport::{MockTransport, Recording, RecordingTransport, ReplayTransport};
+use rps_ping_lib::{LinuxError, PingError, PingV4Builder, PingV6Builder};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// the sessions in tests/recordings, recorded against the TUN test network or hand-written,
+// all with documentation or private addresses and sent with seed 1
+fn replay(name: &str) -> (ReplayTransport, Arc<VirtualClock>) {
+    let recording = Recording::load(format!("tests/recordings/{name}.txt")).unwrap();
+    let clock = Arc::new(VirtualClock::new());
+    (
+        ReplayTransport::new(recording).with_clock(clock.clone()),
+        clock,
+    )
+}
+
+// every recording is about one target
+fn target(name: &str) -> String {
+    let text = std::fs::read_to_string(format!("tests/recordings/{name}.txt")).unwrap();
+    text.lines()
+        .find_map(|line| line.strip_prefix("send "))
+        .and_then(|line| line.split(' ').next())
+        .unwrap()
+        .to_string()
+}
+
+fn ping_v4(name: &str) -> Result<(IpAddr, Duration), PingError> {
+    let (transport, clock) = replay(name);
+    let mut builder = PingV4Builder::new(Duration::from_millis(200));
+    builder.seed = Some(1);
+    builder.transport = Some(Arc::new(transport));
+    builder.clock = Some(clock);
+    builder
+        .build()
+        .ping_in_detail(target(name).parse().unwrap())
+        .map(|result| (result.ip.into(), result.duration))
+}
+
+fn ping_v6(name: &str) -> Result<(IpAddr, Duration), PingError> {
+    let (transport, clock) = replay(name);
+    let mut builder = PingV6Builder::new(Duration::from_millis(200));
+    builder.seed = Some(1);
+    builder.transport = Some(Arc::new(transport));
+    builder.clock = Some(clock);
+    builder
+        .build()
+        .ping_in_detail(target(name).parse().unwrap())
+        .map(|result| (result.ip.into(), result.duration))
+}
+
+#[test]
+fn test_replay_v4() {
+    // the own request on loopback is skipped, durations are the recorded ones
+    assert_eq!(
+        ping_v4("loopback_v4").unwrap(),
+        ("127.0.0.1".parse().unwrap(), Duration::from_nanos(10531))
+    );
+    assert_eq!(
+        ping_v4("time_exceeded_v4").unwrap().0,
+        "10.0.0.1".parse::<IpAddr>().unwrap()
+    );
+    match ping_v4("unreachable_v4") {
+        Err(PingError::LinuxError(LinuxError::IcmpError(e))) => {
+            assert!(e.is_unreachable());
+            assert_eq!(e.icmp_code, 13);
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn test_replay_odd_replies() {
+    assert_eq!(
+        ping_v4("truncated_quote_v4").unwrap(),
+        ("10.0.0.1".parse().unwrap(), Duration::from_micros(1500))
+    );
+    assert_eq!(
+        ping_v4("bad_total_length_v4").unwrap().0,
+        "203.0.113.7".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        ping_v6("extension_headers_v6").unwrap().0,
+        "2001:db8::1".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        ping_v6("time_exceeded_v6").unwrap().0,
+        "2001:db8::1".parse::<IpAddr>().unwrap()
+    );
+}
+
+#[test]
+fn test_replay_mismatch() {
+    let (transport, clock) = replay("loopback_v4");
+    let mut builder = PingV4Builder::new(Duration::from_millis(200));
+    builder.seed = Some(2);
+    builder.transport = Some(Arc::new(transport));
+    builder.clock = Some(clock);
+    assert!(matches!(
+        builder.build().ping("127.0.0.1".parse().unwrap()),
+        Err(PingError::LinuxError(LinuxError::ReplayMismatch))
+    ));
+}
+
+#[test]
+fn test_record_round_trip() {
+    let recorder = RecordingTransport::new(Arc::new(MockTransport::echo(Duration::from_millis(3))));
+    let mut builder = PingV4Builder::new(Duration::from_millis(200));
+    builder.seed = Some(7);
+    builder.transport = Some(Arc::new(recorder.clone()));
+    let ping = builder.build();
+    let target = "192.0.2.10".parse().unwrap();
+    let recorded: Vec<_> = (0..3).map(|_| ping.ping(target).unwrap()).collect();
+
+    let text = recorder.recording().to_string();
+    let recording: Recording = text.parse().unwrap();
+    assert_eq!(recording.to_string(), text);
+
+    let clock = Arc::new(VirtualClock::new());
+    let mut builder = PingV4Builder::new(Duration::from_millis(200));
+    builder.seed = Some(7);
+    builder.transport = Some(Arc::new(
+        ReplayTransport::new(recording).with_clock(clock.clone()),
+    ));
+    builder.clock = Some(clock);
+    let ping = builder.build();
+    let replayed: Vec<_> = (0..3).map(|_| ping.ping(target).unwrap()).collect();
+    assert_eq!(replayed, recorded);
+    // the recording is used up
+    assert!(ping.ping(target).is_err());
+}
@@ -0,0 +1,334 @@
+use rps_ping_lib::clock::VirtualClock;
+use rps_ping_lib::sweep::{HostState, SweepBuilder};
+use rps_ping_lib::traceroute::{HopKind, Traceroute};
+use rps_ping_lib::transport::{Hop, IcmpRateLimit, Latency, Link, SimulatedNetwork};
+use rps_ping_lib::{LinuxError, PingError, PingV4, PingV4Builder, PingV6Builder, SharedError};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// every test runs on the virtual clock, a whole session takes microseconds
+
+const R1: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const R2: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 1);
+const TARGET: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 7);
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn fixed(latency: Duration) -> Link {
+    Link::new(Latency::Fixed(latency))
+}
+
+// us -1ms- R1 -2ms- R2 -3ms- TARGET, `last` is the link to TARGET
+fn three_hops(seed: u64, last: Link) -> SimulatedNetwork {
+    let network = SimulatedNetwork::new(seed);
+    network.add_route(vec![
+        Hop::new(R1.into(), fixed(ms(1))),
+        Hop::new(R2.into(), fixed(ms(2))),
+        Hop::new(TARGET.into(), last),
+    ]);
+    network
+}
+
+fn pinger(network: &SimulatedNetwork, ttl: Option<u8>) -> PingV4 {
+    let mut builder = PingV4Builder::new(Duration::from_secs(1));
+    builder.ttl = ttl;
+    builder.seed = Some(1);
+    builder.transport = Some(Arc::new(network.clone()));
+    builder.clock = Some(network.clock());
+    builder.build()
+}
+
+#[test]
+fn test_sim_hops() {
+    let network = three_hops(0, fixed(ms(3)));
+    for (ttl, ip, rtt) in [(1, R1, 2), (2, R2, 6), (3, TARGET, 12), (64, TARGET, 12)] {
+        let result = pinger(&network, Some(ttl)).ping_in_detail(TARGET).unwrap();
+        assert_eq!((result.ip, result.duration), (ip, ms(rtt)), "ttl {ttl}");
+    }
+    assert!(matches!(
+        pinger(&network, None).ping(Ipv4Addr::new(203, 0, 113, 1)),
+        Err(PingError::SharedError(SharedError::Unreachable))
+    ));
+}
+
+#[test]
+fn test_sim_virtual_timeout() {
+    let mut lossy = fixed(ms(3));
+    lossy.loss = 1.0;
+    let network = three_hops(0, lossy);
+    let real = Instant::now();
+    let clock: Arc<VirtualClock> = network.clock();
+    assert!(matches!(
+        pinger(&network, None).ping(TARGET),
+        Err(PingError::SharedError(SharedError::Timeout))
+    ));
+    assert_eq!(clock.elapsed(), Duration::from_secs(1));
+    assert!(real.elapsed() < Duration::from_millis(500));
+
+    // the session deadline runs on the same clock
+    let mut builder = PingV4Builder::new(Duration::from_secs(1));
+    builder.deadline = Some(Duration::from_millis(2500));
+    builder.transport = Some(Arc::new(network.clone()));
+    builder.clock = Some(clock.clone());
+    let ping = builder.build();
+    for elapsed in [2000, 3000, 3500, 3500] {
+        assert!(matches!(
+            ping.ping(TARGET),
+            Err(PingError::SharedError(SharedError::Timeout))
+        ));
+        assert_eq!(clock.elapsed(), ms(elapsed));
+    }
+}
+
+// same seed, same network: same losses and latencies
+#[test]
+fn test_sim_deterministic() {
+    let run = |seed| {
+        let link = Link {
+            latency: Latency::Normal {
+                mean: ms(20),
+                std_dev: ms(5),
+            },
+            loss: 0.3,
+            ..Default::default()
+        };
+        let network = three_hops(seed, link);
+        let ping = pinger(&network, None);
+        (0..50)
+            .map(|_| ping.ping(TARGET).ok())
+            .collect::<Vec<Option<Duration>>>()
+    };
+    let first = run(42);
+    assert_eq!(first, run(42));
+    assert_ne!(first, run(43));
+    let lost = first.iter().filter(|d| d.is_none()).count();
+    assert!((5..=30).contains(&lost), "{lost}");
+}
+
+#[test]
+fn test_sim_corruption() {
+    let mut link = fixed(ms(3));
+    link.corrupt = 0.5;
+    let network = three_hops(1, link);
+    let ping = pinger(&network, None);
+    let (mut bad, mut lost, mut good) = (0, 0, 0);
+    for _ in 0..40 {
+        match ping.ping(TARGET) {
+            Ok(_) => good += 1,
+            Err(PingError::SharedError(SharedError::BadChecksum)) => bad += 1,
+            Err(PingError::SharedError(SharedError::Timeout)) => lost += 1,
+            Err(e) => panic!("{e:?}"),
+        }
+    }
+    // corrupted requests are dropped by the target, corrupted replies are reported
+    assert!(bad > 0 && lost > 0 && good > 0, "{bad} {lost} {good}");
+}
+
+#[test]
+fn test_sim_duplicate_and_reorder() {
+    let mut link = fixed(ms(3));
+    link.duplicate = 1.0;
+    let network = three_hops(0, link);
+    assert_eq!(pinger(&network, None).ping(TARGET).unwrap(), ms(12));
+
+    let mut link = fixed(ms(3));
+    link.reorder = 1.0;
+    link.reorder_delay = ms(10);
+    let network = three_hops(0, link);
+    assert_eq!(pinger(&network, None).ping(TARGET).unwrap(), ms(32));
+}
+
+#[test]
+fn test_sim_rate_limit() {
+    let network = SimulatedNetwork::new(0);
+    let mut router = Hop::new(R1.into(), fixed(ms(1)));
+    router.icmp_rate_limit = Some(IcmpRateLimit {
+        burst: 1,
+        interval: Duration::from_secs(1),
+    });
+    network.add_route(vec![router, Hop::new(TARGET.into(), fixed(ms(1)))]);
+    let ping = pinger(&network, Some(1));
+    assert_eq!(ping.ping_in_detail(TARGET).unwrap().ip, R1);
+    assert!(ping.ping(TARGET).is_err());
+    // the failed ping waited 1s, so there is a token again
+    assert_eq!(ping.ping_in_detail(TARGET).unwrap().ip, R1);
+    // echo replies aren't limited
+    let ping = pinger(&network, None);
+    assert!((0..5).all(|_| ping.ping(TARGET).is_ok()));
+}
+
+#[test]
+fn test_sim_mtu() {
+    let mut link = fixed(ms(3));
+    link.mtu = 40; // a ping is 42 bytes
+    let network = three_hops(0, link);
+    match pinger(&network, None).ping(TARGET) {
+        Err(PingError::LinuxError(LinuxError::IcmpError(e))) => {
+            assert_eq!((e.icmp_type, e.icmp_code, e.info), (3, 4, 40));
+            assert_eq!(e.offender, Some(IpAddr::V4(R2)));
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn test_sim_v6() {
+    let target = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7);
+    let router = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    let network = SimulatedNetwork::new(0);
+    network.add_route(vec![
+        Hop::new(router.into(), fixed(ms(1))),
+        Hop::new(target.into(), fixed(ms(4))),
+    ]);
+    let mut builder = PingV6Builder::new(Duration::from_secs(1));
+    builder.transport = Some(Arc::new(network.clone()));
+    builder.clock = Some(network.clock());
+    let result = builder.build().ping_in_detail(target).unwrap();
+    assert_eq!((result.ip, result.duration), (target, ms(10)));
+
+    let mut builder = PingV6Builder::new(Duration::from_secs(1));
+    builder.ttl = Some(1);
+    builder.transport = Some(Arc::new(network.clone()));
+    builder.clock = Some(network.clock());
+    assert_eq!(builder.build().ping_in_detail(target).unwrap().ip, router);
+}
+
+#[test]
+fn test_sim_traceroute() {
+    let network = SimulatedNetwork::new(0);
+    let mut silent = Hop::new(R2.into(), fixed(ms(2)));
+    silent.responds = false;
+    network.add_route(vec![
+        Hop::new(R1.into(), fixed(ms(1))),
+        silent,
+        Hop::new(TARGET.into(), fixed(ms(3))),
+    ]);
+    let mut builder = PingV4Builder::new(Duration::from_millis(500));
+    builder.transport = Some(Arc::new(network.clone()));
+    builder.clock = Some(network.clock());
+    let traceroute = Traceroute::v4(builder, TARGET).with_max_hops(5);
+    let round: Vec<_> = traceroute
+        .round()
+        .unwrap()
+        .into_iter()
+        .map(|probe| (probe.ttl, probe.kind))
+        .collect();
+    assert_eq!(
+        round,
+        [
+            (
+                1,
+                HopKind::TimeExceeded {
+                    ip: R1.into(),
+                    duration: ms(2)
+                }
+            ),
+            (2, HopKind::Timeout),
+            (
+                3,
+                HopKind::Reached {
+                    ip: TARGET.into(),
+                    duration: ms(12)
+                }
+            ),
+        ]
+    );
+
+    // a round stops at max_hops even when the target is further away
+    let mut builder = PingV4Builder::new(Duration::from_millis(500));
+    builder.transport = Some(Arc::new(network.clone()));
+    builder.clock = Some(network.clock());
+    let round = Traceroute::v4(builder, TARGET)
+        .with_max_hops(2)
+        .round()
+        .unwrap();
+    assert_eq!(round.len(), 2);
+    assert!(round.iter().all(|probe| !probe.is_last()));
+}
+
+#[test]
+fn test_sim_traceroute_unreachable() {
+    // R2 has no route to the target's network
+    let network = SimulatedNetwork::new(0);
+    let mut r2 = Hop::new(R2.into(), fixed(ms(2)));
+    r2.unreachable = Some(0);
+    network.add_route(vec![
+        Hop::new(R1.into(), fixed(ms(1))),
+        r2,
+        Hop::new(TARGET.into(), fixed(ms(3))),
+    ]);
+    let traceroute = |target| {
+        let mut builder = PingV4Builder::new(Duration::from_millis(500));
+        builder.transport = Some(Arc::new(network.clone()));
+        builder.clock = Some(network.clock());
+        Traceroute::v4(builder, target)
+    };
+    // every round ends at R2, behind its own time exceeded, none of them fails
+    let traceroute_to_target = traceroute(TARGET);
+    for _ in 0..2 {
+        let round = traceroute_to_target.round().unwrap();
+        assert_eq!(round.len(), 3);
+        assert_eq!(round[1].ip(), Some(R2.into()));
+        match &round[2].kind {
+            HopKind::Error(e, duration) => {
+                assert!(e.is_unreachable());
+                assert_eq!((e.icmp_code, e.offender), (0, Some(R2.into())));
+                assert_eq!(*duration, ms(6));
+            }
+            other => panic!("{other:?}"),
+        }
+        assert!(round[2].is_last());
+    }
+
+    // no route at all, the first hop tells so
+    let round = traceroute(Ipv4Addr::new(203, 0, 113, 1)).round().unwrap();
+    assert_eq!(round.len(), 1);
+    assert_eq!(round[0].kind, HopKind::Unreachable);
+    assert!(round[0].is_last() && round[0].ip().is_none());
+}
+
+#[test]
+fn test_sim_sweep() {
+    let network = three_hops(0, fixed(ms(3)));
+    let silent = Ipv4Addr::new(198, 51, 100, 8);
+    let mut hop = Hop::new(silent.into(), fixed(ms(3)));
+    hop.responds = false;
+    network.add_route(vec![Hop::new(R1.into(), fixed(ms(1))), hop]);
+    let mut builder = SweepBuilder::new(Duration::from_millis(500));
+    builder.v4.transport = Some(Arc::new(network.clone()));
+    builder.v4.clock = Some(network.clock());
+    // one at a time, the virtual clock has a single timeline
+    builder.concurrency = 1;
+    builder.rate = Some(10);
+    builder.attempts = 2;
+    let sweep = builder.build();
+
+    let clock = network.clock();
+    let mut reported = 0;
+    let unrouted = Ipv4Addr::new(203, 0, 113, 1);
+    let hosts = sweep.run(&[TARGET.into(), silent.into(), unrouted.into()], |_| {
+        reported += 1
+    });
+    assert_eq!(reported, 3);
+    let states: Vec<_> = hosts
+        .iter()
+        .map(|host| (host.target, host.sent, &host.state))
+        .collect();
+    match &states[..] {
+        [
+            (t1, 1, HostState::Alive { ip, duration }),
+            (t2, 2, HostState::Timeout),
+            (t3, 1, HostState::Unreachable),
+        ] => {
+            assert_eq!(*t1, IpAddr::from(TARGET));
+            assert_eq!((*ip, *duration), (IpAddr::from(TARGET), ms(12)));
+            assert_eq!((*t2, *t3), (IpAddr::from(silent), IpAddr::from(unrouted)));
+        }
+        other => panic!("{other:?}"),
+    }
+    // sends at 0, 100ms (times out at 600ms), 600ms (at 1100ms) and 1100ms
+    assert_eq!(clock.elapsed(), ms(1100));
+}
@@ -0,0 +1,508 @@
+use rps_ping_lib::capture::Capture;
+use rps_ping_lib::clock::VirtualClock;
+use rps_ping_lib::protocol::{IcmpFormat, IcmpMessage};
+use rps_ping_lib::rate::RateLimiterBuilder;
+use rps_ping_lib::sweep::{SweepBuilder, expand};
+use rps_ping_lib::transport::{
+    Channel, ChannelConfig, MockReply, MockTransport, Received, Transport,
+};
+use rps_ping_lib::{LinuxError, PingError, PingV4Builder, PingV6Builder, SharedError};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// no privileges or network needed, every reply comes from the mock
+fn builder_v4(transport: MockTransport) -> PingV4Builder {
+    let mut builder = PingV4Builder::new(Duration::from_millis(100));
+    builder.transport = Some(Arc::new(transport));
+    builder
+}
+
+const TARGET: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 10);
+const ROUTER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+#[test]
+fn test_mock_echo_reply() {
+    let ping = builder_v4(MockTransport::echo(Duration::from_millis(5))).build();
+    let result = ping.ping_in_detail(TARGET).unwrap();
+    assert_eq!(result.ip, TARGET);
+    assert!(result.duration >= Duration::from_millis(5));
+}
+
+#[test]
+fn test_mock_timeout() {
+    let ping = builder_v4(MockTransport::new(|_, _| vec![])).build();
+    let start = Instant::now();
+    assert!(matches!(
+        ping.ping(TARGET),
+        Err(PingError::SharedError(SharedError::Timeout))
+    ));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    // a reply later than the timeout is a timeout too
+    let ping = builder_v4(MockTransport::echo(Duration::from_millis(150))).build();
+    assert!(matches!(
+        ping.ping(TARGET),
+        Err(PingError::SharedError(SharedError::Timeout))
+    ));
+}
+
+#[test]
+fn test_mock_deadline() {
+    let mut builder = builder_v4(MockTransport::echo(Duration::ZERO));
+    builder.deadline = Some(Duration::from_millis(20));
+    let ping = builder.build();
+    assert!(ping.ping(TARGET).is_ok());
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(matches!(
+        ping.ping(TARGET),
+        Err(PingError::SharedError(SharedError::Timeout))
+    ));
+}
+
+#[test]
+fn test_mock_skips_unrelated_packets() {
+    let ping = builder_v4(MockTransport::new(|data, target| {
+        let mut other = data.to_vec();
+        other[10] ^= 0xff;
+        vec![
+            // our own request, as seen on loopback
+            MockReply::icmp(data.to_vec(), target, Duration::ZERO),
+            // the answer to another probe
+            MockReply::echo_reply(&other, target, Duration::ZERO),
+            // too short for an ICMP message
+            MockReply::icmp(vec![0, 0, 0xff], target, Duration::ZERO),
+            MockReply::echo_reply(data, target, Duration::from_millis(1)),
+        ]
+    }))
+    .build();
+    assert_eq!(ping.ping_in_detail(TARGET).unwrap().ip, TARGET);
+}
+
+#[test]
+fn test_mock_time_exceeded() {
+    // queued by IP_RECVERR
+    let ping = builder_v4(MockTransport::new(|data, _| {
+        vec![MockReply::time_exceeded(data, ROUTER, Duration::ZERO)]
+    }))
+    .build();
+    assert_eq!(IpAddr::V4(ping.ping_in_detail(TARGET).unwrap().ip), ROUTER);
+
+    // delivered to the raw socket, quoting the request
+    let ping = builder_v4(MockTransport::new(|data, _| {
+        let mut quote = vec![
+            0x45, 0, 0, 42, 0, 0, 0, 0, 1, 1, 0, 0, 192, 0, 2, 2, 192, 0, 2, 10,
+        ];
+        quote.extend_from_slice(data);
+        let message = IcmpMessage::new_v4(11, 0, [0; 4], &quote);
+        vec![MockReply::icmp(
+            message.get_inner().to_vec(),
+            ROUTER,
+            Duration::ZERO,
+        )]
+    }))
+    .build();
+    assert_eq!(IpAddr::V4(ping.ping_in_detail(TARGET).unwrap().ip), ROUTER);
+}
+
+#[test]
+fn test_mock_unreachable() {
+    let ping = builder_v4(MockTransport::new(|data, _| {
+        vec![MockReply::unreachable(data, ROUTER, 1, Duration::ZERO)]
+    }))
+    .build();
+    match ping.ping(TARGET) {
+        Err(PingError::LinuxError(LinuxError::IcmpError(e))) => {
+            assert!(e.is_unreachable());
+            assert_eq!(e.icmp_code, 1);
+            assert_eq!(e.offender, Some(ROUTER));
+        }
+        other => panic!("{other:?}"),
+    }
+
+    // an error quoting another probe is not ours
+    let ping = builder_v4(MockTransport::new(|data, target| {
+        let mut other = data.to_vec();
+        other[12] ^= 0xff;
+        vec![
+            MockReply::unreachable(&other, ROUTER, 1, Duration::ZERO),
+            MockReply::echo_reply(data, target, Duration::from_millis(1)),
+        ]
+    }))
+    .build();
+    assert!(ping.ping(TARGET).is_ok());
+}
+
+#[test]
+fn test_mock_bad_checksum() {
+    let ping = builder_v4(MockTransport::new(|data, target| {
+        let mut reply = MockReply::echo_reply(data, target, Duration::ZERO);
+        reply.data[2] ^= 0x10;
+        vec![reply]
+    }))
+    .build();
+    assert!(matches!(
+        ping.ping(TARGET),
+        Err(PingError::SharedError(SharedError::BadChecksum))
+    ));
+}
+
+#[test]
+fn test_mock_v6() {
+    let target = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    let mut builder = PingV6Builder::new(Duration::from_millis(100));
+    builder.transport = Some(Arc::new(MockTransport::echo(Duration::ZERO)));
+    assert_eq!(builder.build().ping_in_detail(target).unwrap().ip, target);
+
+    // the pseudo header is checked as well
+    let mut builder = PingV6Builder::new(Duration::from_millis(100));
+    builder.transport = Some(Arc::new(MockTransport::new(move |data, target| {
+        let mut reply = MockReply::echo_reply(data, target, Duration::ZERO);
+        reply.destination = Some(IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2)));
+        vec![reply]
+    })));
+    assert!(matches!(
+        builder.build().ping(target),
+        Err(PingError::SharedError(SharedError::BadChecksum))
+    ));
+}
+
+#[test]
+fn test_mock_send_icmp() {
+    let ping = builder_v4(MockTransport::new(|data, target| {
+        let request = IcmpFormat::from_slice(data).unwrap();
+        let reply = IcmpMessage::new_v4(
+            14,
+            0,
+            request.get_rest_of_header().unwrap().try_into().unwrap(),
+            &[0; 12],
+        );
+        vec![
+            MockReply::unreachable(data, ROUTER, 3, Duration::ZERO),
+            MockReply::icmp(reply.get_inner().to_vec(), target, Duration::ZERO),
+        ]
+    }))
+    .build();
+    let request = IcmpMessage::new_v4(13, 0, [0x12, 0x34, 0, 1], &[0; 12]);
+    let reply = ping
+        .send_icmp(TARGET, &request, |format| format.get_type() == 14)
+        .unwrap();
+    assert_eq!(reply.ip, TARGET);
+    assert_eq!(reply.get_format().get_identifier(), Some(0x1234));
+}
+
+// collects what the capture writes
+#[derive(Clone, Default)]
+struct Shared(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// (block type, body) of every pcapng block
+fn blocks(file: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut blocks = Vec::new();
+    let mut rest = file;
+    while !rest.is_empty() {
+        let block_type = u32::from_le_bytes(rest[0..4].try_into().unwrap());
+        let length = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        assert_eq!(length % 4, 0);
+        assert_eq!(rest[length - 4..length], rest[4..8]);
+        blocks.push((block_type, &rest[8..length - 4]));
+        rest = &rest[length..];
+    }
+    blocks
+}
+
+// the packets of the enhanced packet blocks with their comment
+fn packets<'a>(blocks: &[(u32, &'a [u8])]) -> Vec<(&'a [u8], String)> {
+    blocks[2..]
+        .iter()
+        .map(|(_, body)| {
+            let len = u32::from_le_bytes(body[12..16].try_into().unwrap()) as usize;
+            let packet = &body[20..20 + len];
+            let options = &body[20 + len.next_multiple_of(4)..];
+            assert_eq!(options[0..2], [1, 0]);
+            let comment_len = u16::from_le_bytes([options[2], options[3]]) as usize;
+            let comment = String::from_utf8(options[4..4 + comment_len].to_vec()).unwrap();
+            (packet, comment)
+        })
+        .collect()
+}
+
+#[test]
+fn test_mock_capture() {
+    let file = Shared::default();
+    let capture = Arc::new(Capture::new(file.clone()).unwrap());
+    let mut builder = builder_v4(MockTransport::new(|data, target| {
+        let mut other = data.to_vec();
+        other[10] ^= 0xff;
+        // as a raw socket reads it, TTL 37 and identification 0x1234
+        let mut raw = MockReply::echo_reply(&other, target, Duration::ZERO);
+        let mut header = vec![0x45, 0, 0, 0, 0x12, 0x34, 0, 0, 37, 1, 0, 0];
+        header.extend_from_slice(&TARGET.octets());
+        header.extend_from_slice(&[192, 0, 2, 100]);
+        header[2..4].copy_from_slice(&((20 + raw.data.len()) as u16).to_be_bytes());
+        raw.data.splice(0..0, header);
+        raw.ip_header = true;
+        vec![
+            raw,
+            MockReply::time_exceeded(data, ROUTER, Duration::from_millis(1)),
+        ]
+    }));
+    builder.capture = Some(capture.clone());
+    assert_eq!(
+        IpAddr::V4(builder.build().ping_in_detail(TARGET).unwrap().ip),
+        ROUTER
+    );
+    capture.flush().unwrap();
+
+    let file = file.0.lock().unwrap();
+    let blocks = blocks(&file);
+    let types: Vec<u32> = blocks.iter().map(|(block_type, _)| *block_type).collect();
+    assert_eq!(types, [0x0A0D0D0A, 1, 6, 6, 6]);
+    // LINKTYPE_RAW
+    assert_eq!(blocks[1].1[0..2], [101, 0]);
+
+    let packets = packets(&blocks);
+    assert_eq!(packets[0].1, "probe sent, synthetic IP header");
+    assert_eq!(packets[0].0[16..20], TARGET.octets());
+    assert_eq!(packets[0].0[20], 8);
+    // the header of the raw socket, unchanged
+    assert_eq!(packets[1].1, "rejected: not an answer to the probe");
+    assert_eq!(packets[1].0[4..6], [0x12, 0x34]);
+    assert_eq!(packets[1].0[8], 37);
+    assert_eq!(packets[1].0[12..16], TARGET.octets());
+    assert_eq!(
+        packets[2].1,
+        "accepted: ICMP error quoting the probe, rebuilt from the error queue"
+    );
+    // time exceeded from the router, quoting an IP header and our request
+    assert_eq!(packets[2].0[12..16], [192, 0, 2, 1]);
+    assert_eq!(packets[2].0[20], 11);
+    assert_eq!(packets[2].0[48..], packets[0].0[20..]);
+}
+
+#[test]
+fn test_mock_capture_unparseable() {
+    let file = Shared::default();
+    let capture = Arc::new(Capture::new(file.clone()).unwrap());
+    let mut builder = builder_v4(MockTransport::new(|_, target| {
+        let mut reply = MockReply::icmp(vec![0x45, 0, 0], target, Duration::ZERO);
+        reply.ip_header = true;
+        vec![reply]
+    }));
+    builder.capture = Some(capture.clone());
+    assert!(matches!(
+        builder.build().ping(TARGET),
+        Err(PingError::LinuxError(LinuxError::ResolveRecvFailed))
+    ));
+    capture.flush().unwrap();
+
+    let file = file.0.lock().unwrap();
+    let blocks = blocks(&file);
+    let packets = packets(&blocks);
+    assert_eq!(
+        packets[1],
+        (
+            &[0x45, 0, 0][..],
+            "rejected: no valid IP header, ResolveRecvFailed".to_string()
+        )
+    );
+}
+
+// holds every probe sent until `count` were, or a second passed, so that they all leave at once
+struct Gate {
+    sent: std::sync::Mutex<usize>,
+    all_sent: std::sync::Condvar,
+    count: usize,
+}
+
+impl Gate {
+    fn wait(&self) {
+        let mut sent = self.sent.lock().unwrap();
+        *sent += 1;
+        self.all_sent.notify_all();
+        let _ = self
+            .all_sent
+            .wait_timeout_while(sent, Duration::from_secs(1), |sent| *sent < self.count)
+            .unwrap();
+    }
+}
+
+struct GatedTransport(MockTransport, Arc<Gate>);
+
+struct GatedChannel(Box<dyn Channel>, Arc<Gate>);
+
+impl Transport for GatedTransport {
+    fn open(&self, config: &ChannelConfig) -> Result<Box<dyn Channel>, PingError> {
+        Ok(Box::new(GatedChannel(self.0.open(config)?, self.1.clone())))
+    }
+}
+
+impl Channel for GatedChannel {
+    fn send_to(&mut self, data: &[u8], target: IpAddr) -> Result<Instant, PingError> {
+        let sent_at = self.0.send_to(data, target)?;
+        self.1.wait();
+        Ok(sent_at)
+    }
+
+    fn recv(&mut self, buff: &mut [u8], timeout: Duration) -> Result<Received, PingError> {
+        self.0.recv(buff, timeout)
+    }
+}
+
+#[test]
+fn test_mock_sweep() {
+    let clock = Arc::new(VirtualClock::new());
+    let gate = Arc::new(Gate {
+        sent: Default::default(),
+        all_sent: Default::default(),
+        count: 16,
+    });
+    let transport = || {
+        let mock = MockTransport::echo(Duration::from_millis(50)).with_clock(clock.clone());
+        Arc::new(GatedTransport(mock, gate.clone()))
+    };
+    let mut builder = SweepBuilder::new(Duration::from_secs(1));
+    builder.v4.transport = Some(transport());
+    builder.v4.clock = Some(clock.clone());
+    builder.v6.transport = Some(transport());
+    builder.v6.clock = Some(clock.clone());
+    builder.concurrency = 16;
+    let mut targets = expand("192.0.2.0/29").unwrap();
+    targets.extend(expand("2001:db8::1-a").unwrap());
+    assert_eq!(targets.len(), 16);
+
+    let hosts = builder
+        .build()
+        .run(&targets, |host| assert!(host.is_alive()));
+    // all at once, one after another would take 800ms
+    assert_eq!(clock.elapsed(), Duration::from_millis(50));
+    let order: Vec<IpAddr> = hosts.iter().map(|host| host.target).collect();
+    assert_eq!(order, targets);
+}
+
+#[test]
+fn test_mock_ping_all() {
+    let group = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+    let member = |last| IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, last));
+    let clock = Arc::new(VirtualClock::new());
+    let transport = MockTransport::new(move |data, _| {
+        // damaged on the way, skipped without ending the wait
+        let mut corrupted = MockReply::echo_reply(data, member(4), Duration::from_millis(2));
+        corrupted.data[8] ^= 0xff;
+        // only the checksum is wrong, told as corrupted
+        let mut bad_checksum = MockReply::echo_reply(data, member(6), Duration::from_millis(2));
+        bad_checksum.data[2] ^= 0xff;
+        vec![
+            MockReply::echo_reply(data, member(2), Duration::from_millis(1)),
+            corrupted,
+            bad_checksum,
+            MockReply::echo_reply(data, member(3), Duration::from_millis(3)),
+            // a second reply of the same member is dropped
+            MockReply::echo_reply(data, member(2), Duration::from_millis(4)),
+            MockReply::unreachable(data, member(5), 3, Duration::from_millis(5)),
+        ]
+    })
+    .with_clock(clock.clone());
+    let mut builder = PingV6Builder::new(Duration::from_millis(100));
+    builder.transport = Some(Arc::new(transport));
+    builder.clock = Some(clock.clone());
+    let all = builder.build().ping_all(group, 7).unwrap();
+    assert_eq!(all.corrupted, [Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 6)]);
+    let replies: Vec<_> = all
+        .replies
+        .iter()
+        .map(|reply| (IpAddr::V6(reply.ip), reply.duration, reply.sequence))
+        .collect();
+    assert_eq!(
+        replies,
+        [
+            (member(2), Duration::from_millis(1), 7),
+            (member(3), Duration::from_millis(3), 7)
+        ]
+    );
+    // waits out the whole timeout
+    assert_eq!(clock.elapsed(), Duration::from_millis(100));
+}
+
+#[test]
+fn test_mock_ping_all_v4() {
+    let host = |last| IpAddr::V4(Ipv4Addr::new(192, 0, 2, last));
+    let clock = Arc::new(VirtualClock::new());
+    let transport = MockTransport::new(move |data, _| {
+        vec![
+            MockReply::echo_reply(data, host(9), Duration::from_millis(2)),
+            MockReply::echo_reply(data, host(1), Duration::from_millis(6)),
+        ]
+    })
+    .with_clock(clock.clone());
+    let mut builder = builder_v4(transport);
+    builder.broadcast = true;
+    builder.clock = Some(clock.clone());
+    let all = builder
+        .build()
+        .ping_all(Ipv4Addr::new(192, 0, 2, 255), 3)
+        .unwrap();
+    assert!(all.corrupted.is_empty());
+    let replies: Vec<_> = all
+        .replies
+        .iter()
+        .map(|reply| (IpAddr::V4(reply.ip), reply.duration, reply.sequence))
+        .collect();
+    assert_eq!(
+        replies,
+        [
+            (host(9), Duration::from_millis(2), 3),
+            (host(1), Duration::from_millis(6), 3)
+        ]
+    );
+}
+
+#[test]
+fn test_mock_rate_limiter() {
+    let clock = Arc::new(VirtualClock::new());
+    // 50ms apart overall, 200ms apart to one destination
+    let limiter = RateLimiterBuilder {
+        per_target: Some(5.0),
+        clock: Some(clock.clone()),
+        ..RateLimiterBuilder::new(20.0)
+    }
+    .build();
+    let transport =
+        Arc::new(MockTransport::echo(Duration::from_millis(1)).with_clock(clock.clone()));
+    let mut v4 = PingV4Builder::new(Duration::from_millis(100));
+    v4.transport = Some(transport.clone());
+    v4.clock = Some(clock.clone());
+    v4.rate_limiter = Some(limiter.clone());
+    let mut v6 = PingV6Builder::new(Duration::from_millis(100));
+    v6.transport = Some(transport);
+    v6.clock = Some(clock.clone());
+    v6.rate_limiter = Some(limiter);
+    let (v4, v6) = (v4.build(), v6.build());
+
+    let mut done = Vec::new();
+    for target in [
+        IpAddr::V4(TARGET),
+        IpAddr::V6(Ipv6Addr::LOCALHOST),
+        IpAddr::V4(TARGET),
+    ] {
+        let duration = match target {
+            IpAddr::V4(target) => v4.ping(target),
+            IpAddr::V6(target) => v6.ping(target),
+        };
+        // the wait for the slot isn't part of the round trip
+        assert_eq!(duration.unwrap(), Duration::from_millis(1));
+        done.push(clock.elapsed());
+    }
+    // sent at 0, 50 and 200ms
+    assert_eq!(done, [1, 51, 201].map(Duration::from_millis));
+}
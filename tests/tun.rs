@@ -0,0 +1,138 @@
+#![cfg(all(target_os = "linux", feature = "TUN"))]
+
+use rps_ping_lib::netns::Netns;
+use rps_ping_lib::tun::{HopReply, TunHop};
+use rps_ping_lib::{LinuxError, PingError, PingV4Builder, PingV6Builder, SharedError};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+// the real socket code against a TUN interface
+mod common;
+
+use common::network;
+
+const R1: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const R2: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 1);
+const TARGET: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+
+fn builder_v4(ttl: Option<u8>) -> PingV4Builder {
+    let mut builder = PingV4Builder::new(Duration::from_millis(300));
+    builder.ttl = ttl;
+    builder
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_tun_v4() {
+    let network = network();
+    let mut r1 = TunHop::new(R1.into());
+    r1.delay = Duration::from_millis(20);
+    let mut r2 = TunHop::new(R2.into());
+    r2.reply = HopReply::Silent;
+    network
+        .add_route(vec![r1, r2, TunHop::new(TARGET.into())])
+        .unwrap();
+
+    let result = builder_v4(None).build().ping_in_detail(TARGET).unwrap();
+    assert_eq!(result.ip, TARGET);
+    assert!(result.duration >= Duration::from_millis(20));
+
+    let hop = |ttl| {
+        builder_v4(Some(ttl))
+            .build()
+            .ping_in_detail(TARGET)
+            .map(|r| r.ip)
+    };
+    assert_eq!(hop(1).unwrap(), R1);
+    assert!(matches!(
+        hop(2),
+        Err(PingError::SharedError(SharedError::Timeout))
+    ));
+    assert_eq!(hop(3).unwrap(), TARGET);
+    // a router can be pinged as well
+    assert_eq!(builder_v4(None).build().ping_in_detail(R1).unwrap().ip, R1);
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_tun_unreachable() {
+    let network = network();
+    let mut r1 = TunHop::new(R1.into());
+    r1.reply = HopReply::Unreachable(1);
+    network
+        .add_route(vec![r1, TunHop::new(TARGET.into())])
+        .unwrap();
+    match builder_v4(None).build().ping(TARGET) {
+        Err(PingError::LinuxError(LinuxError::IcmpError(e))) => {
+            assert!(e.is_unreachable());
+            assert_eq!(e.icmp_code, 1);
+            assert_eq!(e.offender, Some(IpAddr::V4(R1)));
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_tun_net_unreachable() {
+    let network = network();
+    // net unreachable makes the recv fail with ENETUNREACH, the error queue still tells who sent it
+    let mut r1 = TunHop::new(R1.into());
+    r1.reply = HopReply::Unreachable(0);
+    network
+        .add_route(vec![r1, TunHop::new(TARGET.into())])
+        .unwrap();
+    match builder_v4(None).build().ping(TARGET) {
+        Err(PingError::LinuxError(LinuxError::IcmpError(e))) => {
+            assert!(e.is_unreachable());
+            assert_eq!(e.icmp_code, 0);
+            assert_eq!(e.offender, Some(IpAddr::V4(R1)));
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_tun_v6() {
+    let network = network();
+    let router = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    let target = Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 7);
+    network
+        .add_route(vec![TunHop::new(router.into()), TunHop::new(target.into())])
+        .unwrap();
+
+    let hop = |ttl| {
+        let mut builder = PingV6Builder::new(Duration::from_millis(300));
+        builder.ttl = ttl;
+        builder.build().ping_in_detail(target).map(|r| r.ip)
+    };
+    assert_eq!(hop(None).unwrap(), target);
+    assert_eq!(hop(Some(1)).unwrap(), router);
+}
+
+#[test]
+#[ignore = "needs CAP_NET_ADMIN"]
+fn test_tun_netns_fd() {
+    use std::os::fd::AsRawFd;
+    use std::sync::mpsc;
+
+    // the TUN network lives on its own thread, this one stays in the original namespace
+    let (netns_sender, netns_receiver) = mpsc::channel();
+    let (done_sender, done_receiver) = mpsc::channel::<()>();
+    let owner = std::thread::spawn(move || {
+        let network = network();
+        network
+            .add_route(vec![TunHop::new(R1.into()), TunHop::new(TARGET.into())])
+            .unwrap();
+        let netns = std::fs::File::open("/proc/thread-self/ns/net").unwrap();
+        netns_sender.send(netns.as_raw_fd()).unwrap();
+        let _ = done_receiver.recv();
+    });
+    let fd = netns_receiver.recv().unwrap();
+    let mut builder = builder_v4(None);
+    builder.netns = Some(Netns::Fd(fd));
+    assert_eq!(builder.build().ping_in_detail(TARGET).unwrap().ip, TARGET);
+    drop(done_sender);
+    owner.join().unwrap();
+}